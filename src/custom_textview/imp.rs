@@ -1,18 +1,87 @@
 use adw::prelude::*;
 use adw::subclass::prelude::*;
+use chrono::Local;
 use gio::Settings;
+use git2::Repository;
 use gtk::glib;
 use gtk::{CompositeTemplate, TemplateChild};
 use std::cell::RefCell;
+use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 use std::time::Duration;
 use webkit2gtk::WebView;
-use webkit2gtk::prelude::WebViewExt;
+use webkit2gtk::prelude::{PolicyDecisionExt, WebViewExt};
+
+use crate::diagrams;
+use crate::diff::{self, DiffLine};
+use crate::footnotes::{self, Footnotes};
+use crate::frontmatter::{self, FrontMatter};
+use crate::git::{self, LineChange};
+use crate::goals::{DocumentGoals, WritingHistory, WritingSession};
+use crate::images;
+use crate::lists::{self, ListItem};
+use crate::markdown;
+use crate::notes::{self, Backlink, WikiLink};
+use crate::preferences;
+use crate::preview::{self, PreviewTheme};
+use crate::print;
+use crate::recent;
+use crate::search::{SearchIndex, SearchResult};
+use crate::snippets::{self, Snippet, Variables};
+use crate::stats::{self, DocumentStats};
+use crate::table::{self, Alignment, Table};
+use crate::tags::{self, TagIndex};
+use crate::templates;
+use crate::toc;
+
+const FOCUS_DIM_TAG: &str = "focus-dim";
+const DEFAULT_TITLE: &str = "Text Editor";
+const WIKI_LINK_TAG: &str = "wiki-link";
+const FOOTNOTE_ORPHAN_TAG: &str = "footnote-orphan";
+// Wraps lines with image previews so they sit below the text
+const IMAGE_LINE_TAG: &str = "image-line";
+// Tallest an image preview is shown
+const IMAGE_PREVIEW_HEIGHT: f64 = 240.0;
+
+// Characters that get a closing partner typed automatically
+const AUTO_PAIRS: &[(char, char)] = &[
+    ('(', ')'),
+    ('[', ']'),
+    ('{', '}'),
+    ('`', '`'),
+    ('*', '*'),
+    ('_', '_'),
+    ('"', '"'),
+    ('\'', '\''),
+];
+
+/// A table found around a buffer line
+struct TableContext {
+    first_line: i32,
+    last_line: i32,
+    table: Table,
+}
 
 #[derive(CompositeTemplate)]
 #[template(resource = "/org/md-wr/com/text-editor.ui")]
 pub struct CustomTextView {
+    #[template_child]
+    pub header_bar: TemplateChild<gtk::HeaderBar>,
+
+    #[template_child]
+    pub status_bar: TemplateChild<gtk::ActionBar>,
+
+    #[template_child]
+    pub file_changed_banner: TemplateChild<adw::Banner>,
+
+    #[template_child]
+    pub git_button: TemplateChild<gtk::MenuButton>,
+
+    #[template_child]
+    pub image_preview_button: TemplateChild<gtk::ToggleButton>,
+
     #[template_child]
     pub scrolled_window: TemplateChild<gtk::ScrolledWindow>,
 
@@ -25,9 +94,40 @@ pub struct CustomTextView {
     #[template_child]
     pub word_count_label: TemplateChild<gtk::Label>,
 
+    #[template_child]
+    pub footnote_status: TemplateChild<gtk::Label>,
+
     #[template_child]
     pub char_count_label: TemplateChild<gtk::Label>,
 
+    #[template_child]
+    pub stats_button: TemplateChild<gtk::MenuButton>,
+
+    #[template_child]
+    pub stats_popover: TemplateChild<gtk::Popover>,
+
+    #[template_child]
+    pub stats_grid: TemplateChild<gtk::Grid>,
+
+    #[template_child]
+    pub count_code_check: TemplateChild<gtk::CheckButton>,
+
+    // Writing goals
+    #[template_child]
+    pub goal_progress: TemplateChild<gtk::ProgressBar>,
+
+    #[template_child]
+    pub document_goal_spin: TemplateChild<gtk::SpinButton>,
+
+    #[template_child]
+    pub daily_goal_spin: TemplateChild<gtk::SpinButton>,
+
+    #[template_child]
+    pub session_label: TemplateChild<gtk::Label>,
+
+    #[template_child]
+    pub history_chart: TemplateChild<gtk::DrawingArea>,
+
     #[template_child]
     pub save_button: TemplateChild<gtk::Button>,
 
@@ -50,6 +150,46 @@ pub struct CustomTextView {
     #[template_child]
     pub web_view: TemplateChild<WebView>,
 
+    #[template_child]
+    pub preview_theme_dropdown: TemplateChild<gtk::DropDown>,
+
+    // Properties form for the front matter
+    #[template_child]
+    pub metadata_title_entry: TemplateChild<gtk::Entry>,
+
+    #[template_child]
+    pub metadata_date_entry: TemplateChild<gtk::Entry>,
+
+    #[template_child]
+    pub metadata_tags_entry: TemplateChild<gtk::Entry>,
+
+    #[template_child]
+    pub metadata_other_label: TemplateChild<gtk::Label>,
+
+    #[template_child]
+    pub sidebar_stack: TemplateChild<gtk::Stack>,
+
+    #[template_child]
+    pub backlinks_list: TemplateChild<gtk::ListBox>,
+
+    #[template_child]
+    pub tags_list: TemplateChild<gtk::ListBox>,
+
+    #[template_child]
+    pub tag_notes_heading: TemplateChild<gtk::Label>,
+
+    #[template_child]
+    pub tag_notes_list: TemplateChild<gtk::ListBox>,
+
+    #[template_child]
+    pub notes_search_entry: TemplateChild<gtk::SearchEntry>,
+
+    #[template_child]
+    pub search_status_label: TemplateChild<gtk::Label>,
+
+    #[template_child]
+    pub search_results_list: TemplateChild<gtk::ListBox>,
+
     settings: Option<Settings>,
     config_dir: PathBuf,
     settings_key: RefCell<Option<String>>,
@@ -57,6 +197,87 @@ pub struct CustomTextView {
     is_loading: RefCell<bool>,
     nav_visible: RefCell<bool>,
     paned_position: RefCell<i32>,
+    count_code_blocks: RefCell<bool>,
+    writing_history: RefCell<WritingHistory>,
+    document_goals: RefCell<DocumentGoals>,
+    session: RefCell<WritingSession>,
+    last_word_count: RefCell<Option<usize>>,
+    daily_goal: RefCell<usize>,
+    focus_mode: RefCell<bool>,
+    // Whether focus mode made the window fullscreen, so leaving it keeps a
+    // fullscreen the user chose
+    focus_fullscreened: RefCell<bool>,
+    saved_margins: RefCell<(i32, i32)>,
+    preview_theme: RefCell<String>,
+    preview_themes: RefCell<Vec<PreviewTheme>>,
+    preview_update_pending: RefCell<bool>,
+    populating_themes: RefCell<bool>,
+    loading_goals: RefCell<bool>,
+    history_save_pending: RefCell<bool>,
+    theme_monitor: RefCell<Option<gio::FileMonitor>>,
+    editing_table: RefCell<bool>,
+    // Whether the buffer was edited since the cursor moved to its line, so
+    // tables are only aligned after typing in them
+    table_edited: RefCell<bool>,
+    last_cursor_line: RefCell<i32>,
+    auto_pair: RefCell<bool>,
+    updating_metadata: RefCell<bool>,
+    // File backing the buffer; None for the scratch document kept in settings
+    current_file: RefCell<Option<PathBuf>>,
+    file_monitor: RefCell<Option<gio::FileMonitor>>,
+    // Contents of the open file as last read or written, to tell our own
+    // saves from changes made by other programs
+    disk_text: RefCell<Option<String>>,
+    // Entity tag (modification time) of the open file at that point, so
+    // saving can tell whether it changed without reading it
+    disk_etag: RefCell<Option<String>>,
+    // Set while a change on disk conflicts with unsaved edits; saving waits
+    // until it is resolved
+    file_conflict: RefCell<bool>,
+    save_pending: RefCell<bool>,
+    // Repository of the open file, with the file as committed in HEAD (empty
+    // for files not committed yet)
+    git_repo: RefCell<Option<Repository>>,
+    git_head_text: RefCell<Option<String>>,
+    git_changes: RefCell<Vec<(usize, LineChange)>>,
+    git_changes_pending: RefCell<bool>,
+    backlinks: RefCell<Vec<Backlink>>,
+    tag_index: RefCell<TagIndex>,
+    // Lowercased tags the note list is filtered by
+    selected_tags: RefCell<Vec<String>>,
+    tag_notes: RefCell<Vec<PathBuf>>,
+    // Loaded on the first search
+    search_index: RefCell<Option<SearchIndex>>,
+    search_results: RefCell<Vec<SearchResult>>,
+    search_refresh_pending: RefCell<bool>,
+    notes_monitor: RefCell<Option<(PathBuf, gio::FileMonitor)>>,
+    // Open auto-inserted pairs: marks after the opener and before the closer
+    auto_pair_marks: RefCell<Vec<(gtk::TextMark, gtk::TextMark)>>,
+    // Selection deleted to make way for typed text, with where it started
+    replaced_selection: RefCell<Option<(i32, String)>>,
+    inserting_pair: RefCell<bool>,
+    // Only text the user types is paired: not undo and redo replays, pastes
+    // or text inserted outside a user action
+    in_user_action: RefCell<bool>,
+    replaying_history: RefCell<bool>,
+    pasting: RefCell<bool>,
+    // Parsed snippets file, dropped by its monitor when the file changes
+    snippets: RefCell<Option<Rc<Vec<Snippet>>>>,
+    snippets_monitor: RefCell<Option<gio::FileMonitor>>,
+    // Tab stops of the snippet being filled in, as start and end marks with
+    // the visited range before its mirrors, and the index of the current stop
+    snippet_stops: RefCell<Vec<Vec<(gtk::TextMark, gtk::TextMark)>>>,
+    snippet_stop: RefCell<usize>,
+    updating_mirrors: RefCell<bool>,
+    // Image previews: each anchor at the end of an image's line with the file
+    // it shows. Anchors take a character in the buffer but not in its text.
+    image_previews: RefCell<Vec<(gtk::TextChildAnchor, PathBuf)>>,
+    image_previews_enabled: RefCell<bool>,
+    image_preview_pending: RefCell<bool>,
+    updating_image_previews: RefCell<bool>,
+    // The print operation being sent to the printer with the page it prints,
+    // kept alive until printing finishes
+    print_job: RefCell<Option<(webkit2gtk::PrintOperation, WebView)>>,
 }
 
 impl Default for CustomTextView {
@@ -66,12 +287,43 @@ impl Default for CustomTextView {
         // Try to create settings, fallback to None if it fails
         let settings = Settings::new("org.md-wr.com");
 
+        let writing_history = WritingHistory::load(&config_dir);
+        let document_goals = DocumentGoals::load(&config_dir);
+
         let instance = Self {
+            header_bar: TemplateChild::default(),
+            status_bar: TemplateChild::default(),
+            file_changed_banner: TemplateChild::default(),
+            git_button: TemplateChild::default(),
+            image_preview_button: TemplateChild::default(),
             scrolled_window: TemplateChild::default(),
             text_view: TemplateChild::default(),
             web_view: TemplateChild::default(),
+            preview_theme_dropdown: TemplateChild::default(),
+            metadata_title_entry: TemplateChild::default(),
+            metadata_date_entry: TemplateChild::default(),
+            metadata_tags_entry: TemplateChild::default(),
+            metadata_other_label: TemplateChild::default(),
+            sidebar_stack: TemplateChild::default(),
+            backlinks_list: TemplateChild::default(),
+            tags_list: TemplateChild::default(),
+            tag_notes_heading: TemplateChild::default(),
+            tag_notes_list: TemplateChild::default(),
+            notes_search_entry: TemplateChild::default(),
+            search_status_label: TemplateChild::default(),
+            search_results_list: TemplateChild::default(),
             word_count_label: TemplateChild::default(),
+            footnote_status: TemplateChild::default(),
             char_count_label: TemplateChild::default(),
+            stats_button: TemplateChild::default(),
+            stats_popover: TemplateChild::default(),
+            stats_grid: TemplateChild::default(),
+            count_code_check: TemplateChild::default(),
+            goal_progress: TemplateChild::default(),
+            document_goal_spin: TemplateChild::default(),
+            daily_goal_spin: TemplateChild::default(),
+            session_label: TemplateChild::default(),
+            history_chart: TemplateChild::default(),
             save_button: TemplateChild::default(),
             clear_button: TemplateChild::default(),
             placeholder_label: TemplateChild::default(),
@@ -86,6 +338,61 @@ impl Default for CustomTextView {
             is_loading: RefCell::new(false),
             nav_visible: RefCell::new(false),
             paned_position: RefCell::new(250),
+            count_code_blocks: RefCell::new(false),
+            writing_history: RefCell::new(writing_history),
+            document_goals: RefCell::new(document_goals),
+            session: RefCell::new(WritingSession::new()),
+            last_word_count: RefCell::new(None),
+            daily_goal: RefCell::new(0),
+            focus_mode: RefCell::new(false),
+            focus_fullscreened: RefCell::new(false),
+            saved_margins: RefCell::new((80, 20)),
+            preview_theme: RefCell::new(preview::DEFAULT_THEME.to_string()),
+            preview_themes: RefCell::new(Vec::new()),
+            preview_update_pending: RefCell::new(false),
+            populating_themes: RefCell::new(false),
+            loading_goals: RefCell::new(false),
+            history_save_pending: RefCell::new(false),
+            theme_monitor: RefCell::new(None),
+            editing_table: RefCell::new(false),
+            table_edited: RefCell::new(false),
+            last_cursor_line: RefCell::new(0),
+            auto_pair: RefCell::new(true),
+            updating_metadata: RefCell::new(false),
+            current_file: RefCell::new(None),
+            file_monitor: RefCell::new(None),
+            disk_text: RefCell::new(None),
+            disk_etag: RefCell::new(None),
+            file_conflict: RefCell::new(false),
+            save_pending: RefCell::new(false),
+            git_repo: RefCell::new(None),
+            git_head_text: RefCell::new(None),
+            git_changes: RefCell::new(Vec::new()),
+            git_changes_pending: RefCell::new(false),
+            backlinks: RefCell::new(Vec::new()),
+            tag_index: RefCell::new(TagIndex::default()),
+            selected_tags: RefCell::new(Vec::new()),
+            tag_notes: RefCell::new(Vec::new()),
+            search_index: RefCell::new(None),
+            search_results: RefCell::new(Vec::new()),
+            search_refresh_pending: RefCell::new(false),
+            notes_monitor: RefCell::new(None),
+            auto_pair_marks: RefCell::new(Vec::new()),
+            replaced_selection: RefCell::new(None),
+            inserting_pair: RefCell::new(false),
+            in_user_action: RefCell::new(false),
+            replaying_history: RefCell::new(false),
+            pasting: RefCell::new(false),
+            snippets: RefCell::new(None),
+            snippets_monitor: RefCell::new(None),
+            snippet_stops: RefCell::new(Vec::new()),
+            snippet_stop: RefCell::new(0),
+            updating_mirrors: RefCell::new(false),
+            image_previews: RefCell::new(Vec::new()),
+            image_previews_enabled: RefCell::new(false),
+            image_preview_pending: RefCell::new(false),
+            updating_image_previews: RefCell::new(false),
+            print_job: RefCell::new(None),
         };
         instance.ensure_config_dir(); // Ensure config dir early
         instance
@@ -100,6 +407,161 @@ impl ObjectSubclass for CustomTextView {
 
     fn class_init(klass: &mut Self::Class) {
         klass.bind_template();
+
+        klass.install_action("editor.toggle-focus-mode", None, |widget, _, _| {
+            widget.toggle_focus_mode();
+        });
+        klass.install_action("editor.exit-focus-mode", None, |widget, _, _| {
+            widget.set_focus_mode(false);
+        });
+        klass.add_binding_action(
+            gtk::gdk::Key::F11,
+            gtk::gdk::ModifierType::empty(),
+            "editor.toggle-focus-mode",
+        );
+        klass.add_binding_action(
+            gtk::gdk::Key::Escape,
+            gtk::gdk::ModifierType::empty(),
+            "editor.exit-focus-mode",
+        );
+
+        klass.install_action("editor.search-notes", None, |widget, _, _| {
+            widget.imp().show_note_search();
+        });
+        klass.add_binding_action(
+            gtk::gdk::Key::F,
+            gtk::gdk::ModifierType::CONTROL_MASK | gtk::gdk::ModifierType::SHIFT_MASK,
+            "editor.search-notes",
+        );
+
+        klass.install_action("editor.show-preferences", None, |widget, _, _| {
+            widget.imp().show_preferences();
+        });
+
+        klass.install_action("editor.insert-toc", None, |widget, _, _| {
+            widget.imp().insert_table_of_contents();
+        });
+
+        klass.install_action("editor.insert-footnote", None, |widget, _, _| {
+            widget.imp().insert_footnote();
+        });
+        klass.add_binding_action(
+            gtk::gdk::Key::F,
+            gtk::gdk::ModifierType::CONTROL_MASK | gtk::gdk::ModifierType::ALT_MASK,
+            "editor.insert-footnote",
+        );
+        klass.install_action("editor.footnote-jump", None, |widget, _, _| {
+            widget.imp().jump_to_footnote();
+        });
+        klass.install_action("editor.renumber-footnotes", None, |widget, _, _| {
+            widget.imp().renumber_footnotes();
+        });
+
+        klass.install_action("editor.insert-snippet", None, |widget, _, _| {
+            widget.imp().show_insert_snippet_dialog();
+        });
+        klass.add_binding_action(
+            gtk::gdk::Key::J,
+            gtk::gdk::ModifierType::CONTROL_MASK | gtk::gdk::ModifierType::SHIFT_MASK,
+            "editor.insert-snippet",
+        );
+
+        klass.install_action("editor.print", None, |widget, _, _| {
+            widget.imp().show_print_dialog();
+        });
+        klass.add_binding_action(
+            gtk::gdk::Key::P,
+            gtk::gdk::ModifierType::CONTROL_MASK,
+            "editor.print",
+        );
+
+        // Git
+        klass.install_action("editor.git-diff", None, |widget, _, _| {
+            widget.imp().show_git_diff();
+        });
+        klass.install_action("editor.git-stage", None, |widget, _, _| {
+            widget.imp().stage_current_file();
+        });
+        klass.install_action("editor.git-commit", None, |widget, _, _| {
+            widget.imp().show_commit_dialog();
+        });
+
+        // Table editing
+        klass.install_action("editor.insert-table", None, |widget, _, _| {
+            widget.imp().show_insert_table_dialog();
+        });
+        klass.install_action("editor.table-insert-row-above", None, |widget, _, _| {
+            widget.imp().edit_table(|table, row, column| {
+                table.insert_row(row);
+                (row.max(1), column)
+            });
+        });
+        klass.install_action("editor.table-insert-row-below", None, |widget, _, _| {
+            widget.imp().edit_table(|table, row, column| {
+                table.insert_row(row + 1);
+                (row + 1, column)
+            });
+        });
+        klass.install_action("editor.table-delete-row", None, |widget, _, _| {
+            widget.imp().edit_table(|table, row, column| {
+                table.delete_row(row);
+                (row.min(table.rows.len() - 1), column)
+            });
+        });
+        klass.install_action("editor.table-move-row-up", None, |widget, _, _| {
+            widget
+                .imp()
+                .edit_table(|table, row, column| (table.move_row(row, false), column));
+        });
+        klass.install_action("editor.table-move-row-down", None, |widget, _, _| {
+            widget
+                .imp()
+                .edit_table(|table, row, column| (table.move_row(row, true), column));
+        });
+        klass.install_action("editor.table-insert-column-left", None, |widget, _, _| {
+            widget.imp().edit_table(|table, row, column| {
+                table.insert_column(column);
+                (row, column)
+            });
+        });
+        klass.install_action("editor.table-insert-column-right", None, |widget, _, _| {
+            widget.imp().edit_table(|table, row, column| {
+                table.insert_column(column + 1);
+                (row, column + 1)
+            });
+        });
+        klass.install_action("editor.table-delete-column", None, |widget, _, _| {
+            widget.imp().edit_table(|table, row, column| {
+                table.delete_column(column);
+                (row, column.min(table.column_count() - 1))
+            });
+        });
+        klass.install_action("editor.table-move-column-left", None, |widget, _, _| {
+            widget
+                .imp()
+                .edit_table(|table, row, column| (row, table.move_column(column, false)));
+        });
+        klass.install_action("editor.table-move-column-right", None, |widget, _, _| {
+            widget
+                .imp()
+                .edit_table(|table, row, column| (row, table.move_column(column, true)));
+        });
+        klass.install_action(
+            "editor.table-align",
+            Some(glib::VariantTy::STRING),
+            |widget, _, parameter| {
+                let alignment = match parameter.and_then(|p| p.str()) {
+                    Some("left") => Alignment::Left,
+                    Some("center") => Alignment::Center,
+                    Some("right") => Alignment::Right,
+                    _ => Alignment::None,
+                };
+                widget.imp().edit_table(|table, row, column| {
+                    table.set_alignment(column, alignment);
+                    (row, column)
+                });
+            },
+        );
     }
 
     fn instance_init(obj: &glib::subclass::InitializingObject<Self>) {
@@ -118,10 +580,10 @@ impl CustomTextView {
         if let Some(ref settings) = self.settings {
             // Try to get the value, fall back to default if key doesn't exist or operation fails
             match key {
-                "navigation-panel-visible" => {
-                    settings.boolean("navigation-panel-visible").to_string()
+                "navigation-panel-visible" | "count-code-blocks" | "focus-mode" | "auto-pair" => {
+                    settings.boolean(key).to_string()
                 }
-                "paned-position" => settings.int("paned-position").to_string(),
+                "paned-position" | "daily-word-goal" => settings.int(key).to_string(),
                 _ => settings.string(key).to_string(),
             }
         } else {
@@ -134,13 +596,13 @@ impl CustomTextView {
         if let Some(ref settings) = self.settings {
             // Try to set the value directly, fall back to file if it fails
             let result = match key {
-                "navigation-panel-visible" => {
+                "navigation-panel-visible" | "count-code-blocks" | "focus-mode" => {
                     let bool_val = value.parse::<bool>()?;
                     settings
                         .set_boolean(key, bool_val)
                         .map_err(|e| Box::new(e) as Box<dyn std::error::Error>)
                 }
-                "paned-position" => {
+                "paned-position" | "daily-word-goal" => {
                     let int_val = value.parse::<i32>()?;
                     settings
                         .set_int(key, int_val)
@@ -170,9 +632,8 @@ impl CustomTextView {
 
     fn on_save_clicked(&self) {
         println!("Save button clicked!");
-        let obj = self.obj();
-        obj.save_to_settings();
-        println!("Text saved to settings!");
+        self.save_document();
+        println!("Document saved!");
     }
 
     fn on_clear_clicked(&self) {
@@ -180,8 +641,8 @@ impl CustomTextView {
         let obj = self.obj();
         obj.clear_text();
         self.update_counts();
-        if *self.auto_save.borrow() && self.settings_key.borrow().is_some() {
-            self.save_to_settings();
+        if *self.auto_save.borrow() {
+            self.save_document();
         }
         println!("Text cleared!");
     }
@@ -274,6 +735,72 @@ impl CustomTextView {
         );
     }
 
+    // Session
+
+    /// Remember the open document with its cursor, selection and scroll
+    /// position, and the sidebar page so the preview comes back with it
+    pub fn save_session(&self) {
+        let Some(ref settings) = self.settings else {
+            return;
+        };
+        let buffer = self.text_view.buffer();
+        let cursor = self.text_offset(&buffer.iter_at_mark(&buffer.get_insert()));
+        let bound = self.text_offset(&buffer.iter_at_mark(&buffer.selection_bound()));
+        let path = self
+            .current_file
+            .borrow()
+            .as_ref()
+            .map(|path| path.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let scroll = self.scrolled_window.vadjustment().value();
+        let page = self.sidebar_stack.visible_child_name().unwrap_or_default();
+        let result = settings
+            .set("session-document", (path, cursor, bound, scroll))
+            .and_then(|_| settings.set_string("session-sidebar-page", &page));
+        if let Err(e) = result {
+            eprintln!("Failed to save session: {}", e);
+        }
+    }
+
+    /// Reopen the document from the last session where it was left. Returns
+    /// false if its file no longer exists.
+    pub fn restore_session(&self) -> bool {
+        let Some(ref settings) = self.settings else {
+            return false;
+        };
+        // The search page starts out empty, so show the preview instead
+        let page = settings.string("session-sidebar-page");
+        if page != "search" && self.sidebar_stack.child_by_name(&page).is_some() {
+            self.sidebar_stack.set_visible_child_name(&page);
+        }
+
+        let (path, cursor, bound, scroll): (String, i32, i32, f64) =
+            settings.get("session-document");
+        if !path.is_empty() {
+            let path = PathBuf::from(path);
+            if !path.is_file() {
+                println!("Not restoring {:?}, it no longer exists", path);
+                return false;
+            }
+            self.open_file(&path);
+        }
+
+        let buffer = self.text_view.buffer();
+        buffer.select_range(
+            &self.iter_at_text_offset(cursor),
+            &self.iter_at_text_offset(bound),
+        );
+
+        // Lines are measured on idle, so scroll once they are
+        let imp_weak = self.obj().downgrade();
+        glib::idle_add_local_once(move || {
+            if let Some(widget) = imp_weak.upgrade() {
+                widget.imp().scrolled_window.vadjustment().set_value(scroll);
+            }
+        });
+        true
+    }
+
     pub fn clear_text(&self) {
         println!("Clearing text buffer");
         let buffer = self.text_view.buffer();
@@ -293,118 +820,3866 @@ impl CustomTextView {
         println!("Updating counts: text='{}'", text);
 
         let char_count = text.chars().count();
-        let word_count = text.split_whitespace().count();
+        let word_count = stats::markdown_word_count(&text, *self.count_code_blocks.borrow());
 
         self.char_count_label
             .set_text(&format!("Characters: {}", char_count));
         self.word_count_label
             .set_text(&format!("Words: {}", word_count));
 
+        self.track_writing_progress(word_count);
+        self.update_goal_progress(word_count);
+
         self.update_placeholder_visibility(&text);
     }
 
-    fn update_placeholder_visibility(&self, text: &str) {
-        if text.trim().is_empty() {
-            self.placeholder_label.set_visible(true);
-        } else {
-            self.placeholder_label.set_visible(false);
-        }
+    /// Compute statistics for the whole document
+    pub fn document_stats(&self) -> DocumentStats {
+        let buffer = self.text_view.buffer();
+        let start = buffer.start_iter();
+        let end = buffer.end_iter();
+        DocumentStats::from_markdown(
+            &buffer.text(&start, &end, false),
+            *self.count_code_blocks.borrow(),
+        )
     }
 
-    pub fn set_settings_key(&self, key: &str) {
-        *self.settings_key.borrow_mut() = Some(key.to_string());
-        self.load_from_settings();
+    /// Compute statistics for the current selection, if any
+    pub fn selection_stats(&self) -> Option<DocumentStats> {
+        let buffer = self.text_view.buffer();
+        let (start, end) = buffer.selection_bounds()?;
+        Some(DocumentStats::from_markdown(
+            &buffer.text(&start, &end, false),
+            *self.count_code_blocks.borrow(),
+        ))
     }
 
-    pub fn save_to_settings(&self) {
-        if *self.is_loading.borrow() {
+    /// Get the word count of the rendered document text
+    pub fn word_count(&self) -> usize {
+        let buffer = self.text_view.buffer();
+        let start = buffer.start_iter();
+        let end = buffer.end_iter();
+        stats::markdown_word_count(
+            &buffer.text(&start, &end, false),
+            *self.count_code_blocks.borrow(),
+        )
+    }
+
+    /// Include or exclude code blocks from word counts and statistics
+    pub fn set_count_code_blocks(&self, count_code_blocks: bool) {
+        if *self.count_code_blocks.borrow() == count_code_blocks {
             return;
         }
-        if let Some(key) = self.settings_key.borrow().as_ref() {
-            let buffer = self.text_view.buffer();
-            let start = buffer.start_iter();
-            let end = buffer.end_iter();
-            let text = buffer.text(&start, &end, false);
-            if let Err(e) = self.set_config_value(key, &text) {
-                eprintln!("Failed to save text to settings: {}", e);
-            } else {
-                println!("Text saved to key: {}", key);
-            }
-        } else {
-            eprintln!("No settings key configured for this text view");
+        *self.count_code_blocks.borrow_mut() = count_code_blocks;
+        if self.count_code_check.is_active() != count_code_blocks {
+            self.count_code_check.set_active(count_code_blocks);
+        }
+        if let Err(e) = self.set_config_value("count-code-blocks", &count_code_blocks.to_string()) {
+            eprintln!("Failed to save code block counting option: {}", e);
+        }
+        self.update_counts();
+        if self.stats_popover.is_visible() {
+            self.update_stats_popover();
         }
     }
 
-    pub fn load_from_settings(&self) {
-        if let Some(key) = self.settings_key.borrow().as_ref() {
-            *self.is_loading.borrow_mut() = true;
-            let saved_text = self.get_config_value(key, "");
-            let buffer = self.text_view.buffer();
-            buffer.set_text(&saved_text);
-            self.update_counts();
-            *self.is_loading.borrow_mut() = false;
-            println!(
-                "Loaded text from key: {} (length: {})",
-                key,
-                saved_text.len()
-            );
+    fn update_stats_popover(&self) {
+        let document = self.document_stats();
+        let selection = self.selection_stats();
+
+        // Clear previous rows
+        while let Some(child) = self.stats_grid.first_child() {
+            self.stats_grid.remove(&child);
+        }
+
+        let format_score = |score: Option<f64>| match score {
+            Some(score) => format!("{:.1}", score),
+            None => "–".to_string(),
+        };
+
+        let rows: Vec<(&str, String, Option<String>)> = vec![
+            (
+                "Words",
+                document.words.to_string(),
+                selection.as_ref().map(|s| s.words.to_string()),
+            ),
+            (
+                "Characters",
+                document.characters.to_string(),
+                selection.as_ref().map(|s| s.characters.to_string()),
+            ),
+            (
+                "Characters (no spaces)",
+                document.characters_no_spaces.to_string(),
+                selection
+                    .as_ref()
+                    .map(|s| s.characters_no_spaces.to_string()),
+            ),
+            (
+                "Sentences",
+                document.sentences.to_string(),
+                selection.as_ref().map(|s| s.sentences.to_string()),
+            ),
+            (
+                "Paragraphs",
+                document.paragraphs.to_string(),
+                selection.as_ref().map(|s| s.paragraphs.to_string()),
+            ),
+            ("Headings", document.headings.to_string(), None),
+            ("Links", document.links.to_string(), None),
+            ("Images", document.images.to_string(), None),
+            ("Code blocks", document.code_blocks.to_string(), None),
+            (
+                "Reading time",
+                stats::format_minutes(document.reading_minutes()),
+                None,
+            ),
+            (
+                "Speaking time",
+                stats::format_minutes(document.speaking_minutes()),
+                None,
+            ),
+            (
+                "Reading ease",
+                format_score(document.flesch_reading_ease()),
+                None,
+            ),
+            (
+                "Grade level",
+                format_score(document.flesch_kincaid_grade()),
+                None,
+            ),
+        ];
+
+        // Header row
+        let document_header = gtk::Label::new(Some("Document"));
+        document_header.add_css_class("heading");
+        document_header.set_xalign(1.0);
+        self.stats_grid.attach(&document_header, 1, 0, 1, 1);
+        if selection.is_some() {
+            let selection_header = gtk::Label::new(Some("Selection"));
+            selection_header.add_css_class("heading");
+            selection_header.set_xalign(1.0);
+            self.stats_grid.attach(&selection_header, 2, 0, 1, 1);
+        }
+
+        for (row, (name, document_value, selection_value)) in rows.into_iter().enumerate() {
+            let row = row as i32 + 1;
+
+            let name_label = gtk::Label::new(Some(name));
+            name_label.set_xalign(0.0);
+            name_label.add_css_class("dim-label");
+            self.stats_grid.attach(&name_label, 0, row, 1, 1);
+
+            let value_label = gtk::Label::new(Some(&document_value));
+            value_label.set_xalign(1.0);
+            value_label.add_css_class("numeric");
+            self.stats_grid.attach(&value_label, 1, row, 1, 1);
+
+            if let Some(selection_value) = selection_value {
+                let selection_label = gtk::Label::new(Some(&selection_value));
+                selection_label.set_xalign(1.0);
+                selection_label.add_css_class("numeric");
+                self.stats_grid.attach(&selection_label, 2, row, 1, 1);
+            }
         }
     }
 
-    pub fn set_auto_save(&self, auto_save: bool) {
-        *self.auto_save.borrow_mut() = auto_save;
+    // Writing goals
+
+    /// The key used to store per-document goals
+    fn document_goal_key(&self) -> String {
+        if let Some(ref path) = *self.current_file.borrow() {
+            return path.to_string_lossy().into_owned();
+        }
+        self.settings_key
+            .borrow()
+            .clone()
+            .unwrap_or_else(|| "default".to_string())
     }
-}
 
-impl ObjectImpl for CustomTextView {
-    fn constructed(&self) {
-        self.parent_constructed();
+    /// Add newly typed words to the session and the daily history.
+    /// Deletions and text loaded from storage are not counted as writing.
+    fn track_writing_progress(&self, word_count: usize) {
+        let previous = self.last_word_count.replace(Some(word_count));
+        if *self.is_loading.borrow() {
+            return;
+        }
+        let Some(previous) = previous else {
+            return;
+        };
 
-        // the template child WebView
-        self.web_view.load_uri("https://www.gtk.org");
-        self.web_view.set_vexpand(true);
-        self.web_view.set_hexpand(true);
+        if word_count > previous {
+            let added = word_count - previous;
+            self.session.borrow_mut().words_added += added;
 
-        self.web_view
-            .connect_context_menu(|_web_view, _context_menu, _event| true);
+            self.writing_history
+                .borrow_mut()
+                .add_words(Local::now().date_naive(), added);
+            self.schedule_history_save();
 
-        self.web_view.connect_load_changed(|_view, event| {
-            println!("WebView load event: {:?}", event);
+            self.update_session_label();
+            self.history_chart.queue_draw();
+        }
+    }
+
+    /// Write the history once typing pauses instead of on every word
+    fn schedule_history_save(&self) {
+        if self.history_save_pending.replace(true) {
+            return;
+        }
+        let imp_weak = self.obj().downgrade();
+        glib::timeout_add_local_once(Duration::from_secs(5), move || {
+            if let Some(widget) = imp_weak.upgrade() {
+                widget.imp().save_writing_history();
+            }
         });
+    }
 
-        // Set up the text view properties
-        self.text_view.set_wrap_mode(gtk::WrapMode::None);
-        self.text_view.set_accepts_tab(true);
-        self.text_view.set_monospace(false);
-        self.text_view.set_left_margin(8);
-        self.text_view.set_right_margin(8);
-        self.text_view.set_top_margin(8);
-        self.text_view.set_bottom_margin(8);
-        self.text_view.add_css_class("monospace");
+    fn save_writing_history(&self) {
+        if !self.history_save_pending.replace(false) {
+            return;
+        }
+        if let Err(e) = self.writing_history.borrow().save() {
+            eprintln!("Failed to save writing history: {}", e);
+        }
+    }
 
-        // Set up scrolled window properties
-        self.scrolled_window
-            .set_policy(gtk::PolicyType::Automatic, gtk::PolicyType::Automatic);
-        self.scrolled_window.set_min_content_height(150);
-        self.scrolled_window.add_css_class("card");
+    fn update_goal_progress(&self, word_count: usize) {
+        let document_goal = self.document_goals.borrow().get(&self.document_goal_key());
+        let daily_goal = *self.daily_goal.borrow();
+        let words_today = self.writing_history.borrow().words_today();
 
-        // Set up placeholder initially
-        self.placeholder_label.set_visible(true);
-        self.placeholder_label.set_sensitive(false);
-        self.placeholder_label.add_css_class("dim-label");
+        let mut tooltip = Vec::new();
+        if document_goal > 0 {
+            tooltip.push(format!(
+                "Document: {} / {} words",
+                word_count, document_goal
+            ));
+        }
+        if daily_goal > 0 {
+            tooltip.push(format!("Today: {} / {} words", words_today, daily_goal));
+        }
 
-        // Add CSS classes for other widgets
-        self.title_label.add_css_class("heading");
-        self.save_button.add_css_class("flat");
-        self.clear_button.add_css_class("flat");
-        self.clear_button.add_css_class("destructive-action");
-        self.char_count_label.add_css_class("caption");
-        self.char_count_label.add_css_class("dim-label");
-        self.word_count_label.add_css_class("caption");
-        self.word_count_label.add_css_class("dim-label");
+        // Prefer the document goal when both are set
+        let fraction = if document_goal > 0 {
+            word_count as f64 / document_goal as f64
+        } else if daily_goal > 0 {
+            words_today as f64 / daily_goal as f64
+        } else {
+            self.goal_progress.set_visible(false);
+            return;
+        };
 
-        // Manually connect button signals
-        self.save_button.connect_clicked(glib::clone!(
+        self.goal_progress.set_fraction(fraction.min(1.0));
+        self.goal_progress
+            .set_tooltip_text(Some(&tooltip.join("\n")));
+        self.goal_progress.set_visible(true);
+    }
+
+    fn update_session_label(&self) {
+        let session = self.session.borrow();
+        self.session_label.set_text(&format!(
+            "Session: {} · {} words · {} today",
+            session.elapsed_text(),
+            session.words_added,
+            self.writing_history.borrow().words_today()
+        ));
+    }
+
+    /// Set the word target for the current document, 0 removes it
+    pub fn set_document_goal(&self, goal: usize) {
+        let mut goals = self.document_goals.borrow_mut();
+        goals.set(&self.document_goal_key(), goal);
+        if let Err(e) = goals.save() {
+            eprintln!("Failed to save document goals: {}", e);
+        }
+        drop(goals);
+
+        if self.document_goal_spin.value() as usize != goal {
+            self.document_goal_spin.set_value(goal as f64);
+        }
+        self.update_counts();
+    }
+
+    /// Set the number of words to write per day, 0 removes the goal
+    pub fn set_daily_goal(&self, goal: usize) {
+        *self.daily_goal.borrow_mut() = goal;
+        if let Err(e) = self.set_config_value("daily-word-goal", &goal.to_string()) {
+            eprintln!("Failed to save daily goal: {}", e);
+        }
+
+        if self.daily_goal_spin.value() as usize != goal {
+            self.daily_goal_spin.set_value(goal as f64);
+        }
+        self.update_counts();
+    }
+
+    /// Show the saved goals without saving them again from the spin buttons
+    fn load_goals(&self) {
+        *self.loading_goals.borrow_mut() = true;
+        let daily_goal = self
+            .get_config_value("daily-word-goal", "0")
+            .parse::<usize>()
+            .unwrap_or(0);
+        *self.daily_goal.borrow_mut() = daily_goal;
+        self.daily_goal_spin.set_value(daily_goal as f64);
+
+        let document_goal = self.document_goals.borrow().get(&self.document_goal_key());
+        self.document_goal_spin.set_value(document_goal as f64);
+        *self.loading_goals.borrow_mut() = false;
+        self.update_counts();
+    }
+
+    /// Draw a bar chart of the words written on each of the last 30 days
+    fn draw_history_chart(&self, cr: &gtk::cairo::Context, width: i32, height: i32) {
+        let days = self.writing_history.borrow().last_days(30);
+        let today = Local::now().date_naive();
+        let daily_goal = *self.daily_goal.borrow();
+        let max_words = days
+            .iter()
+            .map(|(_, words)| *words)
+            .max()
+            .unwrap_or(0)
+            .max(daily_goal)
+            .max(1) as f64;
+
+        let color = self.history_chart.color();
+        let (width, height) = (width as f64, height as f64);
+        let bar_width = width / days.len().max(1) as f64;
+
+        for (index, (date, words)) in days.iter().enumerate() {
+            let bar_height = (*words as f64 / max_words) * (height - 1.0);
+            let x = index as f64 * bar_width;
+
+            // Highlight today and days that reached the goal
+            let alpha = if daily_goal > 0 && *words >= daily_goal {
+                0.9
+            } else if *date == today {
+                0.6
+            } else {
+                0.4
+            };
+            cr.set_source_rgba(
+                color.red() as f64,
+                color.green() as f64,
+                color.blue() as f64,
+                alpha,
+            );
+            cr.rectangle(x + 1.0, height - bar_height, bar_width - 2.0, bar_height);
+            let _ = cr.fill();
+        }
+
+        // Dashed line at the daily goal
+        if daily_goal > 0 {
+            let y = height - (daily_goal as f64 / max_words) * (height - 1.0);
+            cr.set_source_rgba(
+                color.red() as f64,
+                color.green() as f64,
+                color.blue() as f64,
+                0.8,
+            );
+            cr.set_line_width(1.0);
+            cr.set_dash(&[4.0, 4.0], 0.0);
+            cr.move_to(0.0, y);
+            cr.line_to(width, y);
+            let _ = cr.stroke();
+        }
+    }
+
+    // Focus mode
+
+    pub fn is_focus_mode(&self) -> bool {
+        *self.focus_mode.borrow()
+    }
+
+    /// Enter or leave the distraction-free focus mode
+    pub fn set_focus_mode(&self, enabled: bool) {
+        if self.is_focus_mode() == enabled {
+            return;
+        }
+        *self.focus_mode.borrow_mut() = enabled;
+        println!("Focus mode: {}", enabled);
+        // Escape is only taken while there is a focus mode to leave
+        self.obj()
+            .action_set_enabled("editor.exit-focus-mode", enabled);
+
+        self.header_bar.set_visible(!enabled);
+        self.status_bar.set_visible(!enabled);
+        self.nav_revealer.set_visible(!enabled);
+
+        if enabled {
+            *self.saved_margins.borrow_mut() =
+                (self.text_view.top_margin(), self.text_view.bottom_margin());
+            self.update_focus_margins();
+            self.update_focus_dimming();
+            self.center_cursor();
+        } else {
+            let (top, bottom) = *self.saved_margins.borrow();
+            self.text_view.set_top_margin(top);
+            self.text_view.set_bottom_margin(bottom);
+            let buffer = self.text_view.buffer();
+            buffer.remove_tag_by_name(FOCUS_DIM_TAG, &buffer.start_iter(), &buffer.end_iter());
+        }
+        self.apply_focus_fullscreen();
+
+        if let Err(e) = self.set_config_value("focus-mode", &enabled.to_string()) {
+            eprintln!("Failed to save focus mode state: {}", e);
+        }
+    }
+
+    // Leave room so the first and last lines can be centred too
+    fn update_focus_margins(&self) {
+        if !self.is_focus_mode() {
+            return;
+        }
+        let half_height = self.scrolled_window.height() / 2;
+        self.text_view.set_top_margin(half_height);
+        self.text_view.set_bottom_margin(half_height);
+        self.center_cursor();
+    }
+
+    fn apply_focus_fullscreen(&self) {
+        if let Some(window) = self.obj().root().and_downcast::<gtk::Window>() {
+            if self.is_focus_mode() {
+                if !window.is_fullscreen() {
+                    window.fullscreen();
+                    *self.focus_fullscreened.borrow_mut() = true;
+                }
+            } else if self.focus_fullscreened.replace(false) {
+                window.unfullscreen();
+            }
+        }
+
+        // Wait for the fullscreen transition before measuring
+        let imp_weak = self.obj().downgrade();
+        glib::timeout_add_local_once(Duration::from_millis(200), move || {
+            if let Some(widget) = imp_weak.upgrade() {
+                widget.imp().update_focus_margins();
+            }
+        });
+    }
+
+    /// The paragraph containing the cursor: lines up to the nearest blank line either side
+    fn cursor_paragraph_bounds(&self) -> (gtk::TextIter, gtk::TextIter) {
+        let buffer = self.text_view.buffer();
+        let cursor = buffer.iter_at_mark(&buffer.get_insert());
+
+        let is_blank = |line: i32| -> bool {
+            let Some(start) = buffer.iter_at_line(line) else {
+                return true;
+            };
+            let mut end = start;
+            if !end.ends_line() {
+                end.forward_to_line_end();
+            }
+            buffer.text(&start, &end, false).trim().is_empty()
+        };
+
+        let mut first = cursor.line();
+        while first > 0 && !is_blank(first - 1) {
+            first -= 1;
+        }
+        let mut last = cursor.line();
+        while last + 1 < buffer.line_count() && !is_blank(last + 1) {
+            last += 1;
+        }
+
+        let start = buffer
+            .iter_at_line(first)
+            .unwrap_or_else(|| buffer.start_iter());
+        let mut end = buffer
+            .iter_at_line(last)
+            .unwrap_or_else(|| buffer.end_iter());
+        if !end.ends_line() {
+            end.forward_to_line_end();
+        }
+        (start, end)
+    }
+
+    /// Dim every paragraph except the one containing the cursor
+    fn update_focus_dimming(&self) {
+        if !self.is_focus_mode() {
+            return;
+        }
+        let buffer = self.text_view.buffer();
+        let Some(tag) = buffer.tag_table().lookup(FOCUS_DIM_TAG) else {
+            return;
+        };
+
+        let mut dim_color = self.text_view.color();
+        dim_color.set_alpha(0.35);
+        tag.set_foreground_rgba(Some(&dim_color));
+
+        let (paragraph_start, paragraph_end) = self.cursor_paragraph_bounds();
+        buffer.remove_tag(&tag, &buffer.start_iter(), &buffer.end_iter());
+        buffer.apply_tag(&tag, &buffer.start_iter(), &paragraph_start);
+        buffer.apply_tag(&tag, &paragraph_end, &buffer.end_iter());
+    }
+
+    /// Typewriter scrolling: keep the cursor line vertically centred
+    fn center_cursor(&self) {
+        if !self.is_focus_mode() {
+            return;
+        }
+        let buffer = self.text_view.buffer();
+        self.text_view
+            .scroll_to_mark(&buffer.get_insert(), 0.0, true, 0.0, 0.5);
+    }
+
+    // Preview
+
+    /// Render the buffer into the preview with the current theme
+    fn update_preview(&self) {
+        let buffer = self.text_view.buffer();
+        let start = buffer.start_iter();
+        let end = buffer.end_iter();
+        let text = buffer.text(&start, &end, false);
+
+        let dark = adw::StyleManager::default().is_dark();
+        let css = preview::page_css(&self.config_dir, &self.preview_theme.borrow(), dark);
+        let html = preview::render_page(&text, &css, dark);
+        self.web_view.load_html(&html, None);
+    }
+
+    /// Update the preview shortly after typing stops instead of on every keystroke
+    fn schedule_preview_update(&self) {
+        if self.preview_update_pending.replace(true) {
+            return;
+        }
+        let imp_weak = self.obj().downgrade();
+        glib::timeout_add_local_once(Duration::from_millis(300), move || {
+            if let Some(widget) = imp_weak.upgrade() {
+                let imp = widget.imp();
+                *imp.preview_update_pending.borrow_mut() = false;
+                imp.update_preview();
+            }
+        });
+    }
+
+    /// Switch the preview stylesheet, by theme id
+    pub fn set_preview_theme(&self, id: &str) {
+        *self.preview_theme.borrow_mut() = id.to_string();
+        if let Err(e) = self.set_config_value("preview-theme", id) {
+            eprintln!("Failed to save preview theme: {}", e);
+        }
+        self.select_preview_theme();
+        self.update_preview();
+    }
+
+    fn select_preview_theme(&self) {
+        let current = self.preview_theme.borrow().clone();
+        if let Some(index) = self
+            .preview_themes
+            .borrow()
+            .iter()
+            .position(|theme| theme.id == current)
+        {
+            *self.populating_themes.borrow_mut() = true;
+            self.preview_theme_dropdown.set_selected(index as u32);
+            *self.populating_themes.borrow_mut() = false;
+        }
+    }
+
+    /// Reload the list of built-in and user themes into the dropdown
+    fn load_preview_themes(&self) {
+        let themes = preview::available_themes(&self.config_dir);
+        let names: Vec<&str> = themes.iter().map(|theme| theme.name.as_str()).collect();
+        let model = gtk::StringList::new(&names);
+
+        *self.populating_themes.borrow_mut() = true;
+        self.preview_theme_dropdown.set_model(Some(&model));
+        *self.populating_themes.borrow_mut() = false;
+        *self.preview_themes.borrow_mut() = themes;
+
+        self.select_preview_theme();
+    }
+
+    /// Watch the user themes directory so edited stylesheets apply immediately
+    fn monitor_user_themes(&self) {
+        let dir = preview::themes_dir(&self.config_dir);
+        if let Err(e) = fs::create_dir_all(&dir) {
+            eprintln!("Failed to create themes directory: {}", e);
+            return;
+        }
+
+        let monitor = match gio::File::for_path(&dir)
+            .monitor_directory(gio::FileMonitorFlags::NONE, gio::Cancellable::NONE)
+        {
+            Ok(monitor) => monitor,
+            Err(e) => {
+                eprintln!("Failed to monitor themes directory: {}", e);
+                return;
+            }
+        };
+
+        monitor.connect_changed(glib::clone!(
+            #[weak(rename_to = imp)]
+            self,
+            move |_, file, _, event| {
+                println!("Theme file {:?} changed: {:?}", file.path(), event);
+                imp.load_preview_themes();
+                if preview::is_user_theme(&imp.preview_theme.borrow()) {
+                    imp.schedule_preview_update();
+                }
+            }
+        ));
+        *self.theme_monitor.borrow_mut() = Some(monitor);
+    }
+
+    // Front matter
+
+    /// The metadata from the document's front matter, if it has any
+    pub fn front_matter(&self) -> Option<FrontMatter> {
+        let buffer = self.text_view.buffer();
+        FrontMatter::parse(&buffer.text(&buffer.start_iter(), &buffer.end_iter(), false))
+    }
+
+    /// Show the front matter title in the header and window title
+    fn update_title(&self, title: Option<&str>) {
+        let title = title.unwrap_or(DEFAULT_TITLE);
+        self.title_label.set_text(title);
+        if let Some(window) = self.obj().root().and_downcast::<gtk::Window>() {
+            window.set_title(Some(title));
+        }
+    }
+
+    /// Refresh the title and properties form from the front matter
+    fn update_metadata(&self) {
+        if *self.updating_metadata.borrow() {
+            return;
+        }
+        let front_matter = self.front_matter().unwrap_or_default();
+        self.update_title(front_matter.title.as_deref());
+
+        // Only touch entries whose meaning changed, so typing in them isn't disturbed
+        *self.updating_metadata.borrow_mut() = true;
+        let title = front_matter.title.as_deref().unwrap_or("");
+        if self.metadata_title_entry.text().trim() != title {
+            self.metadata_title_entry.set_text(title);
+        }
+        let date = front_matter.date.as_deref().unwrap_or("");
+        if self.metadata_date_entry.text().trim() != date {
+            self.metadata_date_entry.set_text(date);
+        }
+        if frontmatter::tags_from_list(&self.metadata_tags_entry.text()) != front_matter.tags {
+            self.metadata_tags_entry
+                .set_text(&front_matter.tags.join(", "));
+        }
+        *self.updating_metadata.borrow_mut() = false;
+
+        let other_keys = front_matter.other_keys();
+        self.metadata_other_label
+            .set_text(&format!("Other fields: {}", other_keys.join(", ")));
+        self.metadata_other_label
+            .set_visible(!other_keys.is_empty());
+    }
+
+    /// Write the properties form back into the document's front matter,
+    /// adding the block if the document has none
+    fn apply_metadata_form(&self) {
+        if *self.updating_metadata.borrow() {
+            return;
+        }
+        let buffer = self.text_view.buffer();
+        let text = buffer.text(&buffer.start_iter(), &buffer.end_iter(), false);
+
+        // Only the entries that changed are rewritten, so the rest of the
+        // block keeps its order and formatting
+        let current = FrontMatter::parse(&text).unwrap_or_default();
+        let non_empty = |entry: &gtk::Entry| {
+            let value = entry.text().trim().to_string();
+            (!value.is_empty()).then_some(value)
+        };
+        let mut new_text = text.to_string();
+        let title = non_empty(&self.metadata_title_entry);
+        if title != current.title {
+            let entry = title
+                .as_deref()
+                .map(|t| frontmatter::scalar_entry("title", t));
+            new_text = frontmatter::set_entry(&new_text, "title", entry.as_deref());
+        }
+        let date = non_empty(&self.metadata_date_entry);
+        if date != current.date {
+            let entry = date
+                .as_deref()
+                .map(|d| frontmatter::scalar_entry("date", d));
+            new_text = frontmatter::set_entry(&new_text, "date", entry.as_deref());
+        }
+        let tags = frontmatter::tags_from_list(&self.metadata_tags_entry.text());
+        if tags != current.tags {
+            let entry = (!tags.is_empty()).then(|| frontmatter::tags_entry(&new_text, &tags));
+            new_text = frontmatter::set_entry(&new_text, "tags", entry.as_deref());
+        }
+
+        let old_block = &text[..frontmatter::block_len(&text)];
+        let new_block = &new_text[..frontmatter::block_len(&new_text)];
+        if old_block == new_block {
+            return;
+        }
+
+        // Replace only the block so the cursor stays where it was in the body
+        *self.updating_metadata.borrow_mut() = true;
+        let mut start = buffer.start_iter();
+        let mut end = self.iter_at_text_offset(old_block.chars().count() as i32);
+        buffer.begin_user_action();
+        buffer.delete(&mut start, &mut end);
+        buffer.insert(&mut start, new_block);
+        buffer.end_user_action();
+        *self.updating_metadata.borrow_mut() = false;
+
+        let title = FrontMatter::parse(new_block).and_then(|front_matter| front_matter.title);
+        self.update_title(title.as_deref());
+    }
+
+    // Notes and wiki-links
+
+    fn notes_dir(&self) -> PathBuf {
+        notes::notes_dir(&self.get_config_value("notes-folder", ""))
+    }
+
+    /// Load a file into the editor, saving the current document first
+    pub fn open_file(&self, path: &Path) {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) => {
+                eprintln!("Failed to open {:?}: {}", path, e);
+                return;
+            }
+        };
+        self.save_document();
+
+        *self.is_loading.borrow_mut() = true;
+        *self.current_file.borrow_mut() = Some(path.to_path_buf());
+        let buffer = self.text_view.buffer();
+        buffer.set_text(&text);
+        buffer.place_cursor(&buffer.start_iter());
+        buffer.set_modified(false);
+        *self.is_loading.borrow_mut() = false;
+
+        *self.disk_text.borrow_mut() = Some(text);
+        *self.disk_etag.borrow_mut() = Self::file_etag(path);
+        self.set_file_conflict(false);
+        self.watch_file(path);
+        self.end_snippet();
+        self.load_git_state();
+        self.load_goals();
+        self.load_image_preview_state();
+        self.update_backlinks();
+        if let Some(ref settings) = self.settings {
+            recent::record_opened(settings, path);
+        }
+        println!("Opened {:?}", path);
+    }
+
+    /// Create an empty note in the notes folder and open it
+    pub fn new_document(&self) {
+        self.create_document("Untitled", "");
+    }
+
+    /// Create a note in the notes folder, named after its title, and open it
+    fn create_document(&self, title: &str, text: &str) {
+        let dir = self.notes_dir();
+        let name = notes::file_name(title);
+        let path = notes::new_note_path(&dir, if name.is_empty() { "Untitled" } else { &name });
+        let created = fs::create_dir_all(&dir).and_then(|_| fs::write(&path, text));
+        if let Err(e) = created {
+            eprintln!("Failed to create {:?}: {}", path, e);
+            return;
+        }
+
+        // Let the window switch to the editor when it is showing another page
+        let path_variant = path.to_string_lossy().to_variant();
+        if self
+            .obj()
+            .activate_action("win.open-file", Some(&path_variant))
+            .is_err()
+        {
+            self.open_file(&path);
+        }
+        self.text_view.grab_focus();
+    }
+
+    /// Ask for a template, a title and the template's own placeholders, then
+    /// create a note from it
+    pub fn show_new_from_template_dialog(&self) {
+        let templates = Rc::new(templates::available_templates(&self.config_dir));
+        let names: Vec<&str> = templates.iter().map(|t| t.name.as_str()).collect();
+
+        let template_row = adw::ComboRow::new();
+        template_row.set_title("Template");
+        template_row.set_model(Some(&gtk::StringList::new(&names)));
+        let title_row = adw::EntryRow::new();
+        title_row.set_title("Title");
+
+        let fields = adw::PreferencesGroup::new();
+        fields.add(&template_row);
+        fields.add(&title_row);
+
+        // Rows for the selected template's custom placeholders
+        let variable_rows: Rc<RefCell<Vec<(String, adw::EntryRow)>>> = Rc::default();
+        let update_variables = glib::clone!(
+            #[weak]
+            fields,
+            #[strong]
+            templates,
+            #[strong]
+            variable_rows,
+            move |row: &adw::ComboRow| {
+                for (_, entry) in variable_rows.borrow_mut().drain(..) {
+                    fields.remove(&entry);
+                }
+                let Some(template) = templates.get(row.selected() as usize) else {
+                    return;
+                };
+                for name in template.variables() {
+                    let label = name.replace(['_', '-'], " ");
+                    let mut chars = label.chars();
+                    let label: String = chars
+                        .next()
+                        .map(|first| first.to_uppercase().chain(chars).collect())
+                        .unwrap_or_default();
+
+                    let entry = adw::EntryRow::new();
+                    entry.set_title(&label);
+                    fields.add(&entry);
+                    variable_rows.borrow_mut().push((name, entry));
+                }
+            }
+        );
+        update_variables(&template_row);
+        template_row.connect_selected_notify(update_variables);
+
+        let dialog = adw::AlertDialog::new(Some("New from Template"), None);
+        dialog.set_extra_child(Some(&fields));
+        dialog.add_responses(&[("cancel", "Cancel"), ("create", "Create")]);
+        dialog.set_response_appearance("create", adw::ResponseAppearance::Suggested);
+        dialog.set_default_response(Some("create"));
+        dialog.set_close_response("cancel");
+
+        dialog.connect_response(
+            None,
+            glib::clone!(
+                #[weak(rename_to = imp)]
+                self,
+                #[strong]
+                template_row,
+                #[strong]
+                title_row,
+                move |_, response| {
+                    if response != "create" {
+                        return;
+                    }
+                    let Some(template) = templates.get(template_row.selected() as usize) else {
+                        return;
+                    };
+                    let title = title_row.text().trim().to_string();
+                    let title = if title.is_empty() {
+                        "Untitled".to_string()
+                    } else {
+                        title
+                    };
+                    let values: HashMap<String, String> = variable_rows
+                        .borrow()
+                        .iter()
+                        .map(|(name, entry)| (name.clone(), entry.text().to_string()))
+                        .collect();
+                    imp.create_document(&title, &template.expand(&title, &values));
+                }
+            ),
+        );
+        dialog.present(Some(&*self.obj()));
+        title_row.grab_focus();
+    }
+
+    /// Auto-save once typing pauses, so the document isn't written on every
+    /// keystroke and saving never edits the buffer from its `changed` handler
+    fn schedule_save(&self) {
+        if self.save_pending.replace(true) {
+            return;
+        }
+        let imp_weak = self.obj().downgrade();
+        glib::timeout_add_local_once(Duration::from_secs(1), move || {
+            if let Some(widget) = imp_weak.upgrade() {
+                let imp = widget.imp();
+                if imp.save_pending.replace(false) {
+                    imp.save_document();
+                }
+            }
+        });
+    }
+
+    /// Save to the open file, or to settings for the scratch document.
+    /// Returns false if the file couldn't be written or changed on disk.
+    pub fn save_document(&self) -> bool {
+        if *self.is_loading.borrow() {
+            return true;
+        }
+        self.update_table_of_contents();
+        // Saving now covers any pending auto-save, including the one the
+        // table of contents edit just scheduled
+        *self.save_pending.borrow_mut() = false;
+        let Some(path) = self.current_file.borrow().clone() else {
+            if self.settings_key.borrow().is_some() {
+                self.save_to_settings();
+            }
+            return true;
+        };
+        if *self.file_conflict.borrow() {
+            return false;
+        }
+
+        // Don't overwrite a change made by another program that the monitor
+        // hasn't reported yet
+        let etag = Self::file_etag(&path);
+        if etag.is_some() && etag != *self.disk_etag.borrow() {
+            self.check_file_on_disk();
+            if *self.file_conflict.borrow() || Self::file_etag(&path) != *self.disk_etag.borrow() {
+                return false;
+            }
+        }
+
+        let buffer = self.text_view.buffer();
+        let text = buffer.text(&buffer.start_iter(), &buffer.end_iter(), false);
+        if let Err(e) = fs::write(&path, text.as_bytes()) {
+            eprintln!("Failed to save {:?}: {}", path, e);
+            return false;
+        }
+        *self.disk_text.borrow_mut() = Some(text.to_string());
+        *self.disk_etag.borrow_mut() = Self::file_etag(&path);
+        buffer.set_modified(false);
+        true
+    }
+
+    /// Reload the open file from disk, keeping the cursor and scroll position
+    fn reload_file(&self) {
+        let Some(path) = self.current_file.borrow().clone() else {
+            return;
+        };
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) => {
+                eprintln!("Failed to reload {:?}: {}", path, e);
+                return;
+            }
+        };
+
+        let buffer = self.text_view.buffer();
+        let offset = buffer.iter_at_mark(&buffer.get_insert()).offset();
+        let scroll = self.scrolled_window.vadjustment().value();
+        *self.is_loading.borrow_mut() = true;
+        buffer.set_text(&text);
+        buffer.place_cursor(&buffer.iter_at_offset(offset));
+        buffer.set_modified(false);
+        *self.is_loading.borrow_mut() = false;
+
+        *self.disk_text.borrow_mut() = Some(text);
+        *self.disk_etag.borrow_mut() = Self::file_etag(&path);
+        self.set_file_conflict(false);
+
+        // The new text is laid out on idle, so restore the scroll after it
+        let imp_weak = self.obj().downgrade();
+        glib::idle_add_local_once(move || {
+            if let Some(widget) = imp_weak.upgrade() {
+                widget.imp().scrolled_window.vadjustment().set_value(scroll);
+            }
+        });
+    }
+
+    // Files changed on disk
+
+    /// Watch the open file for changes made by other programs
+    fn watch_file(&self, path: &Path) {
+        let monitor = match gio::File::for_path(path)
+            .monitor_file(gio::FileMonitorFlags::WATCH_MOVES, gio::Cancellable::NONE)
+        {
+            Ok(monitor) => monitor,
+            Err(e) => {
+                eprintln!("Failed to monitor {:?}: {}", path, e);
+                *self.file_monitor.borrow_mut() = None;
+                return;
+            }
+        };
+        // Editors often save by writing several events; wait for the last one
+        monitor.set_rate_limit(500);
+        monitor.connect_changed(glib::clone!(
+            #[weak(rename_to = imp)]
+            self,
+            move |_, _, _, event| {
+                if matches!(
+                    event,
+                    gio::FileMonitorEvent::ChangesDoneHint
+                        | gio::FileMonitorEvent::Created
+                        | gio::FileMonitorEvent::Deleted
+                        | gio::FileMonitorEvent::MovedOut
+                        | gio::FileMonitorEvent::Renamed
+                ) {
+                    imp.check_file_on_disk();
+                }
+            }
+        ));
+        *self.file_monitor.borrow_mut() = Some(monitor);
+    }
+
+    /// Compare the open file with what was last loaded or saved. Unmodified
+    /// buffers are reloaded silently; unsaved edits raise the conflict banner.
+    fn check_file_on_disk(&self) {
+        let Some(path) = self.current_file.borrow().clone() else {
+            return;
+        };
+        let on_disk = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) => {
+                // Keep the buffer so the next save recreates the file
+                eprintln!("{:?} is no longer readable: {}", path, e);
+                return;
+            }
+        };
+        if self.disk_text.borrow().as_deref() == Some(on_disk.as_str()) {
+            // Touched without changing it
+            *self.disk_etag.borrow_mut() = Self::file_etag(&path);
+            return;
+        }
+
+        let buffer = self.text_view.buffer();
+        let text = buffer.text(&buffer.start_iter(), &buffer.end_iter(), false);
+        if text == on_disk {
+            *self.disk_text.borrow_mut() = Some(on_disk);
+            *self.disk_etag.borrow_mut() = Self::file_etag(&path);
+            buffer.set_modified(false);
+        } else if !buffer.is_modified() {
+            println!("Reloading {:?} after it changed on disk", path);
+            self.reload_file();
+        } else {
+            self.set_file_conflict(true);
+        }
+    }
+
+    /// The file's entity tag, which changes whenever it is written
+    fn file_etag(path: &Path) -> Option<String> {
+        gio::File::for_path(path)
+            .query_info(
+                gio::FILE_ATTRIBUTE_ETAG_VALUE,
+                gio::FileQueryInfoFlags::NONE,
+                gio::Cancellable::NONE,
+            )
+            .ok()
+            .and_then(|info| info.etag())
+            .map(|etag| etag.to_string())
+    }
+
+    fn set_file_conflict(&self, conflict: bool) {
+        *self.file_conflict.borrow_mut() = conflict;
+        self.file_changed_banner.set_revealed(conflict);
+    }
+
+    /// Keep the edits in the buffer, overwriting the version on disk
+    fn keep_buffer_version(&self) {
+        let path = self.current_file.borrow().clone();
+        *self.disk_text.borrow_mut() = path.as_ref().and_then(|path| fs::read_to_string(path).ok());
+        *self.disk_etag.borrow_mut() = path.as_deref().and_then(Self::file_etag);
+        self.set_file_conflict(false);
+        self.save_document();
+    }
+
+    fn show_file_conflict_dialog(&self) {
+        let dialog = adw::AlertDialog::new(
+            Some("File Changed on Disk"),
+            Some("Another program changed this file while it has unsaved edits here"),
+        );
+        dialog.add_responses(&[
+            ("cancel", "Cancel"),
+            ("compare", "Compare…"),
+            ("keep", "Keep Mine"),
+            ("reload", "Reload"),
+        ]);
+        dialog.set_response_appearance("reload", adw::ResponseAppearance::Destructive);
+        dialog.set_default_response(Some("compare"));
+        dialog.set_close_response("cancel");
+
+        dialog.connect_response(
+            None,
+            glib::clone!(
+                #[weak(rename_to = imp)]
+                self,
+                move |_, response| match response {
+                    "reload" => imp.reload_file(),
+                    "keep" => imp.keep_buffer_version(),
+                    "compare" => imp.show_merge_dialog(),
+                    _ => {}
+                }
+            ),
+        );
+        dialog.present(Some(&*self.obj()));
+    }
+
+    /// Show the version on disk beside the buffer with the differing lines
+    /// highlighted. The buffer side can be edited into a merged version.
+    fn show_merge_dialog(&self) {
+        let Some(path) = self.current_file.borrow().clone() else {
+            return;
+        };
+        let on_disk = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) => {
+                eprintln!("Failed to read {:?}: {}", path, e);
+                return;
+            }
+        };
+        let buffer = self.text_view.buffer();
+        let text = buffer
+            .text(&buffer.start_iter(), &buffer.end_iter(), false)
+            .to_string();
+
+        let side = |title: &str, content: &str, editable: bool| {
+            let view = gtk::TextView::new();
+            view.set_monospace(true);
+            view.set_editable(editable);
+            view.set_left_margin(8);
+            view.set_right_margin(8);
+            view.set_top_margin(8);
+            view.set_bottom_margin(8);
+            view.buffer().set_text(content);
+
+            let scrolled = gtk::ScrolledWindow::new();
+            scrolled.set_child(Some(&view));
+            scrolled.set_vexpand(true);
+            scrolled.set_hexpand(true);
+
+            let heading = gtk::Label::new(Some(title));
+            heading.set_xalign(0.0);
+            heading.set_margin_start(8);
+            heading.set_margin_top(6);
+            heading.set_margin_bottom(6);
+            heading.add_css_class("heading");
+
+            let column = gtk::Box::new(gtk::Orientation::Vertical, 0);
+            column.append(&heading);
+            column.append(&scrolled);
+            (view, scrolled.vadjustment(), column)
+        };
+        let (disk_view, disk_scroll, disk_column) = side("On Disk", &on_disk, false);
+        let (merged_view, merged_scroll, merged_column) =
+            side("Yours (edit to merge)", &text, true);
+
+        for (view, background) in [
+            (&disk_view, "rgba(224, 27, 36, 0.2)"),
+            (&merged_view, "rgba(46, 194, 126, 0.2)"),
+        ] {
+            view.buffer()
+                .create_tag(Some("changed"), &[("paragraph-background", &background)]);
+        }
+        let highlight = |view: &gtk::TextView, line: usize| {
+            let buffer = view.buffer();
+            if let Some(start) = buffer.iter_at_line(line as i32) {
+                let mut end = start;
+                end.forward_line();
+                buffer.apply_tag_by_name("changed", &start, &end);
+            }
+        };
+        for line in diff::line_diff(&on_disk, &text) {
+            match line {
+                DiffLine::Removed(line) => highlight(&disk_view, line),
+                DiffLine::Added(line) => highlight(&merged_view, line),
+                DiffLine::Same(..) => {}
+            }
+        }
+
+        // Scroll both sides together
+        for (from, to) in [
+            (&disk_scroll, &merged_scroll),
+            (&merged_scroll, &disk_scroll),
+        ] {
+            from.connect_value_changed(glib::clone!(
+                #[weak]
+                to,
+                move |from| to.set_value(from.value())
+            ));
+        }
+
+        let paned = gtk::Paned::new(gtk::Orientation::Horizontal);
+        paned.set_start_child(Some(&disk_column));
+        paned.set_end_child(Some(&merged_column));
+        paned.set_wide_handle(true);
+
+        let dialog = adw::Dialog::new();
+        dialog.set_title(&format!(
+            "Compare {}",
+            path.file_name().unwrap_or_default().to_string_lossy()
+        ));
+        dialog.set_content_width(1000);
+        dialog.set_content_height(650);
+
+        let use_disk_button = gtk::Button::with_label("Use Disk Version");
+        let use_merged_button = gtk::Button::with_label("Use Merged");
+        use_merged_button.add_css_class("suggested-action");
+        let header = adw::HeaderBar::new();
+        header.pack_start(&use_disk_button);
+        header.pack_end(&use_merged_button);
+
+        let toolbar_view = adw::ToolbarView::new();
+        toolbar_view.add_top_bar(&header);
+        toolbar_view.set_content(Some(&paned));
+        dialog.set_child(Some(&toolbar_view));
+
+        use_disk_button.connect_clicked(glib::clone!(
+            #[weak(rename_to = imp)]
+            self,
+            #[weak]
+            dialog,
+            move |_| {
+                imp.reload_file();
+                dialog.close();
+            }
+        ));
+        use_merged_button.connect_clicked(glib::clone!(
+            #[weak(rename_to = imp)]
+            self,
+            #[weak]
+            dialog,
+            move |_| {
+                let merged = merged_view.buffer();
+                let merged = merged.text(&merged.start_iter(), &merged.end_iter(), false);
+                let buffer = imp.text_view.buffer();
+                let offset = imp.text_offset(&buffer.iter_at_mark(&buffer.get_insert()));
+                *imp.is_loading.borrow_mut() = true;
+                buffer.set_text(&merged);
+                let offset = offset.min(merged.chars().count() as i32);
+                buffer.place_cursor(&imp.iter_at_text_offset(offset));
+                *imp.is_loading.borrow_mut() = false;
+                imp.keep_buffer_version();
+                dialog.close();
+            }
+        ));
+
+        dialog.present(Some(&*self.obj()));
+    }
+
+    fn go_to_line(&self, line: i32) {
+        let buffer = self.text_view.buffer();
+        if let Some(iter) = buffer.iter_at_line(line) {
+            buffer.place_cursor(&iter);
+        }
+        self.text_view
+            .scroll_to_mark(&buffer.get_insert(), 0.0, true, 0.0, 0.3);
+    }
+
+    /// Move to the first heading with the given text, ignoring case
+    fn go_to_heading(&self, heading: &str) {
+        let buffer = self.text_view.buffer();
+        let text = buffer.text(&buffer.start_iter(), &buffer.end_iter(), false);
+        let wanted = heading.trim().to_lowercase();
+        let line = text.lines().position(|line| {
+            let line = line.trim_start();
+            line.starts_with('#')
+                && line
+                    .trim_start_matches('#')
+                    .trim()
+                    .trim_end_matches('#')
+                    .trim()
+                    .to_lowercase()
+                    == wanted
+        });
+        match line {
+            Some(line) => self.go_to_line(line as i32),
+            None => eprintln!("No heading \"{}\" in this note", heading),
+        }
+    }
+
+    /// Open the note a wiki-link points to, creating it if it doesn't exist
+    pub fn open_wiki_link(&self, target: &str, heading: Option<&str>) {
+        if !target.is_empty() {
+            let dir = self.notes_dir();
+            let path = match notes::resolve(&dir, target) {
+                Some(path) => path,
+                None => match notes::create_note(&dir, target) {
+                    Ok(path) => {
+                        println!("Created note {:?} for link [[{}]]", path, target);
+                        path
+                    }
+                    Err(e) => {
+                        eprintln!("Failed to create note for [[{}]]: {}", target, e);
+                        return;
+                    }
+                },
+            };
+            self.open_file(&path);
+        }
+        if let Some(heading) = heading {
+            self.go_to_heading(heading);
+        }
+    }
+
+    /// The wiki-link under a buffer position, if any
+    fn wiki_link_at(&self, iter: &gtk::TextIter) -> Option<WikiLink> {
+        let buffer = self.text_view.buffer();
+        let text = buffer.text(&buffer.start_iter(), &buffer.end_iter(), false);
+        let offset = text
+            .char_indices()
+            .nth(self.text_offset(iter) as usize)
+            .map_or(text.len(), |(index, _)| index);
+        notes::wiki_links(&text)
+            .into_iter()
+            .find(|link| link.range.contains(&offset))
+    }
+
+    /// Underline wiki-links so they read as clickable
+    fn highlight_wiki_links(&self) {
+        let buffer = self.text_view.buffer();
+        let text = buffer.text(&buffer.start_iter(), &buffer.end_iter(), false);
+        buffer.remove_tag_by_name(WIKI_LINK_TAG, &buffer.start_iter(), &buffer.end_iter());
+        for link in notes::wiki_links(&text) {
+            let start = text[..link.range.start].chars().count() as i32;
+            let end = start + text[link.range.clone()].chars().count() as i32;
+            buffer.apply_tag_by_name(
+                WIKI_LINK_TAG,
+                &self.iter_at_text_offset(start),
+                &self.iter_at_text_offset(end),
+            );
+        }
+    }
+
+    /// List the notes that link to the open note, with the line around each link
+    fn update_backlinks(&self) {
+        self.backlinks_list.remove_all();
+        let placeholder = |text: &str| {
+            let label = gtk::Label::new(Some(text));
+            label.set_wrap(true);
+            label.set_margin_top(12);
+            label.set_margin_start(12);
+            label.set_margin_end(12);
+            label.add_css_class("dim-label");
+            label
+        };
+
+        let Some(path) = self.current_file.borrow().clone() else {
+            self.backlinks.borrow_mut().clear();
+            self.backlinks_list.set_placeholder(Some(&placeholder(
+                "Open a note to see the notes that link to it",
+            )));
+            return;
+        };
+
+        let buffer = self.text_view.buffer();
+        let text = buffer.text(&buffer.start_iter(), &buffer.end_iter(), false);
+        let title = notes::note_title(&path, &text);
+        let backlinks = notes::backlinks(&self.notes_dir(), &path, &title);
+
+        self.backlinks_list
+            .set_placeholder(Some(&placeholder("No notes link here yet")));
+        for backlink in &backlinks {
+            let row_box = gtk::Box::new(gtk::Orientation::Vertical, 2);
+            row_box.set_margin_top(6);
+            row_box.set_margin_bottom(6);
+
+            let title_label = gtk::Label::new(Some(&backlink.title));
+            title_label.set_xalign(0.0);
+            title_label.set_ellipsize(gtk::pango::EllipsizeMode::End);
+            title_label.add_css_class("heading");
+            row_box.append(&title_label);
+
+            let snippet_label = gtk::Label::new(Some(&backlink.snippet));
+            snippet_label.set_xalign(0.0);
+            snippet_label.set_wrap(true);
+            snippet_label.set_lines(3);
+            snippet_label.set_ellipsize(gtk::pango::EllipsizeMode::End);
+            snippet_label.add_css_class("dim-label");
+            snippet_label.add_css_class("caption");
+            row_box.append(&snippet_label);
+
+            let row = gtk::ListBoxRow::new();
+            row.set_child(Some(&row_box));
+            row.set_tooltip_text(Some(&backlink.path.to_string_lossy()));
+            self.backlinks_list.append(&row);
+        }
+        *self.backlinks.borrow_mut() = backlinks;
+    }
+
+    // Tags
+
+    /// Re-scan the notes folder and rebuild the tag browser
+    fn update_tag_index(&self) {
+        let index = TagIndex::build(&self.notes_dir());
+        self.selected_tags
+            .borrow_mut()
+            .retain(|key| index.contains(key));
+
+        self.tags_list.remove_all();
+        let placeholder = gtk::Label::new(Some("No tags in the notes folder"));
+        placeholder.set_margin_top(12);
+        placeholder.add_css_class("dim-label");
+        self.tags_list.set_placeholder(Some(&placeholder));
+
+        for (key, name, count) in index.tags() {
+            let row_box = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+
+            let check = gtk::CheckButton::with_label(&format!("#{}", name));
+            check.set_hexpand(true);
+            check.set_active(self.selected_tags.borrow().contains(&key));
+            check.connect_toggled(glib::clone!(
+                #[weak(rename_to = imp)]
+                self,
+                move |check| {
+                    let mut selected = imp.selected_tags.borrow_mut();
+                    selected.retain(|selected_key| *selected_key != key);
+                    if check.is_active() {
+                        selected.push(key.clone());
+                    }
+                    drop(selected);
+                    imp.update_tag_notes();
+                }
+            ));
+            row_box.append(&check);
+
+            let count_label = gtk::Label::new(Some(&count.to_string()));
+            count_label.add_css_class("dim-label");
+            count_label.add_css_class("numeric");
+            row_box.append(&count_label);
+
+            let rename_button = gtk::Button::from_icon_name("document-edit-symbolic");
+            rename_button.set_tooltip_text(Some("Rename or Merge Tag"));
+            rename_button.add_css_class("flat");
+            rename_button.connect_clicked(glib::clone!(
+                #[weak(rename_to = imp)]
+                self,
+                move |_| {
+                    imp.show_rename_tag_dialog(&name);
+                }
+            ));
+            row_box.append(&rename_button);
+
+            let row = gtk::ListBoxRow::new();
+            row.set_activatable(false);
+            row.set_child(Some(&row_box));
+            self.tags_list.append(&row);
+        }
+
+        *self.tag_index.borrow_mut() = index;
+        self.update_tag_notes();
+    }
+
+    /// List the notes that have every selected tag
+    fn update_tag_notes(&self) {
+        let selected = self.selected_tags.borrow().clone();
+        let index = self.tag_index.borrow();
+        let notes = index.notes_with_tags(&selected);
+
+        if selected.is_empty() {
+            self.tag_notes_heading.set_text("All Notes");
+        } else {
+            let tags: Vec<String> = selected.iter().map(|key| format!("#{}", key)).collect();
+            self.tag_notes_heading
+                .set_text(&format!("Tagged {}", tags.join(" ")));
+        }
+
+        self.tag_notes_list.remove_all();
+        for note in &notes {
+            let label = gtk::Label::new(Some(&note.title));
+            label.set_xalign(0.0);
+            label.set_ellipsize(gtk::pango::EllipsizeMode::End);
+
+            let row = gtk::ListBoxRow::new();
+            row.set_child(Some(&label));
+            row.set_tooltip_text(Some(&note.path.to_string_lossy()));
+            self.tag_notes_list.append(&row);
+        }
+        *self.tag_notes.borrow_mut() = notes.iter().map(|note| note.path.clone()).collect();
+    }
+
+    pub fn show_rename_tag_dialog(&self, tag: &str) {
+        let entry = gtk::Entry::new();
+        entry.set_text(tag);
+        entry.set_activates_default(true);
+
+        let dialog = adw::AlertDialog::new(
+            Some("Rename Tag"),
+            Some(&format!("Every note tagged #{} will be updated", tag)),
+        );
+        dialog.set_extra_child(Some(&entry));
+        dialog.add_responses(&[("cancel", "Cancel"), ("rename", "Rename")]);
+        dialog.set_response_appearance("rename", adw::ResponseAppearance::Suggested);
+        dialog.set_default_response(Some("rename"));
+        dialog.set_close_response("cancel");
+
+        // Renaming onto an existing tag merges the two
+        let old = tag.to_string();
+        entry.connect_changed(glib::clone!(
+            #[weak(rename_to = imp)]
+            self,
+            #[weak]
+            dialog,
+            move |entry| {
+                let new = entry.text().trim().trim_start_matches('#').to_string();
+                let valid = tags::is_valid_tag(&new) && new != old;
+                dialog.set_response_enabled("rename", valid);
+                let merging = valid
+                    && new.to_lowercase() != old.to_lowercase()
+                    && imp.tag_index.borrow().contains(&new);
+                dialog.set_body(&if merging {
+                    format!("#{} will be merged into the existing tag #{}", old, new)
+                } else {
+                    format!("Every note tagged #{} will be updated", old)
+                });
+            }
+        ));
+        dialog.set_response_enabled("rename", false);
+
+        let old = tag.to_string();
+        dialog.connect_response(
+            None,
+            glib::clone!(
+                #[weak(rename_to = imp)]
+                self,
+                move |_, response| {
+                    if response == "rename" {
+                        let new = entry.text().trim().trim_start_matches('#').to_string();
+                        imp.rename_tag(&old, &new);
+                    }
+                }
+            ),
+        );
+        dialog.present(Some(&*self.obj()));
+    }
+
+    /// Rename or merge a tag across the notes folder, rewriting every file that uses it
+    pub fn rename_tag(&self, old: &str, new: &str) {
+        if !tags::is_valid_tag(new) {
+            eprintln!("Invalid tag name: {}", new);
+            return;
+        }
+        // Write pending edits first so the open note is renamed too
+        self.save_document();
+
+        match tags::rename_tag(&self.notes_dir(), old, new) {
+            Ok(changed) => {
+                println!("Renamed #{} to #{} in {} notes", old, new, changed.len());
+                let current = self.current_file.borrow().clone();
+                if current.is_some_and(|path| changed.contains(&path)) {
+                    self.reload_file();
+                }
+            }
+            Err(e) => eprintln!("Failed to rename tag #{}: {}", old, e),
+        }
+
+        let old_key = old.to_lowercase();
+        for key in self.selected_tags.borrow_mut().iter_mut() {
+            if *key == old_key {
+                *key = new.to_lowercase();
+            }
+        }
+        self.update_tag_index();
+    }
+
+    // Notes search
+
+    /// Show the notes search in the sidebar and focus the search entry
+    pub fn show_note_search(&self) {
+        if !self.nav_toggle.is_active() {
+            self.nav_toggle.set_active(true);
+            self.on_nav_toggle_clicked();
+        }
+        self.sidebar_stack.set_visible_child_name("search");
+        self.notes_search_entry.grab_focus();
+    }
+
+    /// Search the notes folder, re-indexing notes changed since the last search
+    fn run_note_search(&self) {
+        let query = self.notes_search_entry.text().to_string();
+        self.search_results_list.remove_all();
+        if query.trim().is_empty() {
+            self.search_status_label.set_text("");
+            self.search_results.borrow_mut().clear();
+            return;
+        }
+
+        let dir = self.notes_dir();
+        self.monitor_notes_dir(&dir);
+        let results = {
+            let mut index = self.search_index.borrow_mut();
+            let index = index.get_or_insert_with(|| SearchIndex::load(&self.config_dir));
+            index.refresh(&dir);
+            index.search(&query)
+        };
+
+        self.search_status_label.set_text(&match results.len() {
+            0 => "No matching notes".to_string(),
+            1 => "1 note".to_string(),
+            count => format!("{} notes", count),
+        });
+
+        for result in &results {
+            let row_box = gtk::Box::new(gtk::Orientation::Vertical, 2);
+            row_box.set_margin_top(6);
+            row_box.set_margin_bottom(6);
+
+            let title_label = gtk::Label::new(Some(&result.title));
+            title_label.set_xalign(0.0);
+            title_label.set_ellipsize(gtk::pango::EllipsizeMode::End);
+            title_label.add_css_class("heading");
+            row_box.append(&title_label);
+
+            let snippet_label = gtk::Label::new(None);
+            snippet_label.set_markup(&result.snippet);
+            snippet_label.set_xalign(0.0);
+            snippet_label.set_wrap(true);
+            snippet_label.set_lines(3);
+            snippet_label.set_ellipsize(gtk::pango::EllipsizeMode::End);
+            snippet_label.add_css_class("caption");
+            row_box.append(&snippet_label);
+
+            let row = gtk::ListBoxRow::new();
+            row.set_child(Some(&row_box));
+            row.set_tooltip_text(Some(&result.path.to_string_lossy()));
+            self.search_results_list.append(&row);
+        }
+        *self.search_results.borrow_mut() = results;
+    }
+
+    fn open_search_result(&self, index: usize) {
+        let Some(result) = self.search_results.borrow().get(index).cloned() else {
+            return;
+        };
+        if self.current_file.borrow().as_ref() != Some(&result.path) {
+            self.open_file(&result.path);
+        }
+        self.go_to_line(result.line as i32);
+        self.text_view.grab_focus();
+    }
+
+    /// Watch the notes folder so open searches pick up changed files
+    fn monitor_notes_dir(&self, dir: &Path) {
+        if self
+            .notes_monitor
+            .borrow()
+            .as_ref()
+            .is_some_and(|(watched, _)| watched == dir)
+        {
+            return;
+        }
+
+        let monitor = match gio::File::for_path(dir)
+            .monitor_directory(gio::FileMonitorFlags::NONE, gio::Cancellable::NONE)
+        {
+            Ok(monitor) => monitor,
+            Err(e) => {
+                eprintln!("Failed to monitor notes folder {:?}: {}", dir, e);
+                return;
+            }
+        };
+        monitor.connect_changed(glib::clone!(
+            #[weak(rename_to = imp)]
+            self,
+            move |_, _, _, _| {
+                imp.schedule_search_refresh();
+            }
+        ));
+        *self.notes_monitor.borrow_mut() = Some((dir.to_path_buf(), monitor));
+    }
+
+    /// Re-run the search once a burst of file changes has settled
+    fn schedule_search_refresh(&self) {
+        if self.search_refresh_pending.replace(true) {
+            return;
+        }
+        let imp_weak = self.obj().downgrade();
+        glib::timeout_add_local_once(Duration::from_secs(1), move || {
+            if let Some(widget) = imp_weak.upgrade() {
+                let imp = widget.imp();
+                *imp.search_refresh_pending.borrow_mut() = false;
+                if imp.sidebar_stack.visible_child_name().as_deref() == Some("search") {
+                    imp.run_note_search();
+                }
+            }
+        });
+    }
+
+    // Git
+
+    /// Find the repository of the open file and what it has in HEAD
+    fn load_git_state(&self) {
+        let path = self.current_file.borrow().clone();
+        let repo = path.as_deref().and_then(git::repository);
+        let head_text = match (&repo, &path) {
+            (Some(repo), Some(path)) => Some(git::head_text(repo, path).unwrap_or_default()),
+            _ => None,
+        };
+
+        match repo.as_ref().and_then(git::branch_name) {
+            Some(branch) => self.git_button.set_label(&branch),
+            None => self.git_button.set_label("Git"),
+        }
+        self.git_button.set_visible(repo.is_some());
+        if let Some(gutter) = self.text_view.gutter(gtk::TextWindowType::Left) {
+            gutter.set_visible(repo.is_some());
+        }
+
+        *self.git_repo.borrow_mut() = repo;
+        *self.git_head_text.borrow_mut() = head_text;
+        self.update_git_changes();
+    }
+
+    /// Update the gutter shortly after typing stops, as diffing the whole
+    /// file on every keystroke is too slow for long notes
+    fn schedule_git_changes_update(&self) {
+        if self.git_head_text.borrow().is_none() || self.git_changes_pending.replace(true) {
+            return;
+        }
+        let imp_weak = self.obj().downgrade();
+        glib::timeout_add_local_once(Duration::from_millis(300), move || {
+            if let Some(widget) = imp_weak.upgrade() {
+                let imp = widget.imp();
+                *imp.git_changes_pending.borrow_mut() = false;
+                imp.update_git_changes();
+            }
+        });
+    }
+
+    /// Compare the buffer with HEAD and redraw the gutter
+    fn update_git_changes(&self) {
+        let changes = match self.git_head_text.borrow().as_deref() {
+            Some(head) => {
+                let buffer = self.text_view.buffer();
+                let text = buffer.text(&buffer.start_iter(), &buffer.end_iter(), false);
+                git::line_changes(head, &text)
+            }
+            None => Vec::new(),
+        };
+        *self.git_changes.borrow_mut() = changes;
+        if let Some(gutter) = self.text_view.gutter(gtk::TextWindowType::Left) {
+            gutter.queue_draw();
+        }
+    }
+
+    fn draw_git_gutter(&self, cr: &gtk::cairo::Context, width: i32) {
+        let buffer = self.text_view.buffer();
+        let width = width as f64;
+        for &(line, change) in self.git_changes.borrow().iter() {
+            // Deletions at the very end are marked below the last line
+            let (y, height) = match buffer.iter_at_line(line as i32) {
+                Some(iter) => self.text_view.line_yrange(&iter),
+                None => {
+                    let (y, height) = self.text_view.line_yrange(&buffer.end_iter());
+                    (y + height, 0)
+                }
+            };
+            let (_, y) = self
+                .text_view
+                .buffer_to_window_coords(gtk::TextWindowType::Left, 0, y);
+            let (y, height) = (y as f64, height as f64);
+
+            match change {
+                LineChange::Added => cr.set_source_rgb(0.18, 0.76, 0.49),
+                LineChange::Modified => cr.set_source_rgb(0.21, 0.52, 0.89),
+                LineChange::RemovedAbove => cr.set_source_rgb(0.88, 0.11, 0.14),
+            }
+            if change == LineChange::RemovedAbove {
+                cr.move_to(0.0, y - width);
+                cr.line_to(width, y);
+                cr.line_to(0.0, y + width);
+                cr.close_path();
+            } else {
+                cr.rectangle(0.0, y, width, height);
+            }
+            let _ = cr.fill();
+        }
+    }
+
+    /// Show the changes to the open file since HEAD as a unified diff
+    pub fn show_git_diff(&self) {
+        let Some(head) = self.git_head_text.borrow().clone() else {
+            return;
+        };
+        let buffer = self.text_view.buffer();
+        let text = buffer
+            .text(&buffer.start_iter(), &buffer.end_iter(), false)
+            .to_string();
+        let old_lines: Vec<&str> = head.lines().collect();
+        let new_lines: Vec<&str> = text.lines().collect();
+        let lines = diff::line_diff(&head, &text);
+
+        // Keep three lines of context around each change
+        let changed: Vec<bool> = lines
+            .iter()
+            .map(|line| !matches!(line, DiffLine::Same(..)))
+            .collect();
+        let shown = |index: usize| {
+            let start = index.saturating_sub(3);
+            let end = (index + 4).min(changed.len());
+            changed[start..end].contains(&true)
+        };
+
+        let view = gtk::TextView::new();
+        view.set_monospace(true);
+        view.set_editable(false);
+        view.set_left_margin(8);
+        view.set_right_margin(8);
+        view.set_top_margin(8);
+        view.set_bottom_margin(8);
+        let diff_buffer = view.buffer();
+        diff_buffer.create_tag(
+            Some("removed"),
+            &[("paragraph-background", &"rgba(224, 27, 36, 0.2)")],
+        );
+        diff_buffer.create_tag(
+            Some("added"),
+            &[("paragraph-background", &"rgba(46, 194, 126, 0.2)")],
+        );
+        diff_buffer.create_tag(Some("skipped"), &[("foreground", &"#9a9996")]);
+
+        let mut end = diff_buffer.end_iter();
+        let mut skipping = false;
+        for (index, line) in lines.iter().enumerate() {
+            if !shown(index) {
+                if !skipping {
+                    diff_buffer.insert_with_tags_by_name(&mut end, "⋯\n", &["skipped"]);
+                    skipping = true;
+                }
+                continue;
+            }
+            skipping = false;
+            match *line {
+                DiffLine::Same(_, new) => {
+                    diff_buffer.insert(&mut end, &format!("  {}\n", new_lines[new]))
+                }
+                DiffLine::Removed(old) => diff_buffer.insert_with_tags_by_name(
+                    &mut end,
+                    &format!("- {}\n", old_lines[old]),
+                    &["removed"],
+                ),
+                DiffLine::Added(new) => diff_buffer.insert_with_tags_by_name(
+                    &mut end,
+                    &format!("+ {}\n", new_lines[new]),
+                    &["added"],
+                ),
+            }
+        }
+        if !changed.contains(&true) {
+            diff_buffer.set_text("No changes since the last commit");
+        }
+
+        let scrolled = gtk::ScrolledWindow::new();
+        scrolled.set_child(Some(&view));
+        scrolled.set_vexpand(true);
+
+        let toolbar_view = adw::ToolbarView::new();
+        toolbar_view.add_top_bar(&adw::HeaderBar::new());
+        toolbar_view.set_content(Some(&scrolled));
+
+        let name = self
+            .current_file
+            .borrow()
+            .as_ref()
+            .and_then(|path| path.file_name())
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let dialog = adw::Dialog::new();
+        dialog.set_title(&format!("Changes to {} Since HEAD", name));
+        dialog.set_content_width(800);
+        dialog.set_content_height(600);
+        dialog.set_child(Some(&toolbar_view));
+        dialog.present(Some(&*self.obj()));
+    }
+
+    /// Save the open file and add it to the index
+    pub fn stage_current_file(&self) -> bool {
+        let Some(path) = self.current_file.borrow().clone() else {
+            return false;
+        };
+        // Staging the version on disk would leave out the unsaved edits
+        if !self.save_document() {
+            eprintln!("Not staging {:?}: it could not be saved", path);
+            return false;
+        }
+        let result = match self.git_repo.borrow().as_ref() {
+            Some(repo) => git::stage(repo, &path),
+            None => return false,
+        };
+        match result {
+            Ok(()) => {
+                println!("Staged {:?}", path);
+                true
+            }
+            Err(e) => {
+                self.show_git_error("Could Not Stage File", &e);
+                false
+            }
+        }
+    }
+
+    pub fn show_commit_dialog(&self) {
+        if self.git_repo.borrow().is_none() {
+            return;
+        }
+        let message_view = gtk::TextView::new();
+        message_view.set_wrap_mode(gtk::WrapMode::WordChar);
+        message_view.set_top_margin(6);
+        message_view.set_bottom_margin(6);
+        message_view.set_left_margin(6);
+        message_view.set_right_margin(6);
+        let message_frame = gtk::Frame::new(None);
+        message_frame.set_child(Some(&message_view));
+        message_frame.set_size_request(360, 120);
+
+        let dialog = adw::AlertDialog::new(
+            Some("Commit"),
+            Some("The open file is saved and staged before committing"),
+        );
+        dialog.set_extra_child(Some(&message_frame));
+        dialog.add_responses(&[("cancel", "Cancel"), ("commit", "Commit")]);
+        dialog.set_response_appearance("commit", adw::ResponseAppearance::Suggested);
+        dialog.set_default_response(Some("commit"));
+        dialog.set_close_response("cancel");
+        dialog.set_response_enabled("commit", false);
+
+        message_view.buffer().connect_changed(glib::clone!(
+            #[weak]
+            dialog,
+            move |buffer| {
+                let message = buffer.text(&buffer.start_iter(), &buffer.end_iter(), false);
+                dialog.set_response_enabled("commit", !message.trim().is_empty());
+            }
+        ));
+
+        dialog.connect_response(
+            None,
+            glib::clone!(
+                #[weak(rename_to = imp)]
+                self,
+                move |_, response| {
+                    if response == "commit" {
+                        let buffer = message_view.buffer();
+                        let message = buffer.text(&buffer.start_iter(), &buffer.end_iter(), false);
+                        imp.commit(message.trim());
+                    }
+                }
+            ),
+        );
+        dialog.present(Some(&*self.obj()));
+    }
+
+    /// Stage the open file and commit it
+    pub fn commit(&self, message: &str) {
+        if !self.stage_current_file() {
+            return;
+        }
+        let Some(path) = self.current_file.borrow().clone() else {
+            return;
+        };
+        let result = match self.git_repo.borrow().as_ref() {
+            Some(repo) => git::commit(repo, &path, message),
+            None => return,
+        };
+        match result {
+            Ok(id) => {
+                println!("Committed {}", id);
+                self.load_git_state();
+            }
+            Err(e) => self.show_git_error("Could Not Commit", &e),
+        }
+    }
+
+    fn show_git_error(&self, heading: &str, error: &git2::Error) {
+        eprintln!("{}: {}", heading, error);
+        let dialog = adw::AlertDialog::new(Some(heading), Some(error.message()));
+        dialog.add_response("close", "Close");
+        dialog.present(Some(&*self.obj()));
+    }
+
+    // Table of contents
+
+    /// Insert a table of contents at the cursor, or refresh the existing one
+    pub fn insert_table_of_contents(&self) {
+        let buffer = self.text_view.buffer();
+        let text = buffer.text(&buffer.start_iter(), &buffer.end_iter(), false);
+        if let Some(range) = toc::find(&text) {
+            self.update_table_of_contents();
+            let offset = text[..range.start].chars().count() as i32;
+            buffer.place_cursor(&self.iter_at_text_offset(offset));
+            self.text_view
+                .scroll_to_mark(&buffer.get_insert(), 0.0, true, 0.0, 0.3);
+            return;
+        }
+
+        let cursor = buffer.iter_at_mark(&buffer.get_insert());
+        let line_text = self.line_text(cursor.line()).unwrap_or_default();
+        let mut toc = toc::generate(&text);
+        toc.push('\n');
+
+        // Like tables, the list needs a blank line before it
+        buffer.begin_user_action();
+        if !line_text.trim().is_empty() {
+            let mut end = cursor;
+            if !end.ends_line() {
+                end.forward_to_line_end();
+            }
+            buffer.place_cursor(&end);
+            toc.insert_str(0, "\n\n");
+        }
+        buffer.insert_at_cursor(&toc);
+        buffer.end_user_action();
+        self.text_view.grab_focus();
+    }
+
+    /// Regenerate the table of contents when the headings have changed since
+    /// it was written
+    fn update_table_of_contents(&self) {
+        let buffer = self.text_view.buffer();
+        let text = buffer.text(&buffer.start_iter(), &buffer.end_iter(), false);
+        let Some((range, toc)) = toc::update(&text) else {
+            return;
+        };
+        let start = text[..range.start].chars().count() as i32;
+        let end = start + text[range].chars().count() as i32;
+
+        buffer.begin_user_action();
+        let mut start = self.iter_at_text_offset(start);
+        let mut end = self.iter_at_text_offset(end);
+        buffer.delete(&mut start, &mut end);
+        buffer.insert(&mut start, &toc);
+        buffer.end_user_action();
+    }
+
+    // Footnotes
+
+    /// Insert the next numbered footnote reference at the cursor and its
+    /// definition at the end of the document, then move to the definition
+    pub fn insert_footnote(&self) {
+        let buffer = self.text_view.buffer();
+        let text = buffer.text(&buffer.start_iter(), &buffer.end_iter(), false);
+        let footnotes = Footnotes::scan(&text);
+        let number = footnotes.next_number();
+
+        // Definitions follow the ones already at the end, or a blank line
+        let body = text.trim_end();
+        let after_definitions = footnotes
+            .definitions
+            .last()
+            .is_some_and(|d| text[d.range.end..].trim().is_empty());
+        let newlines: usize = match () {
+            _ if body.is_empty() => 0,
+            _ if after_definitions => 1,
+            _ => 2,
+        };
+        let existing = text[body.len()..].matches('\n').count();
+        let separator = "\n".repeat(newlines.saturating_sub(existing));
+
+        buffer.begin_user_action();
+        let mut cursor = match buffer.selection_bounds() {
+            Some((_, end)) => end,
+            None => buffer.iter_at_mark(&buffer.get_insert()),
+        };
+        buffer.insert(&mut cursor, &format!("[^{}]", number));
+        let mut end = buffer.end_iter();
+        buffer.insert(&mut end, &format!("{}[^{}]: ", separator, number));
+        buffer.end_user_action();
+
+        buffer.place_cursor(&buffer.end_iter());
+        self.text_view.scroll_mark_onscreen(&buffer.get_insert());
+        self.text_view.grab_focus();
+    }
+
+    /// Go from a footnote reference to its definition, or from a definition to
+    /// its first reference
+    pub fn jump_to_footnote(&self) {
+        let buffer = self.text_view.buffer();
+        let text = buffer.text(&buffer.start_iter(), &buffer.end_iter(), false);
+        let cursor = self.text_offset(&buffer.iter_at_mark(&buffer.get_insert())) as usize;
+        let cursor = text
+            .char_indices()
+            .nth(cursor)
+            .map_or(text.len(), |(i, _)| i);
+
+        let footnotes = Footnotes::scan(&text);
+        let target = match footnotes.reference_at(cursor) {
+            Some(reference) => footnotes
+                .definition(&reference.label)
+                .map(|definition| definition.marker.end),
+            None => footnotes
+                .definition_at(cursor)
+                .and_then(|definition| {
+                    footnotes
+                        .references
+                        .iter()
+                        .find(|reference| reference.label == definition.label)
+                })
+                .map(|reference| reference.range.end),
+        };
+        let Some(target) = target else {
+            eprintln!("No matching footnote at the cursor");
+            return;
+        };
+
+        let mut iter = self.iter_at_text_offset(text[..target].chars().count() as i32);
+        if iter.char() == ' ' {
+            iter.forward_char();
+        }
+        buffer.place_cursor(&iter);
+        self.text_view
+            .scroll_to_mark(&buffer.get_insert(), 0.0, true, 0.0, 0.3);
+        self.text_view.grab_focus();
+    }
+
+    /// Number the footnotes in order of appearance. Only the labels that
+    /// change are replaced, so marks and image previews elsewhere stay put.
+    pub fn renumber_footnotes(&self) {
+        let buffer = self.text_view.buffer();
+        let text = buffer.text(&buffer.start_iter(), &buffer.end_iter(), false);
+        let edits = footnotes::renumber(&text);
+        if edits.is_empty() {
+            return;
+        }
+
+        // Edits come last first, so the offsets of the rest stay valid
+        buffer.begin_user_action();
+        for (range, replacement) in edits {
+            let start = text[..range.start].chars().count() as i32;
+            let end = start + text[range].chars().count() as i32;
+            let mut start = self.iter_at_text_offset(start);
+            let mut end = self.iter_at_text_offset(end);
+            buffer.delete(&mut start, &mut end);
+            buffer.insert(&mut start, &replacement);
+        }
+        buffer.end_user_action();
+    }
+
+    /// Underline references without a definition and definitions nothing
+    /// refers to, and count them in the status bar
+    fn check_footnotes(&self) {
+        let buffer = self.text_view.buffer();
+        let text = buffer.text(&buffer.start_iter(), &buffer.end_iter(), false);
+        buffer.remove_tag_by_name(
+            FOOTNOTE_ORPHAN_TAG,
+            &buffer.start_iter(),
+            &buffer.end_iter(),
+        );
+
+        let footnotes = Footnotes::scan(&text);
+        let references = footnotes.orphaned_references();
+        let definitions = footnotes.orphaned_definitions();
+        let ranges = references
+            .iter()
+            .map(|reference| reference.range.clone())
+            .chain(definitions.iter().map(|d| d.marker.clone()));
+        for range in ranges {
+            let start = text[..range.start].chars().count() as i32;
+            let end = start + text[range].chars().count() as i32;
+            buffer.apply_tag_by_name(
+                FOOTNOTE_ORPHAN_TAG,
+                &self.iter_at_text_offset(start),
+                &self.iter_at_text_offset(end),
+            );
+        }
+
+        let mut problems: Vec<String> = Vec::new();
+        let messages = references
+            .iter()
+            .map(|reference| format!("[^{}] has no definition", reference.label))
+            .chain(
+                definitions
+                    .iter()
+                    .map(|definition| format!("[^{}] is never referenced", definition.label)),
+            );
+        for message in messages {
+            if !problems.contains(&message) {
+                problems.push(message);
+            }
+        }
+        self.footnote_status
+            .set_text(&format!("Orphaned footnotes: {}", problems.len()));
+        self.footnote_status
+            .set_tooltip_text(Some(&problems.join("\n")));
+        self.footnote_status.set_visible(!problems.is_empty());
+    }
+
+    // Image previews
+
+    /// Character offset in the buffer's text of a buffer position
+    fn text_offset(&self, iter: &gtk::TextIter) -> i32 {
+        let offset = iter.offset();
+        let anchors_before = self
+            .image_anchor_offsets()
+            .into_iter()
+            .filter(|anchor| *anchor < offset)
+            .count();
+        offset - anchors_before as i32
+    }
+
+    /// Buffer position of a character offset in the buffer's text
+    fn iter_at_text_offset(&self, offset: i32) -> gtk::TextIter {
+        let mut offset = offset;
+        for anchor in self.image_anchor_offsets() {
+            if anchor <= offset {
+                offset += 1;
+            }
+        }
+        self.text_view.buffer().iter_at_offset(offset)
+    }
+
+    /// Buffer offsets of the preview anchors, in order
+    fn image_anchor_offsets(&self) -> Vec<i32> {
+        let buffer = self.text_view.buffer();
+        let mut offsets: Vec<i32> = self
+            .image_previews
+            .borrow()
+            .iter()
+            .filter(|(anchor, _)| !anchor.is_deleted())
+            .map(|(anchor, _)| buffer.iter_at_child_anchor(anchor).offset())
+            .collect();
+        offsets.sort_unstable();
+        offsets
+    }
+
+    /// Show previews if they were turned on for the document just loaded
+    fn load_image_preview_state(&self) {
+        let key = self.document_goal_key();
+        let enabled = self.settings.as_ref().is_some_and(|settings| {
+            settings
+                .strv("image-preview-documents")
+                .iter()
+                .any(|document| document.as_str() == key)
+        });
+        *self.image_previews_enabled.borrow_mut() = enabled;
+        self.image_preview_button.set_active(enabled);
+        self.update_image_previews();
+    }
+
+    /// Turn previews on or off for the open document and remember it
+    fn set_image_previews(&self, enabled: bool) {
+        if self.image_previews_enabled.replace(enabled) == enabled {
+            return;
+        }
+        if let Some(ref settings) = self.settings {
+            let key = self.document_goal_key();
+            let current = settings.strv("image-preview-documents");
+            let mut documents: Vec<&str> = current
+                .iter()
+                .map(|document| document.as_str())
+                .filter(|document| *document != key)
+                .collect();
+            if enabled {
+                documents.push(&key);
+            }
+            if let Err(e) = settings.set_strv("image-preview-documents", documents) {
+                eprintln!("Failed to save image preview setting: {}", e);
+            }
+        }
+        let buffer = self.text_view.buffer();
+        buffer.begin_irreversible_action();
+        self.update_image_previews();
+        buffer.end_irreversible_action();
+    }
+
+    fn schedule_image_preview_update(&self) {
+        if !*self.image_previews_enabled.borrow() && self.image_previews.borrow().is_empty() {
+            return;
+        }
+        if self.image_preview_pending.replace(true) {
+            return;
+        }
+        let imp_weak = self.obj().downgrade();
+        glib::timeout_add_local_once(Duration::from_millis(500), move || {
+            if let Some(widget) = imp_weak.upgrade() {
+                let imp = widget.imp();
+                *imp.image_preview_pending.borrow_mut() = false;
+                imp.update_image_previews();
+            }
+        });
+    }
+
+    /// Put a thumbnail at the end of each image's line, keeping the previews
+    /// whose line and file haven't changed
+    fn update_image_previews(&self) {
+        let buffer = self.text_view.buffer();
+        let mut wanted: Vec<(usize, PathBuf)> = Vec::new();
+        if *self.image_previews_enabled.borrow() {
+            let text = buffer.text(&buffer.start_iter(), &buffer.end_iter(), false);
+            let dir = self
+                .current_file
+                .borrow()
+                .as_ref()
+                .and_then(|path| path.parent())
+                .map(Path::to_path_buf);
+            wanted = images::image_links(&text)
+                .into_iter()
+                .filter_map(|link| {
+                    Some((link.line, images::resolve(&link.target, dir.as_deref())?))
+                })
+                .collect();
+        }
+
+        let modified = buffer.is_modified();
+        *self.updating_image_previews.borrow_mut() = true;
+        // Previews aren't part of the text, so undo must never bring back or
+        // remove their anchors
+        buffer.begin_irreversible_action();
+        let mut previews = Vec::new();
+        for (anchor, path) in self.image_previews.take() {
+            if anchor.is_deleted() {
+                continue;
+            }
+            let start = buffer.iter_at_child_anchor(&anchor);
+            // Only other previews may follow it on its line
+            let mut after = start;
+            while after.child_anchor().is_some() {
+                after.forward_char();
+            }
+            let found = wanted
+                .iter()
+                .position(|(line, file)| *line == start.line() as usize && *file == path);
+            match found {
+                Some(index) if after.ends_line() => {
+                    wanted.remove(index);
+                    previews.push((anchor, path));
+                }
+                _ => {
+                    let mut start = start;
+                    let mut end = start;
+                    end.forward_char();
+                    buffer.delete(&mut start, &mut end);
+                }
+            }
+        }
+
+        for (line, path) in wanted {
+            let Some(mut iter) = buffer.iter_at_line(line as i32) else {
+                continue;
+            };
+            if !iter.ends_line() {
+                iter.forward_to_line_end();
+            }
+            let anchor = buffer.create_child_anchor(&mut iter);
+            self.text_view
+                .add_child_at_anchor(&self.image_preview_widget(&path), &anchor);
+            previews.push((anchor, path));
+        }
+        buffer.end_irreversible_action();
+
+        buffer.remove_tag_by_name(IMAGE_LINE_TAG, &buffer.start_iter(), &buffer.end_iter());
+        for (anchor, _) in &previews {
+            let mut start = buffer.iter_at_child_anchor(anchor);
+            let mut end = start;
+            start.set_line_offset(0);
+            end.forward_to_line_end();
+            buffer.apply_tag_by_name(IMAGE_LINE_TAG, &start, &end);
+        }
+        *self.image_previews.borrow_mut() = previews;
+        buffer.set_modified(modified);
+        *self.updating_image_previews.borrow_mut() = false;
+    }
+
+    /// A thumbnail of the image, or a note when it can't be loaded
+    fn image_preview_widget(&self, path: &Path) -> gtk::Widget {
+        // As wide as the visible text, so the preview wraps below the line
+        let available = self.scrolled_window.width()
+            - self.text_view.left_margin()
+            - self.text_view.right_margin();
+        let width = if available > 100 { available - 24 } else { 600 };
+
+        let child: gtk::Widget = match gtk::gdk::Texture::from_filename(path) {
+            Ok(texture) => {
+                let (image_width, image_height) = (texture.width() as f64, texture.height() as f64);
+                let scale = (IMAGE_PREVIEW_HEIGHT / image_height)
+                    .min(width as f64 / image_width)
+                    .min(1.0);
+                let picture = gtk::Picture::for_paintable(&texture);
+                let (width, height) = (image_width * scale, image_height * scale);
+                picture.set_size_request(width as i32, height as i32);
+                picture.set_halign(gtk::Align::Start);
+                picture.upcast()
+            }
+            Err(e) => {
+                eprintln!("Failed to load image {:?}: {}", path, e);
+                let label = gtk::Label::new(Some(&format!("Can't show {}", path.display())));
+                label.set_xalign(0.0);
+                label.add_css_class("dim-label");
+                label.upcast()
+            }
+        };
+        child.set_tooltip_text(Some(&path.to_string_lossy()));
+
+        let container = gtk::Box::new(gtk::Orientation::Vertical, 0);
+        container.set_size_request(width, -1);
+        container.set_margin_top(6);
+        container.set_margin_bottom(6);
+        container.append(&child);
+        container.upcast()
+    }
+
+    /// Keep the cursor in front of the previews at the end of a line, so
+    /// typing and Backspace there act on the text
+    fn keep_cursor_before_image_previews(&self) {
+        let buffer = self.text_view.buffer();
+        if buffer.has_selection() || self.image_previews.borrow().is_empty() {
+            return;
+        }
+        let cursor = buffer.iter_at_mark(&buffer.get_insert());
+        let mut before = cursor;
+        loop {
+            let mut previous = before;
+            if !previous.backward_char() || previous.child_anchor().is_none() {
+                break;
+            }
+            before = previous;
+        }
+        if before != cursor {
+            buffer.place_cursor(&before);
+        }
+    }
+
+    /// The end of the cursor's line when only previews are between them
+    fn image_previews_after_cursor(&self) -> Option<gtk::TextIter> {
+        let buffer = self.text_view.buffer();
+        if buffer.has_selection() {
+            return None;
+        }
+        let mut iter = buffer.iter_at_mark(&buffer.get_insert());
+        if iter.child_anchor().is_none() {
+            return None;
+        }
+        while iter.child_anchor().is_some() {
+            iter.forward_char();
+        }
+        iter.ends_line().then_some(iter)
+    }
+
+    /// Right arrow in front of previews goes on to the next line
+    fn skip_image_previews(&self) -> bool {
+        let Some(mut iter) = self.image_previews_after_cursor() else {
+            return false;
+        };
+        if iter.forward_char() {
+            self.text_view.buffer().place_cursor(&iter);
+        }
+        true
+    }
+
+    /// Delete in front of previews joins the next line instead of removing
+    /// them; they move to the end of the joined line
+    fn delete_after_image_previews(&self) -> bool {
+        let Some(mut line_end) = self.image_previews_after_cursor() else {
+            return false;
+        };
+        let mut next_line = line_end;
+        if next_line.forward_char() {
+            let buffer = self.text_view.buffer();
+            buffer.begin_user_action();
+            buffer.delete(&mut line_end, &mut next_line);
+            buffer.end_user_action();
+        }
+        true
+    }
+
+    // Printing
+
+    /// Preview the document as it will print, rendered or as numbered
+    /// source, with page setup and printing from the dialog
+    pub fn show_print_dialog(&self) {
+        let page = WebView::new();
+        page.set_vexpand(true);
+        page.connect_context_menu(|_, _, _| true);
+
+        let mode = gtk::DropDown::from_strings(&["Rendered Document", "Source with Line Numbers"]);
+        mode.set_tooltip_text(Some("What to Print"));
+        let page_setup_button = gtk::Button::with_label("Page Setup…");
+        let print_button = gtk::Button::with_label("Print…");
+        print_button.add_css_class("suggested-action");
+        let header = adw::HeaderBar::new();
+        header.pack_start(&mode);
+        header.pack_end(&print_button);
+        header.pack_end(&page_setup_button);
+
+        let toolbar_view = adw::ToolbarView::new();
+        toolbar_view.add_top_bar(&header);
+        toolbar_view.set_content(Some(&page));
+
+        let dialog = adw::Dialog::new();
+        dialog.set_title("Print Preview");
+        dialog.set_content_width(800);
+        dialog.set_content_height(900);
+        dialog.set_child(Some(&toolbar_view));
+
+        page.load_html(&self.print_html(false), None);
+        mode.connect_selected_notify(glib::clone!(
+            #[weak(rename_to = imp)]
+            self,
+            #[weak]
+            page,
+            move |mode| page.load_html(&imp.print_html(mode.selected() == 1), None)
+        ));
+        page_setup_button.connect_clicked(glib::clone!(
+            #[weak(rename_to = imp)]
+            self,
+            move |_| imp.show_page_setup()
+        ));
+        print_button.connect_clicked(glib::clone!(
+            #[weak(rename_to = imp)]
+            self,
+            #[weak]
+            dialog,
+            #[weak]
+            page,
+            move |_| {
+                if imp.print_page(&page) {
+                    dialog.close();
+                }
+            }
+        ));
+
+        dialog.present(Some(&*self.obj()));
+    }
+
+    /// The page to print: the rendered document in the light preview theme,
+    /// or its source with line numbers
+    fn print_html(&self, source: bool) -> String {
+        let buffer = self.text_view.buffer();
+        let text = buffer.text(&buffer.start_iter(), &buffer.end_iter(), false);
+        if source {
+            let file = self.current_file.borrow();
+            let title = match file.as_ref().and_then(|path| path.file_name()) {
+                Some(name) => name.to_string_lossy(),
+                None => DEFAULT_TITLE.into(),
+            };
+            return print::source_page(&text, &title);
+        }
+        let css = preview::page_css(&self.config_dir, &self.preview_theme.borrow(), false);
+        preview::render_page(&text, &css, false)
+    }
+
+    /// Choose the paper size, orientation and margins used from now on
+    fn show_page_setup(&self) {
+        let settings = print::load_settings(&self.config_dir);
+        let page_setup = print::load_page_setup(&self.config_dir);
+        let window = self.obj().root().and_downcast::<gtk::Window>();
+        let config_dir = self.config_dir.clone();
+        let saved_settings = settings.clone();
+        gtk::print_run_page_setup_dialog_async(
+            window.as_ref(),
+            Some(&page_setup),
+            &settings,
+            move |page_setup| print::save(&config_dir, &saved_settings, page_setup),
+        );
+    }
+
+    /// Print a loaded page through the print dialog, starting from the
+    /// remembered settings and remembering the ones chosen. Returns false
+    /// when the dialog was cancelled.
+    fn print_page(&self, page: &WebView) -> bool {
+        let operation = webkit2gtk::PrintOperation::new(page);
+        operation.set_print_settings(&print::load_settings(&self.config_dir));
+        operation.set_page_setup(&print::load_page_setup(&self.config_dir));
+
+        operation.connect_failed(|_, error| eprintln!("Printing failed: {}", error));
+        operation.connect_finished(glib::clone!(
+            #[weak(rename_to = imp)]
+            self,
+            move |_| {
+                println!("Printing finished");
+                imp.print_job.replace(None);
+            }
+        ));
+
+        let window = self.obj().root().and_downcast::<gtk::Window>();
+        let response = operation.run_dialog(window.as_ref());
+        if response != webkit2gtk::PrintOperationResponse::Print {
+            return false;
+        }
+        let settings = operation.print_settings();
+        let page_setup = operation.page_setup();
+        if let (Some(settings), Some(page_setup)) = (settings, page_setup) {
+            print::save(&self.config_dir, &settings, &page_setup);
+        }
+        self.print_job.replace(Some((operation, page.clone())));
+        true
+    }
+
+    // Table editing
+
+    fn line_text(&self, line: i32) -> Option<String> {
+        let buffer = self.text_view.buffer();
+        let start = buffer.iter_at_line(line)?;
+        let mut end = start;
+        if !end.ends_line() {
+            end.forward_to_line_end();
+        }
+        Some(buffer.text(&start, &end, false).to_string())
+    }
+
+    /// Find the table containing a buffer line
+    fn table_context(&self, line: i32) -> Option<TableContext> {
+        if !table::is_table_line(&self.line_text(line)?) {
+            return None;
+        }
+
+        let is_table_line = |line: i32| {
+            self.line_text(line)
+                .is_some_and(|text| table::is_table_line(&text))
+        };
+        let mut first_line = line;
+        while first_line > 0 && is_table_line(first_line - 1) {
+            first_line -= 1;
+        }
+        let mut last_line = line;
+        while last_line + 1 < self.text_view.buffer().line_count() && is_table_line(last_line + 1) {
+            last_line += 1;
+        }
+
+        let lines: Vec<String> = (first_line..=last_line)
+            .filter_map(|line| self.line_text(line))
+            .collect();
+        let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+        let table = Table::parse(&lines)?;
+
+        Some(TableContext {
+            first_line,
+            last_line,
+            table,
+        })
+    }
+
+    /// The (row, column) of the cursor inside a table
+    fn cursor_table_cell(&self, context: &TableContext) -> (usize, usize) {
+        let buffer = self.text_view.buffer();
+        let cursor = buffer.iter_at_mark(&buffer.get_insert());
+        let line_index = (cursor.line() - context.first_line).max(0) as usize;
+        let row = table::row_for_line(line_index).unwrap_or(0);
+        let column = self
+            .line_text(cursor.line())
+            .map(|text| table::column_at(&text, cursor.line_offset() as usize))
+            .unwrap_or(0)
+            .min(context.table.column_count().saturating_sub(1));
+        (row, column)
+    }
+
+    /// Replace a table's lines with the formatted table, returning whether anything changed
+    fn write_table(&self, context: &TableContext, table: &Table) -> bool {
+        let buffer = self.text_view.buffer();
+        let (Some(mut start), Some(mut end)) = (
+            buffer.iter_at_line(context.first_line),
+            buffer.iter_at_line(context.last_line),
+        ) else {
+            return false;
+        };
+        if !end.ends_line() {
+            end.forward_to_line_end();
+        }
+
+        let formatted = table.format();
+        if buffer.text(&start, &end, false) == formatted {
+            return false;
+        }
+
+        *self.editing_table.borrow_mut() = true;
+        buffer.begin_user_action();
+        buffer.delete(&mut start, &mut end);
+        buffer.insert(&mut start, &formatted);
+        buffer.end_user_action();
+        *self.editing_table.borrow_mut() = false;
+        true
+    }
+
+    /// Put the cursor in a cell, selecting its contents
+    fn select_table_cell(&self, first_line: i32, row: usize, column: usize) {
+        let line = first_line + table::line_for_row(row) as i32;
+        let Some(text) = self.line_text(line) else {
+            return;
+        };
+        let Some((start, end)) = table::cell_range(&text, column) else {
+            return;
+        };
+        let buffer = self.text_view.buffer();
+        if let (Some(start), Some(end)) = (
+            buffer.iter_at_line_offset(line, start as i32),
+            buffer.iter_at_line_offset(line, end as i32),
+        ) {
+            buffer.select_range(&end, &start);
+        }
+    }
+
+    /// Apply an edit to the table under the cursor. The closure gets the
+    /// cursor's (row, column) and returns the cell to put the cursor in.
+    pub fn edit_table<F: FnOnce(&mut Table, usize, usize) -> (usize, usize)>(&self, edit: F) {
+        let buffer = self.text_view.buffer();
+        let cursor_line = buffer.iter_at_mark(&buffer.get_insert()).line();
+        let Some(context) = self.table_context(cursor_line) else {
+            println!("Cursor is not inside a table");
+            return;
+        };
+
+        let (row, column) = self.cursor_table_cell(&context);
+        let mut table = context.table.clone();
+        let (row, column) = edit(&mut table, row, column);
+        self.write_table(&context, &table);
+
+        *self.editing_table.borrow_mut() = true;
+        self.select_table_cell(context.first_line, row, column);
+        *self.editing_table.borrow_mut() = false;
+        *self.last_cursor_line.borrow_mut() = buffer.iter_at_mark(&buffer.get_insert()).line();
+    }
+
+    /// Move to the next or previous cell, adding a row after the last cell
+    fn move_table_cell(&self, backwards: bool) -> bool {
+        let buffer = self.text_view.buffer();
+        let cursor_line = buffer.iter_at_mark(&buffer.get_insert()).line();
+        if self.table_context(cursor_line).is_none() {
+            return false;
+        }
+
+        self.edit_table(|table, row, column| {
+            let columns = table.column_count();
+            if backwards {
+                match (row, column) {
+                    (0, 0) => (0, 0),
+                    (row, 0) => (row - 1, columns - 1),
+                    (row, column) => (row, column - 1),
+                }
+            } else if column + 1 < columns {
+                (row, column + 1)
+            } else {
+                if row + 1 >= table.rows.len() {
+                    table.insert_row(row + 1);
+                }
+                (row + 1, 0)
+            }
+        });
+        true
+    }
+
+    /// Align the table on the line the cursor just left
+    fn on_cursor_line_changed(&self) {
+        if *self.editing_table.borrow() {
+            return;
+        }
+        let buffer = self.text_view.buffer();
+        let cursor = buffer.iter_at_mark(&buffer.get_insert());
+        let previous_line = self.last_cursor_line.replace(cursor.line());
+        if previous_line == cursor.line() {
+            return;
+        }
+        // Moving through a table without editing it leaves it as it is, so
+        // there is no formatting step to undo
+        if !self.table_edited.replace(false) {
+            return;
+        }
+        let Some(context) = self.table_context(previous_line) else {
+            return;
+        };
+
+        // Formatting keeps the line count, so the cursor is restored by
+        // line and offset, or by cell when it is inside the same table
+        let cursor_line = cursor.line();
+        let in_table = (context.first_line..=context.last_line).contains(&cursor_line);
+        let cell = if in_table {
+            let text = self.line_text(cursor_line).unwrap_or_default();
+            let offset = cursor.line_offset() as usize;
+            let column = table::column_at(&text, offset);
+            let cell_start = table::cell_range(&text, column).map_or(0, |(start, _)| start);
+            Some((column, offset.saturating_sub(cell_start)))
+        } else {
+            None
+        };
+        let line_offset = cursor.line_offset();
+
+        // Don't edit the buffer from inside the cursor notification
+        let imp_weak = self.obj().downgrade();
+        glib::idle_add_local_once(move || {
+            let Some(widget) = imp_weak.upgrade() else {
+                return;
+            };
+            let imp = widget.imp();
+            let table = context.table.clone();
+            if !imp.write_table(&context, &table) {
+                return;
+            }
+
+            let buffer = imp.text_view.buffer();
+            let offset = match cell {
+                Some((column, offset_in_cell)) => imp
+                    .line_text(cursor_line)
+                    .and_then(|text| table::cell_range(&text, column))
+                    .map_or(line_offset, |(start, end)| {
+                        (start + offset_in_cell).min(end) as i32
+                    }),
+                None => line_offset,
+            };
+            if let Some(iter) = buffer.iter_at_line_offset(cursor_line, offset) {
+                *imp.editing_table.borrow_mut() = true;
+                buffer.place_cursor(&iter);
+                *imp.editing_table.borrow_mut() = false;
+            }
+        });
+    }
+
+    /// Insert a new table at the cursor
+    pub fn insert_table(&self, columns: usize, body_rows: usize) {
+        let buffer = self.text_view.buffer();
+        let cursor = buffer.iter_at_mark(&buffer.get_insert());
+        let line_text = self.line_text(cursor.line()).unwrap_or_default();
+
+        // Tables need to start on their own line after a blank line
+        let mut text = String::new();
+        if !line_text.trim().is_empty() {
+            text.push_str("\n\n");
+        }
+        text.push_str(&Table::new(columns, body_rows).format());
+        text.push('\n');
+
+        buffer.begin_user_action();
+        if !line_text.trim().is_empty() {
+            let mut end = cursor;
+            if !end.ends_line() {
+                end.forward_to_line_end();
+            }
+            buffer.place_cursor(&end);
+        }
+        let first_line = buffer.iter_at_mark(&buffer.get_insert()).line()
+            + if line_text.trim().is_empty() { 0 } else { 2 };
+        buffer.insert_at_cursor(&text);
+        buffer.end_user_action();
+
+        self.select_table_cell(first_line, 0, 0);
+        self.text_view.grab_focus();
+    }
+
+    pub fn show_insert_table_dialog(&self) {
+        let columns_spin = gtk::SpinButton::with_range(1.0, 20.0, 1.0);
+        columns_spin.set_value(3.0);
+        let rows_spin = gtk::SpinButton::with_range(0.0, 100.0, 1.0);
+        rows_spin.set_value(2.0);
+
+        let grid = gtk::Grid::builder()
+            .row_spacing(6)
+            .column_spacing(12)
+            .halign(gtk::Align::Center)
+            .build();
+        let columns_label = gtk::Label::new(Some("Columns"));
+        columns_label.set_xalign(0.0);
+        let rows_label = gtk::Label::new(Some("Rows"));
+        rows_label.set_xalign(0.0);
+        grid.attach(&columns_label, 0, 0, 1, 1);
+        grid.attach(&columns_spin, 1, 0, 1, 1);
+        grid.attach(&rows_label, 0, 1, 1, 1);
+        grid.attach(&rows_spin, 1, 1, 1, 1);
+
+        let dialog = adw::AlertDialog::new(Some("Insert Table"), None);
+        dialog.set_extra_child(Some(&grid));
+        dialog.add_responses(&[("cancel", "Cancel"), ("insert", "Insert")]);
+        dialog.set_response_appearance("insert", adw::ResponseAppearance::Suggested);
+        dialog.set_default_response(Some("insert"));
+        dialog.set_close_response("cancel");
+
+        dialog.connect_response(
+            None,
+            glib::clone!(
+                #[weak(rename_to = imp)]
+                self,
+                move |_, response| {
+                    if response == "insert" {
+                        imp.insert_table(columns_spin.value() as usize, rows_spin.value() as usize);
+                    }
+                }
+            ),
+        );
+        dialog.present(Some(&*self.obj()));
+    }
+
+    // Lists and blockquotes
+
+    /// Whether the cursor is inside a fenced code block
+    fn cursor_in_fenced_code(&self) -> bool {
+        let buffer = self.text_view.buffer();
+        let mut line_start = buffer.iter_at_mark(&buffer.get_insert());
+        line_start.set_line_offset(0);
+        let text = buffer.text(&buffer.start_iter(), &line_start, false);
+        markdown::ends_in_fenced_code(&text)
+    }
+
+    /// Replace the text of a whole line, without its newline
+    fn replace_line(&self, line: i32, text: &str) {
+        let buffer = self.text_view.buffer();
+        let Some(mut start) = buffer.iter_at_line(line) else {
+            return;
+        };
+        let mut end = start;
+        if !end.ends_line() {
+            end.forward_to_line_end();
+        }
+        buffer.delete(&mut start, &mut end);
+        buffer.insert(&mut start, text);
+    }
+
+    /// Enter in a list item or blockquote: continue it, or end it on an empty item
+    fn continue_list(&self) -> bool {
+        if self.cursor_in_fenced_code() {
+            return false;
+        }
+        let buffer = self.text_view.buffer();
+        let cursor = buffer.iter_at_mark(&buffer.get_insert());
+        let line = cursor.line();
+        let Some(text) = self.line_text(line) else {
+            return false;
+        };
+        let offset = cursor.line_offset() as usize;
+
+        if let Some(item) = ListItem::parse(&text) {
+            // Enter before the marker is an ordinary newline
+            if offset < item.content_start.min(text.chars().count()) {
+                return false;
+            }
+
+            buffer.begin_user_action();
+            if item.is_empty(&text) {
+                self.replace_line(line, "");
+            } else {
+                buffer.insert_at_cursor(&format!("\n{}", item.next_prefix()));
+                if item.is_ordered() {
+                    self.renumber_list(line);
+                }
+            }
+            buffer.end_user_action();
+            self.text_view.scroll_mark_onscreen(&buffer.get_insert());
+            return true;
+        }
+
+        if let Some(prefix) = lists::quote_prefix(&text) {
+            if offset < prefix.len() {
+                return false;
+            }
+            buffer.begin_user_action();
+            if text.trim().trim_start_matches('>').trim().is_empty() {
+                self.replace_line(line, "");
+            } else {
+                buffer.insert_at_cursor(&format!("\n{}", prefix));
+            }
+            buffer.end_user_action();
+            self.text_view.scroll_mark_onscreen(&buffer.get_insert());
+            return true;
+        }
+
+        false
+    }
+
+    /// Renumber the ordered list containing a line
+    fn renumber_list(&self, line: i32) {
+        let Some(item) = self.line_text(line).and_then(|text| ListItem::parse(&text)) else {
+            return;
+        };
+
+        // Walk back to the first item of this list, skipping nested lines
+        let mut first = line;
+        while first > 0 {
+            let Some(text) = self.line_text(first - 1) else {
+                break;
+            };
+            match ListItem::parse(&text) {
+                Some(above) if above.indent.len() == item.indent.len() && above.is_ordered() => {
+                    first -= 1
+                }
+                _ if text.len() - text.trim_start().len() > item.indent.len() => first -= 1,
+                _ => break,
+            }
+        }
+        while self
+            .line_text(first)
+            .and_then(|text| ListItem::parse(&text))
+            .is_none_or(|found| found.indent.len() != item.indent.len())
+        {
+            first += 1;
+        }
+
+        let line_count = self.text_view.buffer().line_count();
+        let lines: Vec<String> = (first..line_count)
+            .map_while(|line| self.line_text(line))
+            .collect();
+        for (index, text) in lists::renumber(&lines) {
+            self.replace_line(first + index as i32, &text);
+        }
+    }
+
+    /// Tab/Shift+Tab on list items and blockquotes: indent or outdent the
+    /// selected lines instead of inserting a tab
+    fn indent_list_lines(&self, outdent: bool) -> bool {
+        if self.cursor_in_fenced_code() {
+            return false;
+        }
+        let buffer = self.text_view.buffer();
+        let (start, end) = buffer.selection_bounds().unwrap_or_else(|| {
+            let cursor = buffer.iter_at_mark(&buffer.get_insert());
+            (cursor, cursor)
+        });
+        let (first_line, last_line) = (start.line(), end.line());
+
+        let first_text = self.line_text(first_line).unwrap_or_default();
+        if ListItem::parse(&first_text).is_none() && lists::quote_prefix(&first_text).is_none() {
+            return false;
+        }
+
+        buffer.begin_user_action();
+        for line in first_line..=last_line {
+            let Some(text) = self.line_text(line) else {
+                continue;
+            };
+
+            if let Some(item) = ListItem::parse(&text) {
+                let new_indent = if outdent {
+                    self.parent_item_indent(line, &item)
+                } else {
+                    self.nested_item_indent(line, &item)
+                };
+                let new_text = format!("{}{}", " ".repeat(new_indent), text.trim_start());
+                self.replace_line(line, &new_text);
+
+                // A newly nested ordered item starts its own list at 1
+                if item.is_ordered() && !outdent {
+                    let above = line > 0
+                        && self
+                            .line_text(line - 1)
+                            .and_then(|text| ListItem::parse(&text))
+                            .is_some_and(|above| above.indent.len() == new_indent);
+                    if !above {
+                        self.replace_line(line, &lists::with_number(&new_text, 1));
+                    }
+                }
+                if item.is_ordered() {
+                    self.renumber_list(line);
+                }
+            } else if let Some(prefix) = lists::quote_prefix(&text) {
+                let new_text = if outdent {
+                    let stripped = prefix.trim_start().strip_prefix('>').unwrap_or(prefix);
+                    format!(
+                        "{}{}",
+                        stripped.strip_prefix(' ').unwrap_or(stripped),
+                        &text[prefix.len()..]
+                    )
+                } else {
+                    format!("> {}", text)
+                };
+                self.replace_line(line, &new_text);
+            } else if !outdent && !text.is_empty() {
+                self.replace_line(line, &format!("    {}", text));
+            }
+        }
+        buffer.end_user_action();
+        true
+    }
+
+    /// Indent that nests an item under the item above it
+    fn nested_item_indent(&self, line: i32, item: &ListItem) -> usize {
+        let above = (0..line).rev().find_map(|line| {
+            let text = self.line_text(line)?;
+            let found = ListItem::parse(&text)?;
+            (found.indent.len() <= item.indent.len()).then_some(found)
+        });
+        match above {
+            Some(above) if above.indent.len() == item.indent.len() => above.content_column(),
+            _ => item.indent.len() + item.marker_width(),
+        }
+    }
+
+    /// Indent of the closest less-indented item above, or 0 at the top level
+    fn parent_item_indent(&self, line: i32, item: &ListItem) -> usize {
+        (0..line)
+            .rev()
+            .map_while(|line| self.line_text(line))
+            .take_while(|text| !text.trim().is_empty())
+            .filter_map(|text| ListItem::parse(&text))
+            .find(|found| found.indent.len() < item.indent.len())
+            .map_or(0, |found| found.indent.len())
+    }
+
+    // Auto-pairing
+
+    pub fn show_preferences(&self) {
+        match self.settings {
+            Some(ref settings) => preferences::show(&*self.obj(), settings, &self.config_dir),
+            None => eprintln!("Preferences need GSettings, which is not available"),
+        }
+    }
+
+    /// Forget auto-inserted pairs the cursor has left
+    fn prune_auto_pairs(&self) {
+        let buffer = self.text_view.buffer();
+        let cursor = buffer.iter_at_mark(&buffer.get_insert()).offset();
+        let mut marks = self.auto_pair_marks.borrow_mut();
+        while let Some((opener, closer)) = marks.last() {
+            let inside = buffer.iter_at_mark(opener).offset() <= cursor
+                && cursor <= buffer.iter_at_mark(closer).offset();
+            if inside {
+                break;
+            }
+            buffer.delete_mark(opener);
+            buffer.delete_mark(closer);
+            marks.pop();
+        }
+    }
+
+    fn pop_auto_pair(&self) {
+        let buffer = self.text_view.buffer();
+        if let Some((opener, closer)) = self.auto_pair_marks.borrow_mut().pop() {
+            buffer.delete_mark(&opener);
+            buffer.delete_mark(&closer);
+        }
+    }
+
+    /// The innermost auto-inserted pair if the cursor is right before its closer,
+    /// with whether the pair is still empty
+    fn auto_pair_at_cursor(&self) -> Option<bool> {
+        let buffer = self.text_view.buffer();
+        let cursor = buffer.iter_at_mark(&buffer.get_insert()).offset();
+        let marks = self.auto_pair_marks.borrow();
+        let (opener, closer) = marks.last()?;
+        let closer = buffer.iter_at_mark(closer).offset();
+        (closer == cursor).then(|| buffer.iter_at_mark(opener).offset() == closer)
+    }
+
+    /// Whether typing `opener` here should also insert its closer
+    fn should_auto_pair(&self, opener: char) -> bool {
+        let buffer = self.text_view.buffer();
+        let cursor = buffer.iter_at_mark(&buffer.get_insert());
+        let next = cursor.char();
+        let mut before = cursor;
+        let previous = if before.backward_char() {
+            Some(before.char())
+        } else {
+            None
+        };
+
+        // Only pair in front of whitespace, punctuation or another closer
+        let next_ok = cursor.is_end()
+            || next.is_whitespace()
+            || ")]}.,;:!?".contains(next)
+            || AUTO_PAIRS.iter().any(|(_, closer)| *closer == next);
+        if !next_ok {
+            return false;
+        }
+
+        // Symmetric markers only open after whitespace or an opening bracket,
+        // so apostrophes and snake_case are left alone
+        let symmetric = AUTO_PAIRS
+            .iter()
+            .any(|(open, close)| *open == opener && open == close);
+        if symmetric {
+            return previous.is_none_or(|p| p.is_whitespace() || "([{".contains(p));
+        }
+        true
+    }
+
+    fn insert_auto_pair(&self, opener: char, closer: char) {
+        let buffer = self.text_view.buffer();
+        buffer.begin_user_action();
+        buffer.insert_at_cursor(&format!("{}{}", opener, closer));
+        let mut cursor = buffer.iter_at_mark(&buffer.get_insert());
+        cursor.backward_char();
+        buffer.place_cursor(&cursor);
+        buffer.end_user_action();
+
+        // The opener mark stays put and the closer mark moves along as text is typed
+        let opener_mark = buffer.create_mark(None, &cursor, true);
+        let closer_mark = buffer.create_mark(None, &cursor, false);
+        self.auto_pair_marks
+            .borrow_mut()
+            .push((opener_mark, closer_mark));
+    }
+
+    /// Put back the selection the opener is replacing, wrapped in the pair,
+    /// keeping the inner text selected
+    fn wrap_selection(&self, opener: char, closer: char, text: &str) {
+        let buffer = self.text_view.buffer();
+        let start = buffer.iter_at_mark(&buffer.get_insert()).offset() + 1;
+        buffer.insert_at_cursor(&format!("{}{}{}", opener, text, closer));
+        let end = start + text.chars().count() as i32;
+        buffer.select_range(&buffer.iter_at_offset(start), &buffer.iter_at_offset(end));
+    }
+
+    /// Remember a selection that is deleted so typed text can replace it
+    fn on_delete_range(&self, start: &gtk::TextIter, end: &gtk::TextIter) {
+        let buffer = self.text_view.buffer();
+        let replaced = buffer
+            .selection_bounds()
+            .filter(|(selection_start, selection_end)| {
+                selection_start == start && selection_end == end
+            })
+            .map(|_| (start.offset(), buffer.text(start, end, true).to_string()));
+        self.replaced_selection.replace(replaced);
+    }
+
+    /// Pair text once the input method has composed it. Returns true if the
+    /// insertion was handled here instead.
+    fn on_insert_text(&self, location: &gtk::TextIter, text: &str) -> bool {
+        let replaced = self
+            .replaced_selection
+            .take()
+            .filter(|(offset, _)| *offset == location.offset())
+            .map(|(_, text)| text);
+        let mut chars = text.chars();
+        let (Some(c), None) = (chars.next(), chars.next()) else {
+            return false;
+        };
+        let typed = *self.in_user_action.borrow()
+            && !*self.replaying_history.borrow()
+            && !*self.pasting.borrow()
+            && !*self.updating_mirrors.borrow();
+        if !typed || self.inserting_pair.replace(true) {
+            return false;
+        }
+        let buffer = self.text_view.buffer();
+        let at_cursor = *location == buffer.iter_at_mark(&buffer.get_insert());
+        let handled = at_cursor && self.handle_auto_pair(c, replaced);
+        self.inserting_pair.replace(false);
+        handled
+    }
+
+    /// Handle a typed character for auto-pairing, returning true if it was
+    /// consumed. `replaced` is the selection the character is typed over.
+    fn handle_auto_pair(&self, c: char, replaced: Option<String>) -> bool {
+        let paired = AUTO_PAIRS
+            .iter()
+            .any(|(opener, closer)| *opener == c || *closer == c);
+        if !paired || !*self.auto_pair.borrow() || self.cursor_in_fenced_code() {
+            return false;
+        }
+        self.prune_auto_pairs();
+        let buffer = self.text_view.buffer();
+
+        // Type over a closer we inserted, unless an empty emphasis or code
+        // pair is being doubled up, as in ** or ``
+        if let Some(empty) = self.auto_pair_at_cursor() {
+            let cursor = buffer.iter_at_mark(&buffer.get_insert());
+            if cursor.char() == c {
+                if empty && "*_`".contains(c) {
+                    self.insert_auto_pair(c, c);
+                } else {
+                    let mut next = cursor;
+                    next.forward_char();
+                    buffer.place_cursor(&next);
+                    self.pop_auto_pair();
+                }
+                return true;
+            }
+        }
+
+        let Some((opener, closer)) = AUTO_PAIRS.iter().copied().find(|(opener, _)| *opener == c)
+        else {
+            return false;
+        };
+
+        if let Some(text) = replaced {
+            self.wrap_selection(opener, closer, &text);
+            return true;
+        }
+        if self.should_auto_pair(opener) {
+            self.insert_auto_pair(opener, closer);
+            return true;
+        }
+        false
+    }
+
+    /// Backspace in an empty auto-inserted pair deletes both halves
+    fn delete_auto_pair(&self) -> bool {
+        if !*self.auto_pair.borrow() {
+            return false;
+        }
+        self.prune_auto_pairs();
+        if self.auto_pair_at_cursor() != Some(true) {
+            return false;
+        }
+
+        let buffer = self.text_view.buffer();
+        let mut start = buffer.iter_at_mark(&buffer.get_insert());
+        let mut end = start;
+        start.backward_char();
+        end.forward_char();
+        self.pop_auto_pair();
+        buffer.delete(&mut start, &mut end);
+        true
+    }
+
+    /// "* " at the start of a line is a bullet, not emphasis: drop the closer
+    fn unpair_bullet_marker(&self) {
+        if self.auto_pair_at_cursor() != Some(true) {
+            return;
+        }
+        let buffer = self.text_view.buffer();
+        let cursor = buffer.iter_at_mark(&buffer.get_insert());
+        let mut line_start = cursor;
+        line_start.set_line_offset(0);
+        let before = buffer.text(&line_start, &cursor, false);
+        if before.trim_start() == "*" || before.trim_start() == "_" {
+            let mut start = cursor;
+            let mut end = cursor;
+            end.forward_char();
+            self.pop_auto_pair();
+            buffer.delete(&mut start, &mut end);
+        }
+    }
+
+    // Snippets
+
+    /// The user's snippets, read from the file the first time they're needed
+    /// and again after it changes
+    fn snippets(&self) -> Rc<Vec<Snippet>> {
+        if let Some(ref snippets) = *self.snippets.borrow() {
+            return snippets.clone();
+        }
+        let snippets = Rc::new(snippets::load(&self.config_dir));
+        *self.snippets.borrow_mut() = Some(snippets.clone());
+        if self.snippets_monitor.borrow().is_none() {
+            self.monitor_snippets();
+        }
+        snippets
+    }
+
+    /// Watch the snippets file so edits, in preferences or elsewhere, are
+    /// picked up on the next expansion
+    fn monitor_snippets(&self) {
+        let path = snippets::snippets_path(&self.config_dir);
+        let monitor = match gio::File::for_path(&path)
+            .monitor_file(gio::FileMonitorFlags::WATCH_MOVES, gio::Cancellable::NONE)
+        {
+            Ok(monitor) => monitor,
+            Err(e) => {
+                eprintln!("Failed to monitor {:?}: {}", path, e);
+                return;
+            }
+        };
+        monitor.connect_changed(glib::clone!(
+            #[weak(rename_to = imp)]
+            self,
+            move |_, _, _, _| {
+                *imp.snippets.borrow_mut() = None;
+            }
+        ));
+        *self.snippets_monitor.borrow_mut() = Some(monitor);
+    }
+
+    /// Expand the snippet whose trigger is the word before the cursor
+    fn expand_snippet_at_cursor(&self) -> bool {
+        let buffer = self.text_view.buffer();
+        if buffer.has_selection() {
+            return false;
+        }
+        let cursor = buffer.iter_at_mark(&buffer.get_insert());
+        let mut start = cursor;
+        while start.backward_char() {
+            if start.char().is_whitespace() {
+                start.forward_char();
+                break;
+            }
+        }
+        let word = buffer.text(&start, &cursor, false);
+        if word.is_empty() {
+            return false;
+        }
+        let Some(snippet) = self
+            .snippets()
+            .iter()
+            .find(|snippet| snippet.trigger == word.as_str())
+            .cloned()
+        else {
+            return false;
+        };
+        self.insert_snippet(snippet, start.offset(), cursor.offset(), String::new());
+        true
+    }
+
+    /// Pick a snippet to insert in place of the selection, which fills in `$SELECTION`
+    pub fn show_insert_snippet_dialog(&self) {
+        let snippets = self.snippets();
+        let buffer = self.text_view.buffer();
+        let (start, end) = buffer.selection_bounds().unwrap_or_else(|| {
+            let cursor = buffer.iter_at_mark(&buffer.get_insert());
+            (cursor, cursor)
+        });
+        let selection = buffer.text(&start, &end, false).to_string();
+        let (start, end) = (start.offset(), end.offset());
+
+        let labels: Vec<String> = snippets
+            .iter()
+            .map(|snippet| match snippet.description.as_str() {
+                "" => snippet.trigger.clone(),
+                description => format!("{} — {}", snippet.trigger, description),
+            })
+            .collect();
+        let labels: Vec<&str> = labels.iter().map(String::as_str).collect();
+        let snippet_row = adw::ComboRow::new();
+        snippet_row.set_title("Snippet");
+        snippet_row.set_model(Some(&gtk::StringList::new(&labels)));
+        let fields = adw::PreferencesGroup::new();
+        fields.add(&snippet_row);
+
+        let dialog = adw::AlertDialog::new(Some("Insert Snippet"), None);
+        dialog.set_extra_child(Some(&fields));
+        dialog.add_responses(&[("cancel", "Cancel"), ("insert", "Insert")]);
+        dialog.set_response_appearance("insert", adw::ResponseAppearance::Suggested);
+        dialog.set_default_response(Some("insert"));
+        dialog.set_close_response("cancel");
+        dialog.set_response_enabled("insert", !snippets.is_empty());
+
+        dialog.connect_response(
+            None,
+            glib::clone!(
+                #[weak(rename_to = imp)]
+                self,
+                move |_, response| {
+                    if response != "insert" {
+                        return;
+                    }
+                    if let Some(snippet) = snippets.get(snippet_row.selected() as usize) {
+                        imp.insert_snippet(snippet.clone(), start, end, selection.clone());
+                        imp.text_view.grab_focus();
+                    }
+                }
+            ),
+        );
+        dialog.present(Some(&*self.obj()));
+    }
+
+    /// Replace the text between two offsets with a snippet, reading the
+    /// clipboard first when the snippet uses it
+    fn insert_snippet(&self, snippet: Snippet, start: i32, end: i32, selection: String) {
+        if !snippet.uses_clipboard() {
+            let variables = Variables {
+                selection,
+                clipboard: String::new(),
+            };
+            self.insert_expanded_snippet(&snippet, start, end, &variables);
+            return;
+        }
+        self.text_view.clipboard().read_text_async(
+            None::<&gio::Cancellable>,
+            glib::clone!(
+                #[weak(rename_to = imp)]
+                self,
+                move |result| {
+                    let clipboard = match result {
+                        Ok(text) => text.map(|text| text.to_string()).unwrap_or_default(),
+                        Err(e) => {
+                            eprintln!("Failed to read the clipboard: {}", e);
+                            String::new()
+                        }
+                    };
+                    let variables = Variables {
+                        selection,
+                        clipboard,
+                    };
+                    imp.insert_expanded_snippet(&snippet, start, end, &variables);
+                }
+            ),
+        );
+    }
+
+    fn insert_expanded_snippet(
+        &self,
+        snippet: &Snippet,
+        start: i32,
+        end: i32,
+        variables: &Variables,
+    ) {
+        self.end_snippet();
+        let buffer = self.text_view.buffer();
+
+        // Lines after the first keep the indentation of the line it starts on
+        let mut line_start = buffer.iter_at_offset(start);
+        line_start.set_line_offset(0);
+        let indent: String = buffer
+            .text(&line_start, &buffer.iter_at_offset(start), false)
+            .chars()
+            .take_while(|c| c.is_whitespace())
+            .collect();
+        let expansion = snippet.expand(variables, &indent);
+
+        buffer.begin_user_action();
+        let mut start_iter = buffer.iter_at_offset(start);
+        let mut end_iter = buffer.iter_at_offset(end);
+        buffer.delete(&mut start_iter, &mut end_iter);
+        buffer.insert(&mut start_iter, &expansion.text);
+        buffer.end_user_action();
+
+        // Start marks stay put and end marks move along as a stop is typed in
+        let stops = expansion
+            .stops
+            .iter()
+            .map(|stop| {
+                stop.ranges
+                    .iter()
+                    .map(|range| {
+                        let from = buffer.iter_at_offset(start + range.start as i32);
+                        let to = buffer.iter_at_offset(start + range.end as i32);
+                        (
+                            buffer.create_mark(None, &from, true),
+                            buffer.create_mark(None, &to, false),
+                        )
+                    })
+                    .collect()
+            })
+            .collect();
+        *self.snippet_stops.borrow_mut() = stops;
+        self.select_snippet_stop(0);
+    }
+
+    /// Select a tab stop's text. The last stop ends the snippet.
+    fn select_snippet_stop(&self, index: usize) {
+        let buffer = self.text_view.buffer();
+        let (start, end, last) = {
+            let stops = self.snippet_stops.borrow();
+            let Some((start, end)) = stops.get(index).and_then(|ranges| ranges.first()) else {
+                return;
+            };
+            (
+                buffer.iter_at_mark(start),
+                buffer.iter_at_mark(end),
+                index + 1 == stops.len(),
+            )
+        };
+        *self.snippet_stop.borrow_mut() = index;
+        buffer.select_range(&start, &end);
+        self.text_view.scroll_mark_onscreen(&buffer.get_insert());
+        if last {
+            self.end_snippet();
+        }
+    }
+
+    /// Go to the next or previous tab stop of the snippet being filled in
+    fn move_snippet_stop(&self, backward: bool) -> bool {
+        let count = self.snippet_stops.borrow().len();
+        if count == 0 {
+            return false;
+        }
+        let current = *self.snippet_stop.borrow();
+        let index = if backward {
+            current.saturating_sub(1)
+        } else {
+            (current + 1).min(count - 1)
+        };
+        self.select_snippet_stop(index);
+        true
+    }
+
+    fn end_snippet(&self) {
+        let buffer = self.text_view.buffer();
+        for (start, end) in self.snippet_stops.borrow_mut().drain(..).flatten() {
+            buffer.delete_mark(&start);
+            buffer.delete_mark(&end);
+        }
+        *self.snippet_stop.borrow_mut() = 0;
+    }
+
+    /// End the snippet once the cursor is outside all of its tab stops
+    fn leave_snippet_if_outside(&self) {
+        let buffer = self.text_view.buffer();
+        let cursor = buffer.iter_at_mark(&buffer.get_insert()).offset();
+        let inside = {
+            let stops = self.snippet_stops.borrow();
+            stops.is_empty()
+                || stops.iter().flatten().any(|(start, end)| {
+                    buffer.iter_at_mark(start).offset() <= cursor
+                        && cursor <= buffer.iter_at_mark(end).offset()
+                })
+        };
+        if !inside {
+            self.end_snippet();
+        }
+    }
+
+    /// Copy what was typed in each tab stop to its mirrors. The copies join
+    /// the user action that changed the stop, so one undo reverts both.
+    fn update_snippet_mirrors(&self) {
+        if *self.updating_mirrors.borrow() {
+            return;
+        }
+        let stops = self.snippet_stops.borrow().clone();
+        if stops.is_empty() {
+            return;
+        }
+        let buffer = self.text_view.buffer();
+        *self.updating_mirrors.borrow_mut() = true;
+        buffer.begin_user_action();
+        for ranges in &stops {
+            let Some(((start, end), mirrors)) = ranges.split_first() else {
+                continue;
+            };
+            let (start, end) = (buffer.iter_at_mark(start), buffer.iter_at_mark(end));
+            let text = buffer.text(&start, &end, false);
+            for (mirror_start, mirror_end) in mirrors {
+                let mut from = buffer.iter_at_mark(mirror_start);
+                let mut to = buffer.iter_at_mark(mirror_end);
+                if buffer.text(&from, &to, false) != text {
+                    buffer.delete(&mut from, &mut to);
+                    buffer.insert(&mut from, &text);
+                }
+            }
+        }
+        buffer.end_user_action();
+        *self.updating_mirrors.borrow_mut() = false;
+    }
+
+    // Key handling
+
+    fn on_key_pressed(
+        &self,
+        key: gtk::gdk::Key,
+        state: gtk::gdk::ModifierType,
+    ) -> glib::Propagation {
+        let modifiers = state & gtk::accelerator_get_default_mod_mask();
+
+        match key {
+            gtk::gdk::Key::Tab if modifiers.is_empty() => {
+                if self.move_snippet_stop(false)
+                    || self.expand_snippet_at_cursor()
+                    || self.move_table_cell(false)
+                    || self.indent_list_lines(false)
+                {
+                    return glib::Propagation::Stop;
+                }
+            }
+            gtk::gdk::Key::ISO_Left_Tab => {
+                if self.move_snippet_stop(true)
+                    || self.move_table_cell(true)
+                    || self.indent_list_lines(true)
+                {
+                    return glib::Propagation::Stop;
+                }
+            }
+            // Leave the snippet first, focus mode on the next press
+            gtk::gdk::Key::Escape if !self.snippet_stops.borrow().is_empty() => {
+                self.end_snippet();
+                return glib::Propagation::Stop;
+            }
+            gtk::gdk::Key::Return | gtk::gdk::Key::KP_Enter if modifiers.is_empty() => {
+                if self.continue_list() {
+                    return glib::Propagation::Stop;
+                }
+            }
+            gtk::gdk::Key::BackSpace if modifiers.is_empty() => {
+                if self.delete_auto_pair() {
+                    return glib::Propagation::Stop;
+                }
+            }
+            gtk::gdk::Key::Right | gtk::gdk::Key::KP_Right if modifiers.is_empty() => {
+                if self.skip_image_previews() {
+                    return glib::Propagation::Stop;
+                }
+            }
+            gtk::gdk::Key::Delete | gtk::gdk::Key::KP_Delete if modifiers.is_empty() => {
+                if self.delete_after_image_previews() {
+                    return glib::Propagation::Stop;
+                }
+            }
+            gtk::gdk::Key::space => self.unpair_bullet_marker(),
+            _ => {}
+        }
+        glib::Propagation::Proceed
+    }
+
+    fn update_placeholder_visibility(&self, text: &str) {
+        if text.trim().is_empty() {
+            self.placeholder_label.set_visible(true);
+        } else {
+            self.placeholder_label.set_visible(false);
+        }
+    }
+
+    pub fn set_settings_key(&self, key: &str) {
+        *self.settings_key.borrow_mut() = Some(key.to_string());
+        self.load_from_settings();
+        self.load_goals();
+        self.load_image_preview_state();
+    }
+
+    pub fn save_to_settings(&self) {
+        if *self.is_loading.borrow() {
+            return;
+        }
+        if let Some(key) = self.settings_key.borrow().as_ref() {
+            let buffer = self.text_view.buffer();
+            let start = buffer.start_iter();
+            let end = buffer.end_iter();
+            let text = buffer.text(&start, &end, false);
+            if let Err(e) = self.set_config_value(key, &text) {
+                eprintln!("Failed to save text to settings: {}", e);
+            } else {
+                println!("Text saved to key: {}", key);
+            }
+        } else {
+            eprintln!("No settings key configured for this text view");
+        }
+    }
+
+    pub fn load_from_settings(&self) {
+        if let Some(key) = self.settings_key.borrow().as_ref() {
+            *self.is_loading.borrow_mut() = true;
+            let saved_text = self.get_config_value(key, "");
+            let buffer = self.text_view.buffer();
+            buffer.set_text(&saved_text);
+            self.update_counts();
+            *self.is_loading.borrow_mut() = false;
+            println!(
+                "Loaded text from key: {} (length: {})",
+                key,
+                saved_text.len()
+            );
+        }
+    }
+
+    pub fn set_auto_save(&self, auto_save: bool) {
+        *self.auto_save.borrow_mut() = auto_save;
+    }
+}
+
+impl ObjectImpl for CustomTextView {
+    fn constructed(&self) {
+        self.parent_constructed();
+
+        // the template child WebView
+        self.web_view.set_vexpand(true);
+        self.web_view.set_hexpand(true);
+
+        self.web_view
+            .connect_context_menu(|_web_view, _context_menu, _event| true);
+
+        self.web_view.connect_load_changed(|_view, event| {
+            println!("WebView load event: {:?}", event);
+        });
+        // Show Graphviz diagrams once `dot` has finished with them
+        let imp_weak = self.obj().downgrade();
+        diagrams::connect_rendered(move || {
+            if let Some(widget) = imp_weak.upgrade() {
+                widget.imp().schedule_preview_update();
+            }
+        });
+
+        // Set up the text view properties
+        self.text_view.set_wrap_mode(gtk::WrapMode::None);
+        self.text_view.set_accepts_tab(true);
+        self.text_view.set_monospace(false);
+        self.text_view.set_left_margin(8);
+        self.text_view.set_right_margin(8);
+        self.text_view.set_top_margin(8);
+        self.text_view.set_bottom_margin(8);
+        self.text_view.add_css_class("monospace");
+
+        // Set up scrolled window properties
+        self.scrolled_window
+            .set_policy(gtk::PolicyType::Automatic, gtk::PolicyType::Automatic);
+        self.scrolled_window.set_min_content_height(150);
+        self.scrolled_window.add_css_class("card");
+
+        // Set up placeholder initially
+        self.placeholder_label.set_visible(true);
+        self.placeholder_label.set_sensitive(false);
+        self.placeholder_label.add_css_class("dim-label");
+
+        // Add CSS classes for other widgets
+        self.title_label.add_css_class("heading");
+        self.save_button.add_css_class("flat");
+        self.clear_button.add_css_class("flat");
+        self.clear_button.add_css_class("destructive-action");
+        self.char_count_label.add_css_class("caption");
+        self.char_count_label.add_css_class("dim-label");
+        self.word_count_label.add_css_class("caption");
+        self.word_count_label.add_css_class("dim-label");
+        self.stats_button.add_css_class("flat");
+
+        // Load code block counting option
+        let count_code_blocks = self
+            .get_config_value("count-code-blocks", "false")
+            .parse::<bool>()
+            .unwrap_or(false);
+        *self.count_code_blocks.borrow_mut() = count_code_blocks;
+        self.count_code_check.set_active(count_code_blocks);
+        self.count_code_check.connect_toggled(glib::clone!(
+            #[weak(rename_to = imp)]
+            self,
+            move |check| {
+                imp.set_count_code_blocks(check.is_active());
+            }
+        ));
+
+        self.image_preview_button.connect_toggled(glib::clone!(
+            #[weak(rename_to = imp)]
+            self,
+            move |button| {
+                imp.set_image_previews(button.is_active());
+            }
+        ));
+
+        // Writing goals
+        self.load_goals();
+        self.document_goal_spin.connect_value_changed(glib::clone!(
+            #[weak(rename_to = imp)]
+            self,
+            move |spin| {
+                if *imp.loading_goals.borrow() {
+                    return;
+                }
+                imp.set_document_goal(spin.value() as usize);
+            }
+        ));
+        self.daily_goal_spin.connect_value_changed(glib::clone!(
+            #[weak(rename_to = imp)]
+            self,
+            move |spin| {
+                if *imp.loading_goals.borrow() {
+                    return;
+                }
+                imp.set_daily_goal(spin.value() as usize);
+            }
+        ));
+        // Gutter marking lines changed since the last commit
+        let git_gutter = gtk::DrawingArea::new();
+        git_gutter.set_content_width(4);
+        git_gutter.set_visible(false);
+        git_gutter.set_draw_func(glib::clone!(
+            #[weak(rename_to = imp)]
+            self,
+            move |_, cr, width, _| {
+                imp.draw_git_gutter(cr, width);
+            }
+        ));
+        self.text_view
+            .set_gutter(gtk::TextWindowType::Left, Some(&git_gutter));
+        self.scrolled_window
+            .vadjustment()
+            .connect_value_changed(glib::clone!(
+                #[weak]
+                git_gutter,
+                move |_| git_gutter.queue_draw()
+            ));
+
+        self.history_chart.set_draw_func(glib::clone!(
+            #[weak(rename_to = imp)]
+            self,
+            move |_, cr, width, height| {
+                imp.draw_history_chart(cr, width, height);
+            }
+        ));
+
+        // Keep the session timer current
+        self.update_session_label();
+        glib::timeout_add_seconds_local(
+            30,
+            glib::clone!(
+                #[weak(rename_to = imp)]
+                self,
+                #[upgrade_or]
+                glib::ControlFlow::Break,
+                move || {
+                    imp.update_session_label();
+                    glib::ControlFlow::Continue
+                }
+            ),
+        );
+
+        // Refresh statistics each time the popover opens
+        self.stats_popover.connect_show(glib::clone!(
+            #[weak(rename_to = imp)]
+            self,
+            move |_| {
+                imp.update_stats_popover();
+            }
+        ));
+
+        // Manually connect button signals
+        self.save_button.connect_clicked(glib::clone!(
             #[weak(rename_to = imp)]
             self,
             move |_| {
@@ -429,15 +4704,342 @@ impl ObjectImpl for CustomTextView {
             }
         ));
 
-        // Connect buffer changed signal to update counts and auto-save
+        // Focus mode: dim other paragraphs and follow the cursor
         let buffer = self.text_view.buffer();
+        buffer.create_tag(Some(FOCUS_DIM_TAG), &[]);
+        buffer.create_tag(
+            Some(WIKI_LINK_TAG),
+            &[
+                ("underline", &gtk::pango::Underline::Single),
+                ("foreground", &"#3584e4"),
+            ],
+        );
+        buffer.create_tag(
+            Some(FOOTNOTE_ORPHAN_TAG),
+            &[("underline", &gtk::pango::Underline::Error)],
+        );
+        buffer.create_tag(
+            Some(IMAGE_LINE_TAG),
+            &[("wrap-mode", &gtk::WrapMode::WordChar)],
+        );
+        buffer.connect_cursor_position_notify(glib::clone!(
+            #[weak(rename_to = imp)]
+            self,
+            move |_| {
+                imp.update_focus_dimming();
+                imp.center_cursor();
+                imp.on_cursor_line_changed();
+                imp.leave_snippet_if_outside();
+                imp.keep_cursor_before_image_previews();
+            }
+        ));
+
+        // Auto-pairing follows the preference live
+        *self.auto_pair.borrow_mut() = self
+            .get_config_value("auto-pair", "true")
+            .parse::<bool>()
+            .unwrap_or(true);
+        if let Some(ref settings) = self.settings {
+            settings.connect_changed(
+                Some("auto-pair"),
+                glib::clone!(
+                    #[weak(rename_to = imp)]
+                    self,
+                    move |settings, key| {
+                        *imp.auto_pair.borrow_mut() = settings.boolean(key);
+                    }
+                ),
+            );
+        }
+
+        // Auto-pairing works on inserted text rather than keys, so dead keys
+        // and compose sequences reach the input method first
+        buffer.connect_delete_range(glib::clone!(
+            #[weak(rename_to = imp)]
+            self,
+            move |_, start, end| imp.on_delete_range(start, end)
+        ));
+        buffer.connect_insert_text(glib::clone!(
+            #[weak(rename_to = imp)]
+            self,
+            move |buffer, location, text| {
+                if imp.on_insert_text(location, text) {
+                    buffer.stop_signal_emission_by_name("insert-text");
+                    *location = buffer.iter_at_mark(&buffer.get_insert());
+                }
+            }
+        ));
+        buffer.connect_begin_user_action(glib::clone!(
+            #[weak(rename_to = imp)]
+            self,
+            move |_| {
+                imp.in_user_action.replace(true);
+            }
+        ));
+        buffer.connect_end_user_action(glib::clone!(
+            #[weak(rename_to = imp)]
+            self,
+            move |_| {
+                imp.in_user_action.replace(false);
+                imp.pasting.replace(false);
+                imp.replaced_selection.replace(None);
+            }
+        ));
+        for (signal, after) in [
+            ("undo", false),
+            ("undo", true),
+            ("redo", false),
+            ("redo", true),
+        ] {
+            buffer.connect_closure(
+                signal,
+                after,
+                glib::closure_local!(
+                    #[weak(rename_to = imp)]
+                    self,
+                    move |_: gtk::TextBuffer| {
+                        imp.replaying_history.replace(!after);
+                    }
+                ),
+            );
+        }
+        self.text_view.connect_paste_clipboard(glib::clone!(
+            #[weak(rename_to = imp)]
+            self,
+            move |_| {
+                imp.pasting.replace(true);
+            }
+        ));
+
+        // Editing keys: snippets, table navigation and list continuation
+        let key_controller = gtk::EventControllerKey::new();
+        key_controller.set_propagation_phase(gtk::PropagationPhase::Capture);
+        key_controller.connect_key_pressed(glib::clone!(
+            #[weak(rename_to = imp)]
+            self,
+            #[upgrade_or]
+            glib::Propagation::Proceed,
+            move |_, key, _, state| imp.on_key_pressed(key, state)
+        ));
+        self.text_view.add_controller(key_controller);
+        self.obj().connect_map(glib::clone!(
+            #[weak(rename_to = imp)]
+            self,
+            move |_| {
+                imp.apply_focus_fullscreen();
+                imp.update_metadata();
+            }
+        ));
+        self.obj()
+            .action_set_enabled("editor.exit-focus-mode", false);
+        let focus_mode = self
+            .get_config_value("focus-mode", "false")
+            .parse::<bool>()
+            .unwrap_or(false);
+        self.set_focus_mode(focus_mode);
+
+        // Preview themes follow the app's light/dark appearance
+        *self.preview_theme.borrow_mut() =
+            self.get_config_value("preview-theme", preview::DEFAULT_THEME);
+        self.load_preview_themes();
+        self.monitor_user_themes();
+        self.preview_theme_dropdown
+            .connect_selected_notify(glib::clone!(
+                #[weak(rename_to = imp)]
+                self,
+                move |dropdown| {
+                    if *imp.populating_themes.borrow() {
+                        return;
+                    }
+                    let id = imp
+                        .preview_themes
+                        .borrow()
+                        .get(dropdown.selected() as usize)
+                        .map(|theme| theme.id.clone());
+                    if let Some(id) = id {
+                        imp.set_preview_theme(&id);
+                    }
+                }
+            ));
+        adw::StyleManager::default().connect_dark_notify(glib::clone!(
+            #[weak(rename_to = imp)]
+            self,
+            move |_| {
+                imp.update_preview();
+            }
+        ));
+
+        // Front matter properties form
+        for entry in [
+            &*self.metadata_title_entry,
+            &*self.metadata_date_entry,
+            &*self.metadata_tags_entry,
+        ] {
+            entry.connect_changed(glib::clone!(
+                #[weak(rename_to = imp)]
+                self,
+                move |_| {
+                    imp.apply_metadata_form();
+                }
+            ));
+        }
+        self.metadata_date_entry
+            .connect_icon_press(|entry, position| {
+                if position == gtk::EntryIconPosition::Secondary {
+                    entry.set_text(&Local::now().format("%Y-%m-%d").to_string());
+                }
+            });
+
+        // Wiki-links: Ctrl+click in the editor, plain click in the preview
+        let link_click = gtk::GestureClick::new();
+        link_click.set_button(gtk::gdk::BUTTON_PRIMARY);
+        link_click.set_propagation_phase(gtk::PropagationPhase::Capture);
+        link_click.connect_pressed(glib::clone!(
+            #[weak(rename_to = imp)]
+            self,
+            move |gesture, _, x, y| {
+                if !gesture
+                    .current_event_state()
+                    .contains(gtk::gdk::ModifierType::CONTROL_MASK)
+                {
+                    return;
+                }
+                let (x, y) = imp.text_view.window_to_buffer_coords(
+                    gtk::TextWindowType::Widget,
+                    x as i32,
+                    y as i32,
+                );
+                let Some(iter) = imp.text_view.iter_at_location(x, y) else {
+                    return;
+                };
+                if let Some(link) = imp.wiki_link_at(&iter) {
+                    gesture.set_state(gtk::EventSequenceState::Claimed);
+                    imp.open_wiki_link(&link.target, link.heading.as_deref());
+                }
+            }
+        ));
+        self.text_view.add_controller(link_click);
+
+        self.web_view.connect_decide_policy(glib::clone!(
+            #[weak(rename_to = imp)]
+            self,
+            #[upgrade_or]
+            false,
+            move |_, decision, decision_type| {
+                if decision_type != webkit2gtk::PolicyDecisionType::NavigationAction {
+                    return false;
+                }
+                let Some(uri) = decision
+                    .downcast_ref::<webkit2gtk::NavigationPolicyDecision>()
+                    .and_then(|decision| decision.navigation_action())
+                    .and_then(|mut action| action.request())
+                    .and_then(|request| request.uri())
+                else {
+                    return false;
+                };
+                let Some((target, heading)) = notes::parse_note_uri(&uri) else {
+                    return false;
+                };
+                decision.ignore();
+                imp.open_wiki_link(&target, heading.as_deref());
+                true
+            }
+        ));
+
+        // Backlinks and tags come from scanning the notes folder, so only when shown
+        self.sidebar_stack
+            .connect_visible_child_name_notify(glib::clone!(
+                #[weak(rename_to = imp)]
+                self,
+                move |stack| match stack.visible_child_name().as_deref() {
+                    Some("backlinks") => imp.update_backlinks(),
+                    Some("tags") => imp.update_tag_index(),
+                    _ => {}
+                }
+            ));
+        self.backlinks_list.connect_row_activated(glib::clone!(
+            #[weak(rename_to = imp)]
+            self,
+            move |_, row| {
+                let Some(backlink) = imp.backlinks.borrow().get(row.index() as usize).cloned()
+                else {
+                    return;
+                };
+                // Opening the note rebuilds this list, so leave the signal handler first
+                let imp_weak = imp.obj().downgrade();
+                glib::idle_add_local_once(move || {
+                    if let Some(widget) = imp_weak.upgrade() {
+                        let imp = widget.imp();
+                        imp.open_file(&backlink.path);
+                        imp.go_to_line(backlink.line as i32);
+                    }
+                });
+            }
+        ));
+        self.update_backlinks();
+        self.notes_search_entry.connect_search_changed(glib::clone!(
+            #[weak(rename_to = imp)]
+            self,
+            move |_| {
+                imp.run_note_search();
+            }
+        ));
+        self.notes_search_entry.connect_activate(glib::clone!(
+            #[weak(rename_to = imp)]
+            self,
+            move |_| {
+                imp.open_search_result(0);
+            }
+        ));
+        self.search_results_list.connect_row_activated(glib::clone!(
+            #[weak(rename_to = imp)]
+            self,
+            move |_, row| {
+                imp.open_search_result(row.index() as usize);
+            }
+        ));
+        self.tag_notes_list.connect_row_activated(glib::clone!(
+            #[weak(rename_to = imp)]
+            self,
+            move |_, row| {
+                let path = imp.tag_notes.borrow().get(row.index() as usize).cloned();
+                if let Some(path) = path {
+                    imp.open_file(&path);
+                }
+            }
+        ));
+
+        self.file_changed_banner
+            .connect_button_clicked(glib::clone!(
+                #[weak(rename_to = imp)]
+                self,
+                move |_| {
+                    imp.show_file_conflict_dialog();
+                }
+            ));
+
+        // Connect buffer changed signal to update counts and auto-save
         buffer.connect_changed(glib::clone!(
             #[weak(rename_to = imp)]
             self,
             move |_| {
+                // Adding or removing previews leaves the text as it was
+                if *imp.updating_image_previews.borrow() {
+                    return;
+                }
+                if !*imp.editing_table.borrow() && !*imp.is_loading.borrow() {
+                    *imp.table_edited.borrow_mut() = true;
+                }
+                imp.update_snippet_mirrors();
                 imp.update_counts();
-                if *imp.auto_save.borrow() && imp.settings_key.borrow().is_some() {
-                    imp.save_to_settings();
+                imp.update_metadata();
+                imp.highlight_wiki_links();
+                imp.check_footnotes();
+                imp.schedule_image_preview_update();
+                imp.schedule_git_changes_update();
+                imp.schedule_preview_update();
+                if *imp.auto_save.borrow() {
+                    imp.schedule_save();
                 }
             }
         ));
@@ -469,12 +5071,14 @@ impl ObjectImpl for CustomTextView {
 
         // Initial count update
         self.update_counts();
+        self.update_preview();
     }
 
     fn dispose(&self) {
         if *self.auto_save.borrow() {
-            self.save_to_settings();
+            self.save_document();
         }
+        self.save_writing_history();
 
         // Save final navigation state
         let nav_visible = *self.nav_visible.borrow();
@@ -2,6 +2,9 @@ use adw::prelude::*;
 use gtk::glib;
 use gtk::subclass::prelude::ObjectSubclassIsExt;
 
+use crate::frontmatter::FrontMatter;
+use crate::stats::DocumentStats;
+
 mod imp;
 
 glib::wrapper! {
@@ -60,6 +63,46 @@ impl CustomTextView {
         imp.save_to_settings();
     }
 
+    /// Open a Markdown file in the editor, saving the current document first
+    pub fn open_file(&self, path: &std::path::Path) {
+        self.imp().open_file(path);
+    }
+
+    /// Create an empty note in the notes folder and open it
+    pub fn new_document(&self) {
+        self.imp().new_document();
+    }
+
+    /// Choose a built-in or user template and create a note from it
+    pub fn show_new_from_template_dialog(&self) {
+        self.imp().show_new_from_template_dialog();
+    }
+
+    /// Save the open file, or the settings-backed document when no file is open
+    pub fn save_document(&self) {
+        self.imp().save_document();
+    }
+
+    /// Open a note by wiki-link target, creating it in the notes folder if needed
+    pub fn open_wiki_link(&self, target: &str, heading: Option<&str>) {
+        self.imp().open_wiki_link(target, heading);
+    }
+
+    /// Rename a tag in every note of the notes folder, merging it into `new` if that tag exists
+    pub fn rename_tag(&self, old: &str, new: &str) {
+        self.imp().rename_tag(old, new);
+    }
+
+    /// Remember the open document, cursor, selection and scroll position for the next launch
+    pub fn save_session(&self) {
+        self.imp().save_session();
+    }
+
+    /// Reopen the document from the last session, returning false if its file is gone
+    pub fn restore_session(&self) -> bool {
+        self.imp().restore_session()
+    }
+
     /// Load text from settings
     pub fn load_from_settings(&self) {
         let imp = self.imp();
@@ -82,9 +125,14 @@ impl CustomTextView {
         imp.text_view.set_wrap_mode(wrap_mode);
     }
 
-    /// Get word count
+    /// Get word count of the rendered text, using Unicode word segmentation
     pub fn word_count(&self) -> usize {
-        self.get_text().split_whitespace().count()
+        self.imp().word_count()
+    }
+
+    /// Include or exclude fenced and indented code blocks from word counts
+    pub fn set_count_code_blocks(&self, count_code_blocks: bool) {
+        self.imp().set_count_code_blocks(count_code_blocks);
     }
 
     /// Get character count
@@ -92,6 +140,57 @@ impl CustomTextView {
         self.get_text().chars().count()
     }
 
+    /// Get detailed statistics for the whole document
+    pub fn statistics(&self) -> DocumentStats {
+        self.imp().document_stats()
+    }
+
+    /// Get detailed statistics for the selected text, if there is a selection
+    pub fn selection_statistics(&self) -> Option<DocumentStats> {
+        self.imp().selection_stats()
+    }
+
+    /// Get the title, date, tags and other metadata from the front matter
+    pub fn front_matter(&self) -> Option<FrontMatter> {
+        self.imp().front_matter()
+    }
+
+    /// Set the target word count for this document (0 removes the goal)
+    pub fn set_document_goal(&self, goal: usize) {
+        self.imp().set_document_goal(goal);
+    }
+
+    /// Set the target number of words written per day (0 removes the goal)
+    pub fn set_daily_goal(&self, goal: usize) {
+        self.imp().set_daily_goal(goal);
+    }
+
+    /// Toggle the distraction-free focus mode
+    pub fn toggle_focus_mode(&self) {
+        let imp = self.imp();
+        imp.set_focus_mode(!imp.is_focus_mode());
+    }
+
+    /// Enter or leave the distraction-free focus mode
+    pub fn set_focus_mode(&self, enabled: bool) {
+        self.imp().set_focus_mode(enabled);
+    }
+
+    /// Check if focus mode is active
+    pub fn is_focus_mode(&self) -> bool {
+        self.imp().is_focus_mode()
+    }
+
+    /// Set the preview stylesheet by id ("github", "academic", "minimal" or "user:NAME")
+    pub fn set_preview_theme(&self, id: &str) {
+        self.imp().set_preview_theme(id);
+    }
+
+    /// Insert an empty table with a header row and `rows` body rows at the cursor
+    pub fn insert_table(&self, columns: usize, rows: usize) {
+        self.imp().insert_table(columns, rows);
+    }
+
     // Navigation panel methods
 
     /// Toggle the navigation panel visibility
@@ -0,0 +1,192 @@
+use gtk::{gio, glib};
+use markdown_ppp::ast::Document;
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::ffi::OsStr;
+use std::path::Path;
+
+use crate::markdown;
+
+const MERMAID_RESOURCE: &str = "/org/md-wr/com/preview/mermaid.min.js";
+
+// Marker class used to decide whether a page needs the Mermaid script
+pub const MERMAID_CLASS: &str = "diagram mermaid";
+
+// Rendered Graphviz diagrams kept so re-rendering while typing stays cheap;
+// the oldest are dropped first
+const GRAPHVIZ_CACHE_SIZE: usize = 32;
+
+thread_local! {
+    // Graphviz output keyed by source, most recently used last
+    static GRAPHVIZ_CACHE: RefCell<VecDeque<(String, String)>> = const { RefCell::new(VecDeque::new()) };
+    // Set while `dot` runs; one diagram is rendered at a time
+    static GRAPHVIZ_RUNNING: Cell<bool> = const { Cell::new(false) };
+    static ON_RENDERED: RefCell<Option<Box<dyn Fn()>>> = const { RefCell::new(None) };
+}
+
+/// Replace `mermaid` and `dot` fenced code blocks with diagram HTML
+pub fn render_diagrams(document: &mut Document) {
+    markdown::replace_code_blocks(document, &mut |language, code| match language {
+        "mermaid" => Some(mermaid_html(code)),
+        "dot" | "graphviz" => graphviz_html(code),
+        _ => None,
+    });
+}
+
+/// Call `callback` when a diagram that was still rendering is ready, so the
+/// page can be rendered again to show it
+pub fn connect_rendered(callback: impl Fn() + 'static) {
+    ON_RENDERED.with(|on_rendered| *on_rendered.borrow_mut() = Some(Box::new(callback)));
+}
+
+fn mermaid_html(source: &str) -> String {
+    format!(
+        "<div class=\"{}\">{}</div>\n",
+        MERMAID_CLASS,
+        markdown::escape_html(source)
+    )
+}
+
+fn error_html(kind: &str, message: &str) -> String {
+    format!(
+        "<div class=\"diagram-error\"><strong>{} diagram error</strong><pre>{}</pre></div>\n",
+        kind,
+        markdown::escape_html(message.trim())
+    )
+}
+
+/// Render a Graphviz block to inline SVG. Returns None when Graphviz is not
+/// installed so the block is shown as ordinary code instead. `dot` runs in
+/// the background; until it is done the block's source is shown.
+fn graphviz_html(source: &str) -> Option<String> {
+    let dot = glib::find_program_in_path("dot")?;
+
+    let cached = GRAPHVIZ_CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        let index = cache.iter().position(|(key, _)| key == source)?;
+        let entry = cache.remove(index)?;
+        let html = entry.1.clone();
+        cache.push_back(entry);
+        Some(html)
+    });
+    if cached.is_some() {
+        return cached;
+    }
+
+    if !GRAPHVIZ_RUNNING.replace(true) {
+        if let Err(e) = run_graphviz(&dot, source) {
+            GRAPHVIZ_RUNNING.set(false);
+            eprintln!("Graphviz failed: {}", e);
+            cache_graphviz(source, error_html("Graphviz", &e.to_string()));
+        }
+    }
+    Some(format!(
+        "<div class=\"diagram graphviz\"><pre>{}</pre></div>\n",
+        markdown::escape_html(source)
+    ))
+}
+
+fn run_graphviz(dot: &Path, source: &str) -> Result<(), glib::Error> {
+    let process = gio::Subprocess::newv(
+        &[dot.as_os_str(), OsStr::new("-Tsvg")],
+        gio::SubprocessFlags::STDIN_PIPE
+            | gio::SubprocessFlags::STDOUT_PIPE
+            | gio::SubprocessFlags::STDERR_PIPE,
+    )?;
+    let source = source.to_string();
+    process.clone().communicate_utf8_async(
+        Some(source.clone()),
+        None::<&gio::Cancellable>,
+        move |result| {
+            let html = match result {
+                Ok((stdout, _)) if process.is_successful() => {
+                    // Drop the XML prolog and doctype, keep only the <svg> element
+                    let svg = stdout.unwrap_or_default();
+                    let svg = svg.find("<svg").map(|i| &svg[i..]).unwrap_or(&svg);
+                    format!("<div class=\"diagram graphviz\">{}</div>\n", svg)
+                }
+                Ok((_, stderr)) => {
+                    let message = stderr.unwrap_or_default();
+                    eprintln!("Graphviz failed: {}", message);
+                    error_html("Graphviz", &message)
+                }
+                Err(e) => {
+                    eprintln!("Graphviz failed: {}", e);
+                    error_html("Graphviz", &e.to_string())
+                }
+            };
+            cache_graphviz(&source, html);
+            GRAPHVIZ_RUNNING.set(false);
+            ON_RENDERED.with(|on_rendered| {
+                if let Some(callback) = on_rendered.borrow().as_ref() {
+                    callback();
+                }
+            });
+        },
+    );
+    Ok(())
+}
+
+fn cache_graphviz(source: &str, html: String) {
+    GRAPHVIZ_CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        if cache.len() >= GRAPHVIZ_CACHE_SIZE {
+            cache.pop_front();
+        }
+        cache.push_back((source.to_string(), html));
+    });
+}
+
+/// Script that renders Mermaid diagrams in place, using the copy bundled in
+/// the gresource. Failures are shown where the diagram would have been.
+pub fn mermaid_script(dark: bool) -> String {
+    let library = match gio::resources_lookup_data(MERMAID_RESOURCE, gio::ResourceLookupFlags::NONE)
+    {
+        // Keep the library from closing its own <script> element early
+        Ok(bytes) => String::from_utf8_lossy(&bytes).replace("</script", "<\\/script"),
+        Err(e) => {
+            eprintln!("Mermaid renderer not available: {}", e);
+            return format!(
+                "<script>document.querySelectorAll('.mermaid').forEach(el => {{ el.outerHTML = {}; }});</script>",
+                js_string(&error_html(
+                    "Mermaid",
+                    "The bundled Mermaid renderer is missing from this build"
+                ))
+            );
+        }
+    };
+
+    format!(
+        r#"<script>{}</script>
+<script>
+mermaid.initialize({{ startOnLoad: false, securityLevel: "strict", theme: "{}" }});
+document.querySelectorAll(".mermaid").forEach((el, index) => {{
+  mermaid.render("mermaid-diagram-" + index, el.textContent)
+    .then(({{ svg }}) => {{ el.innerHTML = svg; }})
+    .catch(error => {{
+      const box = document.createElement("div");
+      box.className = "diagram-error";
+      const title = document.createElement("strong");
+      title.textContent = "Mermaid diagram error";
+      const message = document.createElement("pre");
+      message.textContent = String(error && error.message ? error.message : error);
+      box.append(title, message);
+      el.replaceWith(box);
+      // Mermaid leaves its scratch element behind when rendering fails
+      document.getElementById("dmermaid-diagram-" + index)?.remove();
+    }});
+}});
+</script>"#,
+        library,
+        if dark { "dark" } else { "default" }
+    )
+}
+
+fn js_string(value: &str) -> String {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('\'', "\\'")
+        .replace('\n', "\\n")
+        .replace("</", "<\\/");
+    format!("'{}'", escaped)
+}
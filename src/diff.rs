@@ -0,0 +1,133 @@
+// Above this many line pairs the changed region is reported as replaced
+// outright instead of running the quadratic diff
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+/// One line of a line-by-line diff, with zero-based line indexes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffLine {
+    /// (old line, new line)
+    Same(usize, usize),
+    /// Line of the old text that is not in the new one
+    Removed(usize),
+    /// Line of the new text that is not in the old one
+    Added(usize),
+}
+
+/// Diff two texts line by line using the longest common subsequence
+pub fn line_diff(old: &str, new: &str) -> Vec<DiffLine> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    // Unchanged lines at either end don't need the full comparison
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+
+    let mut diff: Vec<DiffLine> = (0..prefix).map(|i| DiffLine::Same(i, i)).collect();
+
+    if old_middle.len() * new_middle.len() > MAX_DIFF_CELLS {
+        diff.extend((0..old_middle.len()).map(|i| DiffLine::Removed(prefix + i)));
+        diff.extend((0..new_middle.len()).map(|j| DiffLine::Added(prefix + j)));
+    } else {
+        // lengths[i][j]: common subsequence length of old_middle[i..] and new_middle[j..]
+        let (rows, columns) = (old_middle.len(), new_middle.len());
+        let mut lengths = vec![0u32; (rows + 1) * (columns + 1)];
+        let at = |i: usize, j: usize| i * (columns + 1) + j;
+        for i in (0..rows).rev() {
+            for j in (0..columns).rev() {
+                lengths[at(i, j)] = if old_middle[i] == new_middle[j] {
+                    lengths[at(i + 1, j + 1)] + 1
+                } else {
+                    lengths[at(i + 1, j)].max(lengths[at(i, j + 1)])
+                };
+            }
+        }
+
+        let (mut i, mut j) = (0, 0);
+        while i < rows || j < columns {
+            if i < rows && j < columns && old_middle[i] == new_middle[j] {
+                diff.push(DiffLine::Same(prefix + i, prefix + j));
+                i += 1;
+                j += 1;
+            } else if j < columns && (i == rows || lengths[at(i, j + 1)] >= lengths[at(i + 1, j)]) {
+                diff.push(DiffLine::Added(prefix + j));
+                j += 1;
+            } else {
+                diff.push(DiffLine::Removed(prefix + i));
+                i += 1;
+            }
+        }
+    }
+
+    let old_end = old.len() - suffix;
+    let new_end = new.len() - suffix;
+    diff.extend((0..suffix).map(|k| DiffLine::Same(old_end + k, new_end + k)));
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use DiffLine::*;
+
+    #[test]
+    fn identical_texts() {
+        assert_eq!(line_diff("a\nb\n", "a\nb\n"), vec![Same(0, 0), Same(1, 1)]);
+        assert!(line_diff("", "").is_empty());
+    }
+
+    #[test]
+    fn added_and_removed_lines() {
+        assert_eq!(
+            line_diff("a\nc", "a\nb\nc"),
+            vec![Same(0, 0), Added(1), Same(1, 2)]
+        );
+        assert_eq!(
+            line_diff("a\nb\nc", "a\nc"),
+            vec![Same(0, 0), Removed(1), Same(2, 1)]
+        );
+        assert_eq!(line_diff("", "a\nb"), vec![Added(0), Added(1)]);
+        assert_eq!(line_diff("a\nb", ""), vec![Removed(0), Removed(1)]);
+    }
+
+    #[test]
+    fn changed_line_is_added_and_removed() {
+        assert_eq!(
+            line_diff("a\nb\nc", "a\nx\nc"),
+            vec![Same(0, 0), Added(1), Removed(1), Same(2, 2)]
+        );
+    }
+
+    #[test]
+    fn keeps_the_longest_common_lines() {
+        let diff = line_diff("a\nb\nc\nd\ne", "b\nx\nd\ne\nf");
+        let same: Vec<DiffLine> = diff
+            .iter()
+            .copied()
+            .filter(|line| matches!(line, Same(..)))
+            .collect();
+        assert_eq!(same, vec![Same(1, 0), Same(3, 2), Same(4, 3)]);
+        assert_eq!(
+            diff.iter()
+                .filter(|line| matches!(line, Removed(_)))
+                .count(),
+            2
+        );
+        assert_eq!(
+            diff.iter().filter(|line| matches!(line, Added(_))).count(),
+            2
+        );
+    }
+
+    #[test]
+    fn repeated_lines_at_both_ends() {
+        assert_eq!(line_diff("a\na", "a"), vec![Same(0, 0), Removed(1)]);
+        assert_eq!(line_diff("a", "a\na"), vec![Same(0, 0), Added(1)]);
+    }
+}
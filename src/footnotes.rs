@@ -0,0 +1,371 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::markdown;
+
+/// A `[^label]` footnote reference, with the byte range of the brackets
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reference {
+    pub label: String,
+    pub range: Range<usize>,
+}
+
+/// A `[^label]: text` footnote definition. `marker` is the byte range of
+/// `[^label]:` and `range` covers the whole definition, indented
+/// continuation lines included.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Definition {
+    pub label: String,
+    pub marker: Range<usize>,
+    pub range: Range<usize>,
+}
+
+/// The footnote references and definitions of a document, outside code blocks
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Footnotes {
+    pub references: Vec<Reference>,
+    pub definitions: Vec<Definition>,
+}
+
+impl Footnotes {
+    pub fn scan(text: &str) -> Self {
+        let mut footnotes = Footnotes::default();
+        let lines = markdown::prose_lines(text);
+        let mut index = 0;
+        while index < lines.len() {
+            let (offset, line) = lines[index];
+            let Some(label) = definition_label(line) else {
+                footnotes.references.extend(references_in(line, offset, 0));
+                index += 1;
+                continue;
+            };
+
+            // Continuation lines are indented; blank lines only count when
+            // more indented text follows
+            let marker_start = offset + (line.len() - line.trim_start().len());
+            let marker_end = marker_start + label.len() + 4;
+            let content_start = marker_end - offset;
+            footnotes
+                .references
+                .extend(references_in(line, offset, content_start));
+            let mut end = offset + line.len();
+            let mut next = index + 1;
+            while next < lines.len() {
+                let (next_offset, next_line) = lines[next];
+                if next_line.trim().is_empty() {
+                    next += 1;
+                    continue;
+                }
+                if !next_line.starts_with("    ") && !next_line.starts_with('\t') {
+                    break;
+                }
+                footnotes
+                    .references
+                    .extend(references_in(next_line, next_offset, 0));
+                end = next_offset + next_line.len();
+                next += 1;
+                index = next - 1;
+            }
+            footnotes.definitions.push(Definition {
+                label: label.to_string(),
+                marker: marker_start..marker_end,
+                range: offset..end,
+            });
+            index += 1;
+        }
+        footnotes
+    }
+
+    pub fn definition(&self, label: &str) -> Option<&Definition> {
+        self.definitions.iter().find(|d| d.label == label)
+    }
+
+    /// References without a definition
+    pub fn orphaned_references(&self) -> Vec<&Reference> {
+        self.references
+            .iter()
+            .filter(|r| self.definition(&r.label).is_none())
+            .collect()
+    }
+
+    /// Definitions that nothing refers to
+    pub fn orphaned_definitions(&self) -> Vec<&Definition> {
+        self.definitions
+            .iter()
+            .filter(|d| !self.references.iter().any(|r| r.label == d.label))
+            .collect()
+    }
+
+    /// The number after the highest numeric label
+    pub fn next_number(&self) -> u32 {
+        self.references
+            .iter()
+            .map(|r| &r.label)
+            .chain(self.definitions.iter().map(|d| &d.label))
+            .filter_map(|label| label.parse::<u32>().ok())
+            .max()
+            .map_or(1, |n| n + 1)
+    }
+
+    pub fn reference_at(&self, offset: usize) -> Option<&Reference> {
+        self.references
+            .iter()
+            .find(|r| r.range.start <= offset && offset <= r.range.end)
+    }
+
+    pub fn definition_at(&self, offset: usize) -> Option<&Definition> {
+        self.definitions
+            .iter()
+            .find(|d| d.range.start <= offset && offset <= d.range.end)
+    }
+}
+
+/// Number footnotes 1, 2, 3... in the order they are first referenced, with
+/// unreferenced definitions last. Definitions grouped at the end of the
+/// document are also put in that order. Returns the edits as byte ranges of
+/// `text` and their replacements, last first so they can be applied in turn.
+pub fn renumber(text: &str) -> Vec<(Range<usize>, String)> {
+    let footnotes = Footnotes::scan(text);
+    let mut numbers: HashMap<&str, usize> = HashMap::new();
+    let labels = footnotes
+        .references
+        .iter()
+        .map(|r| r.label.as_str())
+        .chain(footnotes.definitions.iter().map(|d| d.label.as_str()));
+    for label in labels {
+        let next = numbers.len() + 1;
+        numbers.entry(label).or_insert(next);
+    }
+
+    let mut edits: Vec<(Range<usize>, String)> = footnotes
+        .references
+        .iter()
+        .map(|r| (r.range.clone(), format!("[^{}]", numbers[r.label.as_str()])))
+        .chain(footnotes.definitions.iter().map(|d| {
+            (
+                d.marker.clone(),
+                format!("[^{}]:", numbers[d.label.as_str()]),
+            )
+        }))
+        .filter(|(range, replacement)| text[range.clone()] != *replacement)
+        .collect();
+
+    if let Some(tail) = sorted_trailing_definitions(text, &footnotes, &numbers, &edits) {
+        edits.retain(|(range, _)| range.start < tail.0.start);
+        edits.push(tail);
+    }
+    edits.sort_by_key(|(range, _)| std::cmp::Reverse(range.start));
+    edits
+}
+
+/// When nothing but definitions and blank lines follows the first definition
+/// and they are out of order, an edit replacing them with the renumbered
+/// definitions sorted by number
+fn sorted_trailing_definitions(
+    text: &str,
+    footnotes: &Footnotes,
+    numbers: &HashMap<&str, usize>,
+    edits: &[(Range<usize>, String)],
+) -> Option<(Range<usize>, String)> {
+    let tail_start = footnotes.definitions.first()?.range.start;
+    let mut covered = tail_start;
+    let mut spaced = false;
+    for definition in &footnotes.definitions {
+        let gap = &text[covered..definition.range.start];
+        if !gap.trim().is_empty() {
+            return None;
+        }
+        spaced |= !gap.is_empty();
+        covered = definition.range.end;
+    }
+    if !text[covered..].trim().is_empty() {
+        return None;
+    }
+
+    let mut definitions: Vec<&Definition> = footnotes.definitions.iter().collect();
+    definitions.sort_by_key(|d| numbers[d.label.as_str()]);
+    if definitions
+        .iter()
+        .zip(&footnotes.definitions)
+        .all(|(sorted, original)| sorted.range == original.range)
+    {
+        return None;
+    }
+
+    // Each definition with the new labels of its marker and references
+    let blocks: Vec<String> = definitions
+        .iter()
+        .map(|d| {
+            let mut inside: Vec<&(Range<usize>, String)> = edits
+                .iter()
+                .filter(|(range, _)| d.range.start <= range.start && range.end <= d.range.end)
+                .collect();
+            inside.sort_by_key(|(range, _)| range.start);
+            let mut block = String::new();
+            let mut last = d.range.start;
+            for (range, replacement) in inside {
+                block.push_str(&text[last..range.start]);
+                block.push_str(replacement);
+                last = range.end;
+            }
+            block.push_str(&text[last..d.range.end]);
+            block.trim_end().to_string()
+        })
+        .collect();
+    // Keep blank lines between definitions if there were any
+    let separator = if spaced { "\n\n" } else { "\n" };
+    Some((
+        tail_start..text.len(),
+        format!("{}\n", blocks.join(separator)),
+    ))
+}
+
+/// The label of a `[^label]:` definition starting this line
+fn definition_label(line: &str) -> Option<&str> {
+    let trimmed = line.trim_start();
+    if line.len() - trimmed.len() > 3 {
+        return None;
+    }
+    let label = parse_label(trimmed)?;
+    trimmed[label.len() + 3..].starts_with(':').then_some(label)
+}
+
+/// References in `line` from byte `from` on, with ranges offset by `offset`
+fn references_in(line: &str, offset: usize, from: usize) -> Vec<Reference> {
+    let mut references = Vec::new();
+    let mut position = from;
+    while let Some(start) = line[position..].find("[^").map(|i| position + i) {
+        match parse_label(&line[start..]) {
+            Some(label) => {
+                let end = start + label.len() + 3;
+                references.push(Reference {
+                    label: label.to_string(),
+                    range: offset + start..offset + end,
+                });
+                position = end;
+            }
+            None => position = start + 2,
+        }
+    }
+    references
+}
+
+/// The label of a `[^label]` at the start of `text`
+fn parse_label(text: &str) -> Option<&str> {
+    let rest = text.strip_prefix("[^")?;
+    let end = rest.find(']')?;
+    let label = &rest[..end];
+    let valid = !label.is_empty() && !label.contains(|c: char| c.is_whitespace() || c == '[');
+    valid.then_some(label)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn renumbered(text: &str) -> String {
+        let mut out = text.to_string();
+        for (range, replacement) in renumber(text) {
+            out.replace_range(range, &replacement);
+        }
+        out
+    }
+
+    #[test]
+    fn scans_references_and_definitions() {
+        let text = "Text[^a] and [^b].\n\n[^a]: First\n    more [^b]\n\nAfter\n";
+        let footnotes = Footnotes::scan(text);
+        let labels: Vec<&str> = footnotes
+            .references
+            .iter()
+            .map(|r| r.label.as_str())
+            .collect();
+        assert_eq!(labels, vec!["a", "b", "b"]);
+        assert_eq!(&text[footnotes.references[0].range.clone()], "[^a]");
+
+        let definition = footnotes.definition("a").unwrap();
+        assert_eq!(&text[definition.marker.clone()], "[^a]:");
+        assert_eq!(
+            &text[definition.range.clone()],
+            "[^a]: First\n    more [^b]\n"
+        );
+        assert!(footnotes.definition("b").is_none());
+    }
+
+    #[test]
+    fn skips_code_and_invalid_labels() {
+        let text = "```\n[^code]\n```\n`[^x]` [^] [^has space] [^ok]\n";
+        let labels: Vec<String> = Footnotes::scan(text)
+            .references
+            .into_iter()
+            .map(|r| r.label)
+            .collect();
+        assert!(labels.contains(&"ok".to_string()));
+        assert!(!labels.contains(&"code".to_string()));
+        assert!(!labels.contains(&String::new()));
+        assert!(!labels.contains(&"has space".to_string()));
+    }
+
+    #[test]
+    fn finds_orphans() {
+        let footnotes = Footnotes::scan("A[^1] B[^2]\n\n[^1]: One\n[^3]: Three\n");
+        let references: Vec<&str> = footnotes
+            .orphaned_references()
+            .iter()
+            .map(|r| r.label.as_str())
+            .collect();
+        let definitions: Vec<&str> = footnotes
+            .orphaned_definitions()
+            .iter()
+            .map(|d| d.label.as_str())
+            .collect();
+        assert_eq!(references, vec!["2"]);
+        assert_eq!(definitions, vec!["3"]);
+        assert_eq!(footnotes.next_number(), 4);
+        assert_eq!(Footnotes::scan("No notes").next_number(), 1);
+    }
+
+    #[test]
+    fn finds_footnotes_at_offsets() {
+        let text = "A[^1]\n\n[^1]: One\n";
+        let footnotes = Footnotes::scan(text);
+        assert_eq!(footnotes.reference_at(2).unwrap().label, "1");
+        assert!(footnotes.reference_at(6).is_none());
+        assert_eq!(footnotes.definition_at(10).unwrap().label, "1");
+    }
+
+    #[test]
+    fn renumbers_in_order_of_reference() {
+        let text = "B[^b] A[^a] B again[^b]\n\n[^a]: A\n\n[^b]: B\n";
+        assert_eq!(
+            renumbered(text),
+            "B[^1] A[^2] B again[^1]\n\n[^1]: B\n\n[^2]: A\n"
+        );
+    }
+
+    #[test]
+    fn unreferenced_definitions_go_last() {
+        let text = "X[^x]\n[^unused]: U\n[^x]: X\n";
+        assert_eq!(renumbered(text), "X[^1]\n[^1]: X\n[^2]: U\n");
+    }
+
+    #[test]
+    fn only_changed_labels_are_edited() {
+        assert!(renumber("A[^1] B[^2]\n\n[^1]: One\n[^2]: Two\n").is_empty());
+
+        let text = "A[^1] B[^7]\n\nMiddle\n\n[^1]: One\n\nMore\n\n[^7]: Seven\n";
+        let edits = renumber(text);
+        assert_eq!(edits.len(), 2);
+        assert!(edits[0].0.start > edits[1].0.start);
+        assert_eq!(
+            renumbered(text),
+            "A[^1] B[^2]\n\nMiddle\n\n[^1]: One\n\nMore\n\n[^2]: Seven\n"
+        );
+    }
+
+    #[test]
+    fn renumbers_references_inside_moved_definitions() {
+        let text = "A[^b] B[^a]\n\n[^a]: See [^b]\n[^b]: B\n";
+        assert_eq!(renumbered(text), "A[^1] B[^2]\n\n[^1]: B\n[^2]: See [^1]\n");
+    }
+}
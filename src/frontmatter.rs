@@ -0,0 +1,424 @@
+/// Metadata from a YAML front matter block at the top of a document. Only
+/// the common `title`, `date` and `tags` keys are modelled; other entries are
+/// kept verbatim so editing the known fields doesn't lose them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FrontMatter {
+    pub title: Option<String>,
+    pub date: Option<String>,
+    pub tags: Vec<String>,
+    /// Lines of entries other than the known keys, in their original order
+    pub other: Vec<String>,
+}
+
+impl FrontMatter {
+    /// Parse the front matter at the start of a document, if it has any
+    pub fn parse(text: &str) -> Option<Self> {
+        let (yaml, _) = split(text)?;
+        let mut front_matter = Self::default();
+
+        let lines: Vec<&str> = yaml.lines().collect();
+        let mut index = 0;
+        while index < lines.len() {
+            let line = lines[index];
+            index += 1;
+
+            // Continuation lines (indented, list items, blank) belong to the
+            // entry before them
+            let Some((key, value)) = top_level_entry(line) else {
+                front_matter.other.push(line.to_string());
+                continue;
+            };
+            let mut continuation = Vec::new();
+            while index < lines.len() && top_level_entry(lines[index]).is_none() {
+                continuation.push(lines[index]);
+                index += 1;
+            }
+
+            match key {
+                "title" if continuation.is_empty() => {
+                    front_matter.title = Some(unquote(value)).filter(|t| !t.is_empty())
+                }
+                "date" if continuation.is_empty() => {
+                    front_matter.date = Some(unquote(value)).filter(|d| !d.is_empty())
+                }
+                "tags" => front_matter.tags = parse_tags(value, &continuation),
+                _ => {
+                    front_matter.other.push(line.to_string());
+                    front_matter
+                        .other
+                        .extend(continuation.iter().map(|l| l.to_string()));
+                }
+            }
+        }
+
+        Some(front_matter)
+    }
+
+    /// Keys of the entries that aren't modelled
+    pub fn other_keys(&self) -> Vec<&str> {
+        self.other
+            .iter()
+            .filter_map(|line| top_level_entry(line))
+            .map(|(key, _)| key)
+            .collect()
+    }
+
+    /// Whether no metadata is set at all
+    pub fn is_empty(&self) -> bool {
+        self.title.is_none()
+            && self.date.is_none()
+            && self.tags.is_empty()
+            && self.other.iter().all(|line| line.trim().is_empty())
+    }
+
+    /// Format as a complete front matter block, including the `---` fences
+    pub fn to_yaml(&self) -> String {
+        let mut out = String::from("---\n");
+        if let Some(ref title) = self.title {
+            out.push_str(&format!("title: {}\n", quote(title)));
+        }
+        if let Some(ref date) = self.date {
+            out.push_str(&format!("date: {}\n", quote(date)));
+        }
+        if !self.tags.is_empty() {
+            let tags: Vec<String> = self.tags.iter().map(|tag| quote(tag)).collect();
+            out.push_str(&format!("tags: [{}]\n", tags.join(", ")));
+        }
+        for line in &self.other {
+            out.push_str(line);
+            out.push('\n');
+        }
+        out.push_str("---\n");
+        out
+    }
+}
+
+/// Set one top-level entry of the front matter in `text`, leaving the other
+/// lines as they were. `entry` is the entry's complete text, such as
+/// `title: Notes\n`; None removes it. A new entry goes at the end of the
+/// block, which is created when needed and dropped once it is empty.
+pub fn set_entry(text: &str, key: &str, entry: Option<&str>) -> String {
+    let Some((yaml, body)) = split(text) else {
+        return match entry {
+            Some(entry) => format!("---\n{}---\n{}", entry, text),
+            None => text.to_string(),
+        };
+    };
+    let yaml_start = if text.starts_with("---\r\n") { 5 } else { 4 };
+    let yaml_end = yaml_start + yaml.len();
+
+    let mut new_yaml = yaml.to_string();
+    match (entry_range(yaml, key), entry) {
+        (Some(range), entry) => new_yaml.replace_range(range, entry.unwrap_or_default()),
+        (None, Some(entry)) => {
+            if !new_yaml.is_empty() && !new_yaml.ends_with('\n') {
+                new_yaml.push('\n');
+            }
+            new_yaml.push_str(entry);
+        }
+        (None, None) => return text.to_string(),
+    }
+
+    if new_yaml.lines().all(|line| line.trim().is_empty()) {
+        return body.to_string();
+    }
+    format!("{}{}{}", &text[..yaml_start], new_yaml, &text[yaml_end..])
+}
+
+/// A `key: value` entry, quoted as needed
+pub fn scalar_entry(key: &str, value: &str) -> String {
+    format!("{}: {}\n", key, quote(value))
+}
+
+/// A `tags` entry, written as a block list when the document already uses
+/// one and inline otherwise
+pub fn tags_entry(text: &str, tags: &[String]) -> String {
+    let yaml = split(text).map(|(yaml, _)| yaml).unwrap_or_default();
+    let item_prefix = entry_range(yaml, "tags").and_then(|range| {
+        let mut lines = yaml[range].lines();
+        let (_, value) = top_level_entry(lines.next()?)?;
+        let item = lines.find(|line| line.trim_start().starts_with('-'))?;
+        let dash = item.find('-')?;
+        let spaces = item[dash + 1..].len() - item[dash + 1..].trim_start().len();
+        value
+            .is_empty()
+            .then(|| format!("{}-{}", &item[..dash], " ".repeat(spaces.max(1))))
+    });
+    let tags: Vec<String> = tags.iter().map(|tag| quote(tag)).collect();
+    match item_prefix {
+        Some(prefix) => {
+            let items: String = tags
+                .iter()
+                .map(|tag| format!("{}{}\n", prefix, tag))
+                .collect();
+            format!("tags:\n{}", items)
+        }
+        None => format!("tags: [{}]\n", tags.join(", ")),
+    }
+}
+
+/// Byte range of a top-level entry in front matter YAML: its line and the
+/// indented or list lines that continue it
+fn entry_range(yaml: &str, key: &str) -> Option<std::ops::Range<usize>> {
+    let mut offset = 0;
+    let mut range: Option<std::ops::Range<usize>> = None;
+    for line in yaml.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+        let entry = top_level_entry(line.trim_end_matches(['\r', '\n']));
+        match range {
+            None => {
+                if entry.is_some_and(|(entry_key, _)| entry_key == key) {
+                    range = Some(start..offset);
+                }
+            }
+            Some(_) if entry.is_some() => break,
+            // Blank lines and comments only belong to the entry when more of
+            // it follows
+            Some(ref mut range) => {
+                if !line.trim().is_empty() && !line.starts_with('#') {
+                    range.end = offset;
+                }
+            }
+        }
+    }
+    range
+}
+
+/// Split a document into its front matter YAML and the Markdown after it.
+/// The block must open with `---` on the first line and close with `---` or
+/// `...` on a line of its own.
+pub fn split(text: &str) -> Option<(&str, &str)> {
+    let rest = text
+        .strip_prefix("---\n")
+        .or_else(|| text.strip_prefix("---\r\n"))?;
+
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        let trimmed = line.trim_end();
+        if trimmed == "---" || trimmed == "..." {
+            return Some((&rest[..offset], &rest[offset + line.len()..]));
+        }
+        offset += line.len();
+    }
+    None
+}
+
+/// Byte length of the front matter block, fences included, or 0 without one
+pub fn block_len(text: &str) -> usize {
+    match split(text) {
+        Some((_, body)) => text.len() - body.len(),
+        None => 0,
+    }
+}
+
+/// The Markdown body with any front matter removed
+pub fn strip(text: &str) -> &str {
+    split(text).map(|(_, body)| body).unwrap_or(text)
+}
+
+/// Split a comma-separated tag list, as typed into the properties form
+pub fn tags_from_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(unquote)
+        .filter(|tag| !tag.is_empty())
+        .collect()
+}
+
+/// A `key: value` line at the top level of the YAML mapping
+fn top_level_entry(line: &str) -> Option<(&str, &str)> {
+    if line.starts_with([' ', '\t', '-', '#']) {
+        return None;
+    }
+    let (key, value) = line.split_once(':')?;
+    let key = key.trim();
+    if key.is_empty() || key.contains(' ') {
+        return None;
+    }
+    Some((key, value.trim()))
+}
+
+/// Tags written inline (`[a, b]` or `a, b`) or as a block list of `- a` lines
+fn parse_tags(value: &str, continuation: &[&str]) -> Vec<String> {
+    let inline = value
+        .strip_prefix('[')
+        .and_then(|v| v.strip_suffix(']'))
+        .unwrap_or(value);
+    let mut tags = tags_from_list(inline);
+
+    tags.extend(
+        continuation
+            .iter()
+            .filter_map(|line| line.trim().strip_prefix('-'))
+            .map(unquote)
+            .filter(|tag| !tag.is_empty()),
+    );
+    tags
+}
+
+fn unquote(value: &str) -> String {
+    let value = value.trim();
+    for quote in ['"', '\''] {
+        if let Some(inner) = value
+            .strip_prefix(quote)
+            .and_then(|v| v.strip_suffix(quote))
+        {
+            return match quote {
+                '"' => unescape(inner),
+                _ => inner.replace("''", "'"),
+            };
+        }
+    }
+    value.to_string()
+}
+
+/// Undo the escapes in a double-quoted scalar
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('t') => unescaped.push('\t'),
+            Some(other) => unescaped.push(other),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+/// Quote a scalar only when YAML would otherwise misread it
+fn quote(value: &str) -> String {
+    let needs_quotes = value.is_empty()
+        || value != value.trim()
+        || value.contains(": ")
+        || value.contains(" #")
+        || value.contains(['\n', '\t'])
+        || value.starts_with([
+            '[', ']', '{', '}', ',', '&', '*', '!', '|', '>', '\'', '"', '%', '@', '`', '#', '-',
+            '?', ':',
+        ]);
+    if needs_quotes {
+        let escaped = value
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n")
+            .replace('\t', "\\t");
+        format!("\"{}\"", escaped)
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_known_keys_and_keeps_others() {
+        let text =
+            "---\ntitle: Notes\ndate: 2024-01-02\ntags: [a, \"b c\"]\nauthor: me\n---\nBody\n";
+        let front_matter = FrontMatter::parse(text).unwrap();
+        assert_eq!(front_matter.title.as_deref(), Some("Notes"));
+        assert_eq!(front_matter.date.as_deref(), Some("2024-01-02"));
+        assert_eq!(front_matter.tags, vec!["a", "b c"]);
+        assert_eq!(front_matter.other, vec!["author: me"]);
+        assert_eq!(front_matter.other_keys(), vec!["author"]);
+    }
+
+    #[test]
+    fn parses_block_list_tags() {
+        let text = "---\ntags:\n  - one\n  - 'two'\n---\n";
+        let front_matter = FrontMatter::parse(text).unwrap();
+        assert_eq!(front_matter.tags, vec!["one", "two"]);
+    }
+
+    #[test]
+    fn no_front_matter() {
+        assert_eq!(FrontMatter::parse("# Title\n"), None);
+        assert_eq!(FrontMatter::parse("---\nunclosed\n"), None);
+        assert_eq!(block_len("# Title\n"), 0);
+        assert_eq!(strip("# Title\n"), "# Title\n");
+    }
+
+    #[test]
+    fn dots_close_the_block() {
+        let text = "---\ntitle: A\n...\nBody\n";
+        assert_eq!(split(text), Some(("title: A\n", "Body\n")));
+        assert_eq!(block_len(text), text.len() - "Body\n".len());
+    }
+
+    #[test]
+    fn to_yaml_round_trips() {
+        let front_matter = FrontMatter {
+            title: Some("A: \"quoted\" title".to_string()),
+            date: Some("2024-01-02".to_string()),
+            tags: vec!["plain".to_string(), "-dash".to_string()],
+            other: vec!["author: me".to_string()],
+        };
+        let text = format!("{}Body\n", front_matter.to_yaml());
+        assert_eq!(FrontMatter::parse(&text), Some(front_matter));
+        assert_eq!(strip(&text), "Body\n");
+    }
+
+    #[test]
+    fn quotes_only_when_needed() {
+        assert_eq!(quote("plain"), "plain");
+        assert_eq!(quote("a: b"), "\"a: b\"");
+        assert_eq!(quote("#tag"), "\"#tag\"");
+        assert_eq!(quote("two\nlines"), "\"two\\nlines\"");
+        assert_eq!(unquote("\"two\\nlines\""), "two\nlines");
+        assert_eq!(unquote("'it''s'"), "it's");
+    }
+
+    #[test]
+    fn set_entry_replaces_in_place() {
+        let text = "---\n# comment\ntags:\n  - a\ntitle: Old\nauthor: me\n...\nBody\n";
+        let new_text = set_entry(text, "title", Some("title: New\n"));
+        assert_eq!(
+            new_text,
+            "---\n# comment\ntags:\n  - a\ntitle: New\nauthor: me\n...\nBody\n"
+        );
+    }
+
+    #[test]
+    fn set_entry_replaces_continuation_lines() {
+        let text = "---\ntitle: |\n  two\n  lines\ndate: 2024\n---\n";
+        let new_text = set_entry(text, "title", Some(&scalar_entry("title", "One")));
+        assert_eq!(new_text, "---\ntitle: One\ndate: 2024\n---\n");
+    }
+
+    #[test]
+    fn set_entry_appends_and_removes() {
+        let text = "---\ntitle: A\n---\nBody\n";
+        let new_text = set_entry(text, "date", Some("date: 2024\n"));
+        assert_eq!(new_text, "---\ntitle: A\ndate: 2024\n---\nBody\n");
+        let new_text = set_entry(&new_text, "title", None);
+        assert_eq!(new_text, "---\ndate: 2024\n---\nBody\n");
+        assert_eq!(set_entry(&new_text, "date", None), "Body\n");
+    }
+
+    #[test]
+    fn set_entry_creates_block() {
+        assert_eq!(
+            set_entry("Body\n", "title", Some("title: A\n")),
+            "---\ntitle: A\n---\nBody\n"
+        );
+        assert_eq!(set_entry("Body\n", "title", None), "Body\n");
+    }
+
+    #[test]
+    fn tags_entry_keeps_list_style() {
+        let tags = vec!["x".to_string(), "y".to_string()];
+        let block = "---\ntags:\n    -  a\n---\n";
+        assert_eq!(tags_entry(block, &tags), "tags:\n    -  x\n    -  y\n");
+        let inline = "---\ntags: [a]\n---\n";
+        assert_eq!(tags_entry(inline, &tags), "tags: [x, y]\n");
+        assert_eq!(tags_entry("Body\n", &tags), "tags: [x, y]\n");
+    }
+}
@@ -0,0 +1,162 @@
+use git2::Repository;
+use std::path::{Path, PathBuf};
+
+use crate::diff::{self, DiffLine};
+
+/// How a line of the working text differs from HEAD
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineChange {
+    Added,
+    Modified,
+    /// Lines of HEAD were deleted just above this one
+    RemovedAbove,
+}
+
+/// The repository a file is in, if any
+pub fn repository(path: &Path) -> Option<Repository> {
+    let repo = Repository::discover(path.parent()?).ok()?;
+    // Bare repositories have no working tree to compare against
+    repo.workdir()?;
+    Some(repo)
+}
+
+/// Path of a file relative to the repository's working tree
+fn relative_path(repo: &Repository, path: &Path) -> Option<PathBuf> {
+    let workdir = repo.workdir()?.canonicalize().ok()?;
+    let path = path.canonicalize().ok()?;
+    path.strip_prefix(workdir).ok().map(Path::to_path_buf)
+}
+
+/// The file's contents in the HEAD commit, or None if it isn't committed yet
+pub fn head_text(repo: &Repository, path: &Path) -> Option<String> {
+    let relative = relative_path(repo, path)?;
+    let tree = repo.head().ok()?.peel_to_tree().ok()?;
+    let entry = tree.get_path(&relative).ok()?;
+    let blob = entry.to_object(repo).ok()?.peel_to_blob().ok()?;
+    Some(String::from_utf8_lossy(blob.content()).into_owned())
+}
+
+/// The current branch name, or a short commit id when HEAD is detached
+pub fn branch_name(repo: &Repository) -> Option<String> {
+    let head = repo.head().ok()?;
+    if head.is_branch() {
+        return head.shorthand().map(str::to_string);
+    }
+    let id = head.target()?.to_string();
+    Some(id[..7.min(id.len())].to_string())
+}
+
+/// Changed lines of the working text against HEAD, by zero-based line. Added
+/// lines that replace removed ones count as modified.
+pub fn line_changes(head: &str, text: &str) -> Vec<(usize, LineChange)> {
+    let mut changes = Vec::new();
+    let mut removed = 0;
+    let mut added = Vec::new();
+    let mut flush = |removed: &mut usize, added: &mut Vec<usize>, next_line: usize| {
+        if added.is_empty() && *removed > 0 {
+            changes.push((next_line, LineChange::RemovedAbove));
+        }
+        for (index, line) in added.drain(..).enumerate() {
+            let change = if index < *removed {
+                LineChange::Modified
+            } else {
+                LineChange::Added
+            };
+            changes.push((line, change));
+        }
+        *removed = 0;
+    };
+
+    for line in diff::line_diff(head, text) {
+        match line {
+            DiffLine::Removed(_) => removed += 1,
+            DiffLine::Added(line) => added.push(line),
+            DiffLine::Same(_, line) => flush(&mut removed, &mut added, line),
+        }
+    }
+    flush(&mut removed, &mut added, text.lines().count());
+    changes
+}
+
+/// Add the file's current contents to the index
+pub fn stage(repo: &Repository, path: &Path) -> Result<(), git2::Error> {
+    let relative = relative_path(repo, path)
+        .ok_or_else(|| git2::Error::from_str("File is outside the repository"))?;
+    let mut index = repo.index()?;
+    index.add_path(&relative)?;
+    index.write()
+}
+
+/// Commit the file as it is staged on top of HEAD, using the author from the
+/// git config. Other staged changes stay in the index for a later commit.
+pub fn commit(repo: &Repository, path: &Path, message: &str) -> Result<git2::Oid, git2::Error> {
+    let relative = relative_path(repo, path)
+        .ok_or_else(|| git2::Error::from_str("File is outside the repository"))?;
+    let entry = repo
+        .index()?
+        .get_path(&relative, 0)
+        .ok_or_else(|| git2::Error::from_str("File is not staged"))?;
+    let signature = repo.signature()?;
+
+    // A new repository has no HEAD yet, so its first commit has no parent
+    let parent = repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+    let mut index = git2::Index::new()?;
+    if let Some(ref parent) = parent {
+        index.read_tree(&parent.tree()?)?;
+    }
+    index.add(&entry)?;
+    let tree = repo.find_tree(index.write_tree_to(repo)?)?;
+
+    let parents: Vec<&git2::Commit> = parent.iter().collect();
+    repo.commit(
+        Some("HEAD"),
+        &signature,
+        &signature,
+        message,
+        &tree,
+        &parents,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unchanged_text_has_no_changes() {
+        assert_eq!(line_changes("a\nb\n", "a\nb\n"), Vec::new());
+    }
+
+    #[test]
+    fn added_lines() {
+        assert_eq!(
+            line_changes("a\nb\n", "a\nnew\nb\nend\n"),
+            vec![(1, LineChange::Added), (3, LineChange::Added)]
+        );
+    }
+
+    #[test]
+    fn replaced_lines_are_modified() {
+        assert_eq!(
+            line_changes("a\nb\nc\n", "a\nB\nextra\nc\n"),
+            vec![(1, LineChange::Modified), (2, LineChange::Added)]
+        );
+    }
+
+    #[test]
+    fn removed_lines_mark_the_line_below() {
+        assert_eq!(
+            line_changes("a\nb\nc\nd\n", "a\nd\n"),
+            vec![(1, LineChange::RemovedAbove)]
+        );
+    }
+
+    #[test]
+    fn removed_last_lines_mark_the_end() {
+        assert_eq!(
+            line_changes("a\nb\nc\n", "a\n"),
+            vec![(1, LineChange::RemovedAbove)]
+        );
+        assert_eq!(line_changes("a\n", ""), vec![(0, LineChange::RemovedAbove)]);
+    }
+}
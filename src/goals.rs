@@ -0,0 +1,241 @@
+use chrono::{DateTime, Days, Local, NaiveDate};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const HISTORY_FILE: &str = "writing-history.txt";
+const DOCUMENT_GOALS_FILE: &str = "document-goals.txt";
+
+/// Words written per day, stored as `YYYY-MM-DD words` lines in the config dir
+#[derive(Debug, Default)]
+pub struct WritingHistory {
+    path: PathBuf,
+    days: BTreeMap<NaiveDate, usize>,
+}
+
+impl WritingHistory {
+    pub fn load(config_dir: &Path) -> Self {
+        let path = config_dir.join(HISTORY_FILE);
+        let mut days = BTreeMap::new();
+
+        if let Ok(contents) = fs::read_to_string(&path) {
+            for line in contents.lines() {
+                let Some((date, words)) = line.split_once(' ') else {
+                    continue;
+                };
+                match (
+                    NaiveDate::parse_from_str(date, "%Y-%m-%d"),
+                    words.trim().parse::<usize>(),
+                ) {
+                    (Ok(date), Ok(words)) => {
+                        days.insert(date, words);
+                    }
+                    _ => eprintln!("Skipping invalid writing history line: {}", line),
+                }
+            }
+        }
+
+        Self { path, days }
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let contents: String = self
+            .days
+            .iter()
+            .map(|(date, words)| format!("{} {}\n", date.format("%Y-%m-%d"), words))
+            .collect();
+        fs::write(&self.path, contents)
+    }
+
+    /// Add newly written words to the given day
+    pub fn add_words(&mut self, date: NaiveDate, words: usize) {
+        *self.days.entry(date).or_insert(0) += words;
+    }
+
+    pub fn words_on(&self, date: NaiveDate) -> usize {
+        self.days.get(&date).copied().unwrap_or(0)
+    }
+
+    pub fn words_today(&self) -> usize {
+        self.words_on(Local::now().date_naive())
+    }
+
+    /// Words written on each of the last `count` days, oldest first, including today
+    pub fn last_days(&self, count: u64) -> Vec<(NaiveDate, usize)> {
+        let today = Local::now().date_naive();
+        (0..count)
+            .rev()
+            .filter_map(|offset| today.checked_sub_days(Days::new(offset)))
+            .map(|date| (date, self.words_on(date)))
+            .collect()
+    }
+}
+
+/// Word targets for individual documents, keyed by their settings key
+#[derive(Debug, Default)]
+pub struct DocumentGoals {
+    path: PathBuf,
+    goals: BTreeMap<String, usize>,
+}
+
+impl DocumentGoals {
+    pub fn load(config_dir: &Path) -> Self {
+        let path = config_dir.join(DOCUMENT_GOALS_FILE);
+        let goals = fs::read_to_string(&path)
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|line| {
+                let (key, goal) = line.rsplit_once('\t')?;
+                Some((key.to_string(), goal.parse().ok()?))
+            })
+            .collect();
+
+        Self { path, goals }
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let contents: String = self
+            .goals
+            .iter()
+            .map(|(key, goal)| format!("{}\t{}\n", key, goal))
+            .collect();
+        fs::write(&self.path, contents)
+    }
+
+    pub fn get(&self, key: &str) -> usize {
+        self.goals.get(key).copied().unwrap_or(0)
+    }
+
+    /// Set the goal for a document, a goal of 0 removes it
+    pub fn set(&mut self, key: &str, goal: usize) {
+        if goal == 0 {
+            self.goals.remove(key);
+        } else {
+            self.goals.insert(key.to_string(), goal);
+        }
+    }
+}
+
+/// The current writing session, started when the editor is opened
+#[derive(Debug)]
+pub struct WritingSession {
+    pub started: DateTime<Local>,
+    pub words_added: usize,
+}
+
+impl WritingSession {
+    pub fn new() -> Self {
+        Self {
+            started: Local::now(),
+            words_added: 0,
+        }
+    }
+
+    /// Elapsed session time formatted as hours and minutes
+    pub fn elapsed_text(&self) -> String {
+        let minutes = (Local::now() - self.started).num_minutes().max(0);
+        if minutes < 60 {
+            format!("{} min", minutes)
+        } else {
+            format!("{} h {} min", minutes / 60, minutes % 60)
+        }
+    }
+}
+
+impl Default for WritingSession {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("md-wr-goals-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn date(text: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(text, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn loads_history_skipping_invalid_lines() {
+        let dir = config_dir("history");
+        fs::write(
+            dir.join(HISTORY_FILE),
+            "2024-03-01 120\nnot a line\n2024-13-01 5\n2024-03-02 many\n2024-03-03  40\n",
+        )
+        .unwrap();
+
+        let history = WritingHistory::load(&dir);
+        assert_eq!(history.words_on(date("2024-03-01")), 120);
+        assert_eq!(history.words_on(date("2024-03-02")), 0);
+        assert_eq!(history.words_on(date("2024-03-03")), 40);
+        assert_eq!(history.days.len(), 2);
+    }
+
+    #[test]
+    fn saves_history_in_date_order() {
+        let dir = config_dir("save-history");
+        let mut history = WritingHistory::load(&dir);
+        history.add_words(date("2024-03-02"), 10);
+        history.add_words(date("2024-03-01"), 5);
+        history.add_words(date("2024-03-02"), 15);
+        history.save().unwrap();
+
+        assert_eq!(
+            fs::read_to_string(dir.join(HISTORY_FILE)).unwrap(),
+            "2024-03-01 5\n2024-03-02 25\n"
+        );
+        assert_eq!(WritingHistory::load(&dir).words_on(date("2024-03-02")), 25);
+    }
+
+    #[test]
+    fn last_days_end_today() {
+        let mut history = WritingHistory::default();
+        let today = Local::now().date_naive();
+        history.add_words(today, 30);
+
+        let days = history.last_days(7);
+        assert_eq!(days.len(), 7);
+        assert_eq!(days.last(), Some(&(today, 30)));
+        assert!(days.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert_eq!(history.words_today(), 30);
+    }
+
+    #[test]
+    fn loads_and_saves_document_goals() {
+        let dir = config_dir("document-goals");
+        fs::write(
+            dir.join(DOCUMENT_GOALS_FILE),
+            "/notes/novel.md\t50000\nuser-notes\t500\nbroken line\nessay\tlots\n",
+        )
+        .unwrap();
+
+        let mut goals = DocumentGoals::load(&dir);
+        assert_eq!(goals.get("/notes/novel.md"), 50000);
+        assert_eq!(goals.get("user-notes"), 500);
+        assert_eq!(goals.get("essay"), 0);
+
+        goals.set("user-notes", 0);
+        goals.set("/notes/essay.md", 2000);
+        goals.save().unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.join(DOCUMENT_GOALS_FILE)).unwrap(),
+            "/notes/essay.md\t2000\n/notes/novel.md\t50000\n"
+        );
+    }
+
+    #[test]
+    fn formats_session_time() {
+        let mut session = WritingSession::new();
+        assert_eq!(session.elapsed_text(), "0 min");
+        session.started = Local::now() - chrono::Duration::minutes(95);
+        assert_eq!(session.elapsed_text(), "1 h 35 min");
+    }
+}
@@ -0,0 +1,109 @@
+use markdown_ppp::ast::Document;
+use syntect::highlighting::ThemeSet;
+use syntect::html::{ClassStyle, ClassedHTMLGenerator, css_for_theme_with_class_style};
+use syntect::parsing::SyntaxSet;
+use syntect::util::LinesWithEndings;
+
+use crate::markdown;
+
+// Highlighted spans use prefixed classes so they can't clash with theme CSS
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+
+thread_local! {
+    static SYNTAX_SET: SyntaxSet = SyntaxSet::load_defaults_newlines();
+    static THEME_SET: ThemeSet = ThemeSet::load_defaults();
+}
+
+/// Replace fenced code blocks in a known language with highlighted HTML
+pub fn highlight_code_blocks(document: &mut Document) {
+    SYNTAX_SET.with(|syntax_set| {
+        markdown::replace_code_blocks(document, &mut |language, code| {
+            if language.is_empty() {
+                return None;
+            }
+            let syntax = syntax_set.find_syntax_by_token(language)?;
+
+            let mut generator =
+                ClassedHTMLGenerator::new_with_class_style(syntax, syntax_set, CLASS_STYLE);
+            for line in LinesWithEndings::from(code) {
+                if let Err(e) = generator.parse_html_for_line_which_includes_newline(line) {
+                    eprintln!("Failed to highlight {} code block: {}", language, e);
+                    return None;
+                }
+            }
+
+            Some(format!(
+                "<pre class=\"highlight\"><code class=\"language-{}\">{}</code></pre>\n",
+                markdown::escape_html(language),
+                generator.finalize()
+            ))
+        });
+    });
+}
+
+/// The syntect theme matching a preview theme in light or dark mode
+fn syntax_theme_name(preview_theme: &str, dark: bool) -> &'static str {
+    match (preview_theme, dark) {
+        ("github", false) => "InspiredGitHub",
+        ("github", true) => "base16-ocean.dark",
+        ("academic", false) => "Solarized (light)",
+        ("academic", true) => "Solarized (dark)",
+        ("minimal", false) => "base16-ocean.light",
+        ("minimal", true) => "base16-eighties.dark",
+        // User themes get a neutral default
+        (_, false) => "InspiredGitHub",
+        (_, true) => "base16-mocha.dark",
+    }
+}
+
+/// CSS for highlighted code that matches the preview theme
+pub fn theme_css(preview_theme: &str, dark: bool) -> String {
+    THEME_SET.with(|theme_set| {
+        let name = syntax_theme_name(preview_theme, dark);
+        let Some(theme) = theme_set.themes.get(name) else {
+            eprintln!("Missing syntax highlighting theme: {}", name);
+            return String::new();
+        };
+        match css_for_theme_with_class_style(theme, CLASS_STYLE) {
+            Ok(css) => css,
+            Err(e) => {
+                eprintln!("Failed to generate highlighting CSS: {}", e);
+                String::new()
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_preview_theme_has_a_syntax_theme() {
+        THEME_SET.with(|theme_set| {
+            for preview_theme in ["github", "academic", "minimal", "user:custom"] {
+                for dark in [false, true] {
+                    let name = syntax_theme_name(preview_theme, dark);
+                    assert!(theme_set.themes.contains_key(name), "missing {}", name);
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn dark_mode_uses_a_different_theme() {
+        for preview_theme in ["github", "academic", "minimal", "user:custom"] {
+            assert_ne!(
+                syntax_theme_name(preview_theme, false),
+                syntax_theme_name(preview_theme, true)
+            );
+        }
+    }
+
+    #[test]
+    fn css_uses_prefixed_classes() {
+        let css = theme_css("github", false);
+        assert!(css.contains(".hl-"));
+        assert!(!theme_css("user:custom", true).is_empty());
+    }
+}
@@ -0,0 +1,179 @@
+use adw::prelude::*;
+use adw::subclass::prelude::*;
+use gio::Settings;
+use gtk::glib;
+use gtk::{CompositeTemplate, TemplateChild};
+use std::fs;
+
+use crate::notes;
+use crate::recent::{self, RecentFile};
+
+#[derive(CompositeTemplate)]
+#[template(resource = "/org/md-wr/com/home-page.ui")]
+pub struct HomePage {
+    #[template_child]
+    pub header_bar: TemplateChild<gtk::HeaderBar>,
+
+    #[template_child]
+    pub pinned_heading: TemplateChild<gtk::Label>,
+
+    #[template_child]
+    pub pinned_list: TemplateChild<gtk::ListBox>,
+
+    #[template_child]
+    pub recent_heading: TemplateChild<gtk::Label>,
+
+    #[template_child]
+    pub recent_list: TemplateChild<gtk::ListBox>,
+
+    settings: Settings,
+}
+
+impl Default for HomePage {
+    fn default() -> Self {
+        Self {
+            header_bar: TemplateChild::default(),
+            pinned_heading: TemplateChild::default(),
+            pinned_list: TemplateChild::default(),
+            recent_heading: TemplateChild::default(),
+            recent_list: TemplateChild::default(),
+            settings: Settings::new("org.md-wr.com"),
+        }
+    }
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for HomePage {
+    const NAME: &'static str = "HomePage";
+    type Type = super::HomePage;
+    type ParentType = gtk::Widget;
+
+    fn class_init(klass: &mut Self::Class) {
+        klass.bind_template();
+    }
+
+    fn instance_init(obj: &glib::subclass::InitializingObject<Self>) {
+        obj.init_template();
+    }
+}
+
+impl HomePage {
+    /// Rebuild both lists from the recent files in settings
+    pub fn refresh(&self) {
+        let files = recent::prune_missing(&self.settings);
+        let (pinned, recent): (Vec<RecentFile>, Vec<RecentFile>) =
+            files.into_iter().partition(|file| file.pinned);
+
+        self.pinned_list.remove_all();
+        for file in &pinned {
+            self.pinned_list.append(&self.file_row(file));
+        }
+        self.pinned_heading.set_visible(!pinned.is_empty());
+        self.pinned_list.set_visible(!pinned.is_empty());
+
+        self.recent_list.remove_all();
+        for file in &recent {
+            self.recent_list.append(&self.file_row(file));
+        }
+        let placeholder = gtk::Label::new(Some("Files you open will be listed here"));
+        placeholder.set_margin_top(12);
+        placeholder.set_margin_bottom(12);
+        placeholder.add_css_class("dim-label");
+        self.recent_list.set_placeholder(Some(&placeholder));
+    }
+
+    /// A row that opens the file, with buttons to pin it or remove it from the list
+    fn file_row(&self, file: &RecentFile) -> adw::ActionRow {
+        let title = fs::read_to_string(&file.path)
+            .map(|text| notes::note_title(&file.path, &text))
+            .unwrap_or_else(|_| file.path.to_string_lossy().into_owned());
+        let folder = file
+            .path
+            .parent()
+            .map(|parent| {
+                let home = glib::home_dir();
+                match parent.strip_prefix(&home) {
+                    Ok(relative) => format!("~/{}", relative.to_string_lossy()),
+                    Err(_) => parent.to_string_lossy().into_owned(),
+                }
+            })
+            .unwrap_or_default();
+
+        let row = adw::ActionRow::new();
+        row.set_title(&glib::markup_escape_text(&title));
+        row.set_subtitle(&glib::markup_escape_text(&format!(
+            "{} · {}",
+            folder.trim_end_matches('/'),
+            file.opened_label()
+        )));
+        row.set_tooltip_text(Some(&file.path.to_string_lossy()));
+        row.add_prefix(&gtk::Image::from_icon_name("text-x-generic-symbolic"));
+        row.set_activatable(true);
+
+        let pin_button = gtk::ToggleButton::new();
+        pin_button.set_icon_name("view-pin-symbolic");
+        pin_button.set_active(file.pinned);
+        pin_button.set_tooltip_text(Some(if file.pinned { "Unpin" } else { "Pin" }));
+        pin_button.set_valign(gtk::Align::Center);
+        pin_button.add_css_class("flat");
+        row.add_suffix(&pin_button);
+
+        let remove_button = gtk::Button::from_icon_name("window-close-symbolic");
+        remove_button.set_tooltip_text(Some("Remove from List"));
+        remove_button.set_valign(gtk::Align::Center);
+        remove_button.add_css_class("flat");
+        row.add_suffix(&remove_button);
+
+        let path = file.path.clone();
+        row.connect_activated(move |row| {
+            let _ =
+                row.activate_action("win.open-file", Some(&path.to_string_lossy().to_variant()));
+        });
+
+        let path = file.path.clone();
+        pin_button.connect_toggled(glib::clone!(
+            #[weak(rename_to = imp)]
+            self,
+            move |button| {
+                recent::set_pinned(&imp.settings, &path, button.is_active());
+                // Rebuild once the toggle has finished, as it moves between lists
+                let imp_weak = imp.obj().downgrade();
+                glib::idle_add_local_once(move || {
+                    if let Some(widget) = imp_weak.upgrade() {
+                        widget.imp().refresh();
+                    }
+                });
+            }
+        ));
+
+        let path = file.path.clone();
+        remove_button.connect_clicked(glib::clone!(
+            #[weak(rename_to = imp)]
+            self,
+            move |_| {
+                recent::remove(&imp.settings, &path);
+                let imp_weak = imp.obj().downgrade();
+                glib::idle_add_local_once(move || {
+                    if let Some(widget) = imp_weak.upgrade() {
+                        widget.imp().refresh();
+                    }
+                });
+            }
+        ));
+
+        row
+    }
+}
+
+impl ObjectImpl for HomePage {
+    fn constructed(&self) {
+        self.parent_constructed();
+        self.refresh();
+    }
+
+    fn dispose(&self) {
+        self.dispose_template();
+    }
+}
+
+impl WidgetImpl for HomePage {}
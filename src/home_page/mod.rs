@@ -0,0 +1,27 @@
+use gtk::glib;
+use gtk::subclass::prelude::ObjectSubclassIsExt;
+
+mod imp;
+
+glib::wrapper! {
+    pub struct HomePage(ObjectSubclass<imp::HomePage>)
+        @extends gtk::Widget,
+        @implements gtk::Accessible, gtk::Buildable, gtk::ConstraintTarget;
+}
+
+impl HomePage {
+    pub fn new() -> Self {
+        glib::Object::builder().build()
+    }
+
+    /// Reload the pinned and recent files, dropping files that no longer exist
+    pub fn refresh(&self) {
+        self.imp().refresh();
+    }
+}
+
+impl Default for HomePage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,141 @@
+use gtk::glib;
+use std::path::{Path, PathBuf};
+
+use crate::markdown;
+
+/// An `![alt](target)` image outside code blocks
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageLink {
+    pub line: usize,
+    pub target: String,
+}
+
+/// Images in `text`, in order, with the line each is on
+pub fn image_links(text: &str) -> Vec<ImageLink> {
+    let mut links = Vec::new();
+    let mut line_number = 0;
+    let mut counted = 0;
+    for (offset, line) in markdown::prose_lines(text) {
+        line_number += text[counted..offset].matches('\n').count();
+        counted = offset;
+
+        let mut rest = line;
+        while let Some(start) = rest.find("![") {
+            rest = &rest[start + 2..];
+            let Some(target) = rest
+                .find("](")
+                .map(|close| &rest[close + 2..])
+                .and_then(|target| target.find(')').map(|end| &target[..end]))
+            else {
+                break;
+            };
+            let target = parse_target(target);
+            if !target.is_empty() {
+                links.push(ImageLink {
+                    line: line_number,
+                    target: target.to_string(),
+                });
+            }
+        }
+    }
+    links
+}
+
+/// The local file an image target points to. Relative paths are resolved
+/// against `document_dir`; remote images are not shown.
+pub fn resolve(target: &str, document_dir: Option<&Path>) -> Option<PathBuf> {
+    if target.starts_with("file://") {
+        return glib::filename_from_uri(target).ok().map(|(path, _)| path);
+    }
+    if target.contains("://") || target.starts_with("data:") {
+        return None;
+    }
+    let target = glib::Uri::unescape_string(target, None::<&str>)
+        .map(|target| target.to_string())
+        .unwrap_or_else(|| target.to_string());
+    let path = match target.strip_prefix("~/") {
+        Some(rest) => glib::home_dir().join(rest),
+        None => PathBuf::from(target),
+    };
+    if path.is_absolute() {
+        Some(path)
+    } else {
+        document_dir.map(|dir| dir.join(path))
+    }
+}
+
+/// The destination of a link, without its optional title or angle brackets
+fn parse_target(target: &str) -> &str {
+    let target = target.trim();
+    if let Some(inner) = target.strip_prefix('<') {
+        return inner.split('>').next().unwrap_or_default();
+    }
+    target.split_whitespace().next().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(line: usize, target: &str) -> ImageLink {
+        ImageLink {
+            line,
+            target: target.to_string(),
+        }
+    }
+
+    #[test]
+    fn finds_images_with_their_line() {
+        let text = "# Title\n![a](one.png) and ![b](two.jpg \"Two\")\n\n![c](<with space.png>)\n";
+        assert_eq!(
+            image_links(text),
+            [
+                link(1, "one.png"),
+                link(1, "two.jpg"),
+                link(3, "with space.png")
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_code_blocks_and_plain_links() {
+        let text = "[not](an-image.png)\n```\n![code](x.png)\n```\n![]()\n![real](y.png)\n";
+        assert_eq!(image_links(text), [link(5, "y.png")]);
+    }
+
+    #[test]
+    fn resolves_relative_to_the_document() {
+        let dir = Path::new("/notes");
+        assert_eq!(
+            resolve("img/a.png", Some(dir)),
+            Some(PathBuf::from("/notes/img/a.png"))
+        );
+        assert_eq!(
+            resolve("my%20pic.png", Some(dir)),
+            Some(PathBuf::from("/notes/my pic.png"))
+        );
+        assert_eq!(resolve("img/a.png", None), None);
+    }
+
+    #[test]
+    fn resolves_absolute_home_and_file_uris() {
+        assert_eq!(
+            resolve("/tmp/a.png", None),
+            Some(PathBuf::from("/tmp/a.png"))
+        );
+        assert_eq!(
+            resolve("~/a.png", None),
+            Some(glib::home_dir().join("a.png"))
+        );
+        assert_eq!(
+            resolve("file:///tmp/b.png", None),
+            Some(PathBuf::from("/tmp/b.png"))
+        );
+    }
+
+    #[test]
+    fn ignores_remote_images() {
+        assert_eq!(resolve("https://example.com/a.png", None), None);
+        assert_eq!(resolve("data:image/png;base64,AAAA", None), None);
+    }
+}
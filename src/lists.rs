@@ -0,0 +1,272 @@
+/// The marker that starts a list item
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Marker {
+    Bullet(char),
+    Ordered { number: u32, delimiter: char },
+}
+
+/// A list item line such as `  - [ ] text` or `3. text`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListItem {
+    /// Leading whitespace before the marker
+    pub indent: String,
+    pub marker: Marker,
+    /// Checkbox state for task list items
+    pub task: Option<bool>,
+    /// Byte offset where the item's text starts (after marker and checkbox)
+    pub content_start: usize,
+}
+
+impl ListItem {
+    /// Parse the list marker at the start of a line
+    pub fn parse(line: &str) -> Option<Self> {
+        let indent_len = line.len() - line.trim_start_matches([' ', '\t']).len();
+        let indent = line[..indent_len].to_string();
+        let rest = &line[indent_len..];
+
+        let (marker, marker_len) = match rest.chars().next()? {
+            c @ ('-' | '*' | '+') => (Marker::Bullet(c), 1),
+            c if c.is_ascii_digit() => {
+                let digits = rest.chars().take_while(|c| c.is_ascii_digit()).count();
+                // CommonMark allows at most 9 digits
+                if digits > 9 {
+                    return None;
+                }
+                let delimiter = rest[digits..].chars().next()?;
+                if delimiter != '.' && delimiter != ')' {
+                    return None;
+                }
+                let number = rest[..digits].parse().ok()?;
+                (Marker::Ordered { number, delimiter }, digits + 1)
+            }
+            _ => return None,
+        };
+
+        // The marker must be followed by a space, or end the line
+        let after_marker = &rest[marker_len..];
+        if !after_marker.is_empty() && !after_marker.starts_with(' ') {
+            return None;
+        }
+        let mut content_start = indent_len + marker_len + after_marker.len().min(1);
+
+        let task = match line.get(content_start..content_start + 3) {
+            Some("[ ]") => Some(false),
+            Some("[x]") | Some("[X]") => Some(true),
+            _ => None,
+        };
+        if task.is_some() {
+            content_start += 3;
+            if line[content_start..].starts_with(' ') {
+                content_start += 1;
+            }
+        }
+
+        Some(Self {
+            indent,
+            marker,
+            task,
+            content_start,
+        })
+    }
+
+    /// Width of the marker and the space after it, used as the nesting indent
+    pub fn marker_width(&self) -> usize {
+        match self.marker {
+            Marker::Bullet(_) => 2,
+            Marker::Ordered { number, .. } => number.to_string().len() + 2,
+        }
+    }
+
+    /// Column where the text of the item starts, where nested items line up
+    pub fn content_column(&self) -> usize {
+        self.indent.len() + self.marker_width()
+    }
+
+    pub fn is_ordered(&self) -> bool {
+        matches!(self.marker, Marker::Ordered { .. })
+    }
+
+    /// Whether the item has no text after its marker
+    pub fn is_empty(&self, line: &str) -> bool {
+        line.get(self.content_start..)
+            .is_none_or(|content| content.trim().is_empty())
+    }
+
+    /// Prefix for the item that follows this one
+    pub fn next_prefix(&self) -> String {
+        let marker = match self.marker {
+            Marker::Bullet(c) => c.to_string(),
+            Marker::Ordered { number, delimiter } => format!("{}{}", number + 1, delimiter),
+        };
+        let task = if self.task.is_some() { "[ ] " } else { "" };
+        format!("{}{} {}", self.indent, marker, task)
+    }
+}
+
+/// The `>` markers at the start of a blockquote line, including trailing space
+pub fn quote_prefix(line: &str) -> Option<&str> {
+    let mut end = 0;
+    let bytes = line.as_bytes();
+    while end < bytes.len() && (bytes[end] == b'>' || bytes[end] == b' ') {
+        end += 1;
+    }
+    let prefix = &line[..end];
+    prefix.contains('>').then_some(prefix)
+}
+
+/// Replace the number of an ordered list item line
+pub fn with_number(line: &str, number: u32) -> String {
+    let Some(item) = ListItem::parse(line) else {
+        return line.to_string();
+    };
+    let Marker::Ordered { delimiter, .. } = item.marker else {
+        return line.to_string();
+    };
+    let marker_end = line[item.indent.len()..]
+        .find(delimiter)
+        .map(|i| item.indent.len() + i + 1)
+        .unwrap_or(item.indent.len());
+    format!(
+        "{}{}{}{}",
+        item.indent,
+        number,
+        delimiter,
+        &line[marker_end..]
+    )
+}
+
+/// Renumber an ordered list so it counts up from its first item. `lines`
+/// starts at that first item; nested lines are skipped and the list ends at
+/// the first line that is neither a sibling item nor nested under one.
+/// Returns the (index, new text) of every line that changed.
+pub fn renumber(lines: &[String]) -> Vec<(usize, String)> {
+    let Some(first) = lines.first().and_then(|line| ListItem::parse(line)) else {
+        return Vec::new();
+    };
+    let Marker::Ordered { number: start, .. } = first.marker else {
+        return Vec::new();
+    };
+
+    let mut changes = Vec::new();
+    let mut expected = start;
+    for (index, line) in lines.iter().enumerate() {
+        let line_indent = line.len() - line.trim_start().len();
+        match ListItem::parse(line) {
+            Some(item) if item.indent.len() == first.indent.len() => {
+                let Marker::Ordered { number, .. } = item.marker else {
+                    break;
+                };
+                if number != expected {
+                    changes.push((index, with_number(line, expected)));
+                }
+                expected += 1;
+            }
+            // Nested items and paragraphs belonging to the current item
+            _ if line_indent > first.indent.len() => {}
+            _ => break,
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.lines().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn parses_bullets_and_numbers() {
+        let item = ListItem::parse("  * text").unwrap();
+        assert_eq!(item.indent, "  ");
+        assert_eq!(item.marker, Marker::Bullet('*'));
+        assert_eq!(item.task, None);
+        assert_eq!(item.content_start, 4);
+        assert_eq!(item.content_column(), 4);
+
+        let item = ListItem::parse("12) text").unwrap();
+        assert_eq!(
+            item.marker,
+            Marker::Ordered {
+                number: 12,
+                delimiter: ')'
+            }
+        );
+        assert_eq!(item.content_start, 4);
+        assert!(item.is_ordered());
+    }
+
+    #[test]
+    fn rejects_non_items() {
+        assert_eq!(ListItem::parse("-text"), None);
+        assert_eq!(ListItem::parse("1.5 apples"), None);
+        assert_eq!(ListItem::parse("1234567890. too long"), None);
+        assert_eq!(ListItem::parse("Plain text"), None);
+        assert_eq!(ListItem::parse(""), None);
+    }
+
+    #[test]
+    fn parses_task_items() {
+        let item = ListItem::parse("- [ ] todo").unwrap();
+        assert_eq!(item.task, Some(false));
+        assert_eq!(item.content_start, 6);
+        assert_eq!(ListItem::parse("- [X] done").unwrap().task, Some(true));
+        assert_eq!(ListItem::parse("- [y] other").unwrap().task, None);
+    }
+
+    #[test]
+    fn empty_items_end_the_list() {
+        let line = "- ";
+        assert!(ListItem::parse(line).unwrap().is_empty(line));
+        let line = "-";
+        assert!(ListItem::parse(line).unwrap().is_empty(line));
+        let line = "- [ ]  ";
+        assert!(ListItem::parse(line).unwrap().is_empty(line));
+        let line = "- text";
+        assert!(!ListItem::parse(line).unwrap().is_empty(line));
+    }
+
+    #[test]
+    fn continues_with_the_next_marker() {
+        let next = |line: &str| ListItem::parse(line).unwrap().next_prefix();
+        assert_eq!(next("  + text"), "  + ");
+        assert_eq!(next("9. text"), "10. ");
+        assert_eq!(next("3) text"), "4) ");
+        assert_eq!(next("- [x] done"), "- [ ] ");
+    }
+
+    #[test]
+    fn finds_quote_prefixes() {
+        assert_eq!(quote_prefix("> > text"), Some("> > "));
+        assert_eq!(quote_prefix(">text"), Some(">"));
+        assert_eq!(quote_prefix("  text"), None);
+    }
+
+    #[test]
+    fn replaces_numbers() {
+        assert_eq!(with_number("  9. nine", 10), "  10. nine");
+        assert_eq!(with_number("1) one", 2), "2) one");
+        assert_eq!(with_number("- bullet", 2), "- bullet");
+    }
+
+    #[test]
+    fn renumbers_siblings_only() {
+        let text = "3. a\n5. b\n   1. nested\n   continued\n1. c\nAfter\n9. d";
+        assert_eq!(
+            renumber(&lines(text)),
+            vec![(1, "4. b".to_string()), (4, "5. c".to_string())]
+        );
+        assert!(renumber(&lines("1. a\n2. b")).is_empty());
+        assert!(renumber(&lines("- a\n- b")).is_empty());
+    }
+
+    #[test]
+    fn renumbering_stops_at_a_bullet_sibling() {
+        assert_eq!(
+            renumber(&lines("1. a\n1. b\n- c\n1. d")),
+            vec![(1, "2. b".to_string())]
+        );
+    }
+}
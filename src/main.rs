@@ -3,7 +3,32 @@ use adw::{Application, ApplicationWindow};
 use gtk::{gio, glib};
 
 mod custom_textview;
+mod diagrams;
+mod diff;
+mod footnotes;
+mod frontmatter;
+mod git;
+mod goals;
+mod highlight;
+mod home_page;
+mod images;
+mod lists;
+mod markdown;
+mod math;
+mod notes;
+mod preferences;
+mod preview;
+mod print;
+mod recent;
+mod search;
+mod snippets;
+mod stats;
+mod table;
+mod tags;
+mod templates;
+mod toc;
 use custom_textview::CustomTextView;
+use home_page::HomePage;
 
 const APP_ID: &str = "org.md-wr.com";
 
@@ -16,12 +41,19 @@ fn main() {
     let app = Application::builder().application_id(APP_ID).build();
 
     app.connect_activate(|app| {
+        let settings = gio::Settings::new(APP_ID);
+        let restore_session = settings.boolean("restore-session");
+
         // Create window
         let window = ApplicationWindow::builder()
             .application(app)
             .default_width(1000)
             .default_height(700)
             .build();
+        if restore_session {
+            window.set_default_size(settings.int("window-width"), settings.int("window-height"));
+            window.set_maximized(settings.boolean("window-maximized"));
+        }
 
         // Create CustomTextView that fills the entire window
         let notes_textview = CustomTextView::with_settings_key("user-notes");
@@ -34,11 +66,149 @@ fn main() {
             println!("Notes updated! Length: {}", text.len());
         });
 
-        // Set the text view as the main window content
-        window.set_content(Some(&notes_textview));
+        // The app opens on the home page; opening a file switches to the editor
+        let home_page = HomePage::new();
+        let stack = gtk::Stack::new();
+        stack.set_transition_type(gtk::StackTransitionType::Crossfade);
+        stack.add_named(&home_page, Some("home"));
+        stack.add_named(&notes_textview, Some("editor"));
+        stack.set_visible_child_name("home");
+        window.set_content(Some(&stack));
+
+        if restore_session
+            && settings.string("session-page") == "editor"
+            && notes_textview.restore_session()
+        {
+            stack.set_visible_child_name("editor");
+        }
+        window.connect_close_request(glib::clone!(
+            #[weak]
+            stack,
+            #[weak]
+            notes_textview,
+            #[upgrade_or]
+            glib::Propagation::Proceed,
+            move |window| {
+                save_session(&settings, window, &stack, &notes_textview);
+                glib::Propagation::Proceed
+            }
+        ));
+
+        add_window_actions(&window, &stack, &home_page, &notes_textview);
+        app.set_accels_for_action("win.open", &["<Control>o"]);
 
         window.present();
     });
 
     app.run();
 }
+
+/// Remember the window layout and open document so the next launch can restore them
+fn save_session(
+    settings: &gio::Settings,
+    window: &ApplicationWindow,
+    stack: &gtk::Stack,
+    editor: &CustomTextView,
+) {
+    // The default size follows the window but keeps the unmaximized size
+    let (width, height) = window.default_size();
+    let page = stack.visible_child_name().unwrap_or_default();
+    let result = settings
+        .set_int("window-width", width)
+        .and_then(|_| settings.set_int("window-height", height))
+        .and_then(|_| settings.set_boolean("window-maximized", window.is_maximized()))
+        .and_then(|_| settings.set_string("session-page", &page));
+    if let Err(e) = result {
+        eprintln!("Failed to save window state: {}", e);
+    }
+    editor.save_session();
+}
+
+/// Actions for moving between the home page and the editor
+fn add_window_actions(
+    window: &ApplicationWindow,
+    stack: &gtk::Stack,
+    home_page: &HomePage,
+    editor: &CustomTextView,
+) {
+    let home_action = gio::SimpleAction::new("home", None);
+    home_action.connect_activate(glib::clone!(
+        #[weak]
+        stack,
+        #[weak]
+        home_page,
+        #[weak]
+        editor,
+        move |_, _| {
+            editor.save_document();
+            home_page.refresh();
+            stack.set_visible_child_name("home");
+        }
+    ));
+    window.add_action(&home_action);
+
+    let open_file_action = gio::SimpleAction::new("open-file", Some(glib::VariantTy::STRING));
+    open_file_action.connect_activate(glib::clone!(
+        #[weak]
+        stack,
+        #[weak]
+        editor,
+        move |_, parameter| {
+            let Some(path) = parameter.and_then(|p| p.str()) else {
+                return;
+            };
+            editor.open_file(std::path::Path::new(path));
+            stack.set_visible_child_name("editor");
+        }
+    ));
+    window.add_action(&open_file_action);
+
+    // The editor opens new notes through open-file, which shows it
+    let new_action = gio::SimpleAction::new("new-document", None);
+    new_action.connect_activate(glib::clone!(
+        #[weak]
+        editor,
+        move |_, _| {
+            editor.new_document();
+        }
+    ));
+    window.add_action(&new_action);
+
+    let new_from_template_action = gio::SimpleAction::new("new-from-template", None);
+    new_from_template_action.connect_activate(glib::clone!(
+        #[weak]
+        editor,
+        move |_, _| {
+            editor.show_new_from_template_dialog();
+        }
+    ));
+    window.add_action(&new_from_template_action);
+
+    let open_action = gio::SimpleAction::new("open", None);
+    open_action.connect_activate(glib::clone!(
+        #[weak]
+        window,
+        #[weak]
+        open_file_action,
+        move |_, _| {
+            let filter = gtk::FileFilter::new();
+            filter.set_name(Some("Markdown"));
+            filter.add_suffix("md");
+            filter.add_suffix("markdown");
+            filter.add_mime_type("text/markdown");
+            let filters = gio::ListStore::new::<gtk::FileFilter>();
+            filters.append(&filter);
+
+            let dialog = gtk::FileDialog::builder()
+                .title("Open File")
+                .filters(&filters)
+                .build();
+            dialog.open(Some(&window), gio::Cancellable::NONE, move |result| {
+                if let Some(path) = result.ok().and_then(|file| file.path()) {
+                    open_file_action.activate(Some(&path.to_string_lossy().to_variant()));
+                }
+            });
+        }
+    ));
+    window.add_action(&open_action);
+}
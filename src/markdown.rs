@@ -0,0 +1,192 @@
+use markdown_ppp::ast::{Block, CodeBlockKind, Document, Inline};
+use markdown_ppp::html_printer::{config::Config as HtmlConfig, render_html};
+use markdown_ppp::parser::{MarkdownParserState, parse_markdown};
+
+/// Parse Markdown source into an AST, returning None if the parser fails
+pub fn parse(text: &str) -> Option<Document> {
+    match parse_markdown(MarkdownParserState::default(), text) {
+        Ok(document) => Some(document),
+        Err(e) => {
+            eprintln!("Failed to parse markdown: {:?}", e);
+            None
+        }
+    }
+}
+
+/// Render a parsed document to an HTML fragment
+pub fn to_html(document: &Document) -> String {
+    render_html(document, HtmlConfig::default())
+}
+
+/// Replace fenced code blocks with raw HTML. `render` gets the block's language
+/// (first word of the info string) and its code, and returns None to keep the block.
+pub fn replace_code_blocks<F>(document: &mut Document, render: &mut F)
+where
+    F: FnMut(&str, &str) -> Option<String>,
+{
+    replace_code_blocks_in(&mut document.blocks, render);
+}
+
+fn replace_code_blocks_in<F>(blocks: &mut [Block], render: &mut F)
+where
+    F: FnMut(&str, &str) -> Option<String>,
+{
+    for block in blocks.iter_mut() {
+        let replacement = match block {
+            Block::CodeBlock(code_block) => match &code_block.kind {
+                CodeBlockKind::Fenced { info: Some(info) } => render(
+                    info.split_whitespace().next().unwrap_or_default(),
+                    &code_block.literal,
+                ),
+                _ => None,
+            },
+            Block::BlockQuote(blocks) => {
+                replace_code_blocks_in(blocks, render);
+                None
+            }
+            Block::List(list) => {
+                for item in &mut list.items {
+                    replace_code_blocks_in(&mut item.blocks, render);
+                }
+                None
+            }
+            Block::FootnoteDefinition(definition) => {
+                replace_code_blocks_in(&mut definition.blocks, render);
+                None
+            }
+            _ => None,
+        };
+
+        if let Some(html) = replacement {
+            *block = Block::HtmlBlock(html);
+        }
+    }
+}
+
+/// Whether the end of `text` is inside a fenced code block
+pub fn ends_in_fenced_code(text: &str) -> bool {
+    let mut open_fence: Option<&str> = None;
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        let fence = ["```", "~~~"]
+            .into_iter()
+            .find(|fence| trimmed.starts_with(fence));
+        match (open_fence, fence) {
+            (None, Some(fence)) => open_fence = Some(fence),
+            (Some(open), Some(fence)) if open == fence => open_fence = None,
+            _ => {}
+        }
+    }
+    open_fence.is_some()
+}
+
+/// Lines outside fenced code blocks, with the byte offset each starts at.
+/// Lines keep their line endings.
+pub fn prose_lines(text: &str) -> Vec<(usize, &str)> {
+    let mut lines = Vec::new();
+    let mut open_fence: Option<&str> = None;
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        let fence = ["```", "~~~"]
+            .into_iter()
+            .find(|fence| trimmed.starts_with(fence));
+        match (open_fence, fence) {
+            (None, Some(fence)) => open_fence = Some(fence),
+            (Some(open), Some(fence)) if open == fence => open_fence = None,
+            (None, None) => lines.push((offset, line)),
+            _ => {}
+        }
+        offset += line.len();
+    }
+    lines
+}
+
+/// Escape text for safe inclusion in HTML
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Get the rendered text content of a document with all Markdown syntax stripped.
+/// Each block ends up on its own paragraph, separated by a blank line.
+/// Fenced and indented code blocks are only included when `include_code` is set.
+pub fn plain_text(document: &Document, include_code: bool) -> String {
+    let mut out = String::new();
+    for block in &document.blocks {
+        push_block_text(block, include_code, &mut out);
+    }
+    out
+}
+
+fn push_block_text(block: &Block, include_code: bool, out: &mut String) {
+    match block {
+        Block::Paragraph(inlines) => {
+            push_inline_text(inlines, out);
+            out.push_str("\n\n");
+        }
+        Block::Heading(heading) => {
+            push_inline_text(&heading.content, out);
+            out.push_str("\n\n");
+        }
+        Block::BlockQuote(blocks) => {
+            for block in blocks {
+                push_block_text(block, include_code, out);
+            }
+        }
+        Block::List(list) => {
+            for item in &list.items {
+                for block in &item.blocks {
+                    push_block_text(block, include_code, out);
+                }
+            }
+        }
+        Block::CodeBlock(code_block) if include_code => {
+            out.push_str(&code_block.literal);
+            out.push_str("\n\n");
+        }
+        Block::Table(table) => {
+            for row in &table.rows {
+                for cell in row {
+                    push_inline_text(cell, out);
+                    out.push(' ');
+                }
+                out.push_str("\n\n");
+            }
+        }
+        Block::FootnoteDefinition(definition) => {
+            for block in &definition.blocks {
+                push_block_text(block, include_code, out);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Append the visible text of a run of inlines (link text, image alt text, code spans)
+pub fn push_inline_text(inlines: &[Inline], out: &mut String) {
+    for inline in inlines {
+        match inline {
+            Inline::Text(text) => out.push_str(text),
+            Inline::Code(code) => out.push_str(code),
+            Inline::LineBreak => out.push('\n'),
+            Inline::Emphasis(children)
+            | Inline::Strong(children)
+            | Inline::Strikethrough(children) => push_inline_text(children, out),
+            Inline::Link(link) => push_inline_text(&link.children, out),
+            Inline::Image(image) => out.push_str(&image.alt),
+            Inline::Autolink(url) => out.push_str(url),
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,275 @@
+use latex2mathml::{DisplayStyle, latex_to_mathml};
+
+use crate::markdown;
+
+/// A `$…$` or `$$…$$` formula found in Markdown source
+#[derive(Debug, Clone, PartialEq)]
+pub struct MathSpan {
+    pub latex: String,
+    pub display: bool,
+}
+
+/// Markdown source with math replaced by placeholders, plus the extracted formulas
+#[derive(Debug, Default)]
+pub struct ExtractedMath {
+    pub text: String,
+    pub spans: Vec<MathSpan>,
+}
+
+/// Placeholder left in the text for the formula at `index`. It only contains
+/// letters and digits so the Markdown parser passes it through as plain text.
+pub fn placeholder(index: usize) -> String {
+    format!("MDWRMATH{}X", index)
+}
+
+/// Replace math spans with placeholders. Fenced code blocks, code spans and
+/// escaped dollars are left alone. Inline math follows the usual rules:
+/// no space after the opening `$`, none before the closing `$`, and the
+/// closing `$` is not followed by a digit (so "$5 and $10" stays text).
+pub fn extract(text: &str) -> ExtractedMath {
+    let bytes = text.as_bytes();
+    let len = text.len();
+    let mut out = String::with_capacity(len);
+    let mut spans = Vec::new();
+    let mut i = 0;
+    let mut at_line_start = true;
+
+    while i < len {
+        if at_line_start {
+            at_line_start = false;
+            let line_end = line_end(text, i);
+            let trimmed = text[i..line_end].trim_start();
+            if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+                // Copy the whole fenced block verbatim
+                let fence = &trimmed[..3];
+                let mut j = line_end;
+                while j < len {
+                    let next = line_end_of(text, j);
+                    let closing = text[j..next].trim_start().starts_with(fence);
+                    j = next;
+                    if closing {
+                        break;
+                    }
+                }
+                out.push_str(&text[i..j]);
+                i = j;
+                at_line_start = true;
+                continue;
+            }
+        }
+
+        match bytes[i] {
+            b'\n' => {
+                out.push('\n');
+                i += 1;
+                at_line_start = true;
+            }
+            b'\\' if bytes.get(i + 1) == Some(&b'$') => {
+                out.push_str("\\$");
+                i += 2;
+            }
+            b'`' => {
+                let run = text[i..].bytes().take_while(|b| *b == b'`').count();
+                let marker = &text[i..i + run];
+                let end = text[i + run..]
+                    .find(marker)
+                    .map(|offset| i + run + offset + run)
+                    .unwrap_or(i + run);
+                out.push_str(&text[i..end]);
+                i = end;
+            }
+            b'$' if text[i..].starts_with("$$") => match text[i + 2..].find("$$") {
+                Some(offset) => {
+                    out.push_str(&placeholder(spans.len()));
+                    spans.push(MathSpan {
+                        latex: text[i + 2..i + 2 + offset].trim().to_string(),
+                        display: true,
+                    });
+                    i += 2 + offset + 2;
+                }
+                None => {
+                    out.push_str("$$");
+                    i += 2;
+                }
+            },
+            b'$' => match find_inline_close(text, i + 1) {
+                Some(close) => {
+                    out.push_str(&placeholder(spans.len()));
+                    spans.push(MathSpan {
+                        latex: text[i + 1..close].to_string(),
+                        display: false,
+                    });
+                    i = close + 1;
+                }
+                None => {
+                    out.push('$');
+                    i += 1;
+                }
+            },
+            _ => {
+                let c = text[i..].chars().next().unwrap_or_default();
+                out.push(c);
+                i += c.len_utf8().max(1);
+            }
+        }
+    }
+
+    ExtractedMath { text: out, spans }
+}
+
+// End of the line starting at `start`, excluding the newline
+fn line_end(text: &str, start: usize) -> usize {
+    text[start..]
+        .find('\n')
+        .map(|offset| start + offset)
+        .unwrap_or(text.len())
+}
+
+// Start of the line after the one containing `start`
+fn line_end_of(text: &str, start: usize) -> usize {
+    text[start..]
+        .find('\n')
+        .map(|offset| start + offset + 1)
+        .unwrap_or(text.len())
+}
+
+fn find_inline_close(text: &str, start: usize) -> Option<usize> {
+    let rest = &text[start..];
+    if rest.starts_with(char::is_whitespace) || rest.starts_with('$') {
+        return None;
+    }
+
+    let mut previous = '\0';
+    for (offset, c) in rest.char_indices() {
+        // Inline math never spans paragraphs
+        if c == '\n' && previous == '\n' {
+            return None;
+        }
+        if c == '$' && previous != '\\' && !previous.is_whitespace() {
+            let next = rest[offset + 1..].chars().next();
+            if !next.is_some_and(|n| n.is_ascii_digit()) {
+                return Some(start + offset);
+            }
+        }
+        previous = c;
+    }
+    None
+}
+
+/// Typeset a formula as MathML, or an inline error message if it fails to parse
+pub fn render(span: &MathSpan) -> String {
+    let style = if span.display {
+        DisplayStyle::Block
+    } else {
+        DisplayStyle::Inline
+    };
+
+    match latex_to_mathml(&span.latex, style) {
+        Ok(mathml) if span.display => format!("<div class=\"math-display\">{}</div>", mathml),
+        Ok(mathml) => mathml,
+        Err(e) => {
+            let delimiter = if span.display { "$$" } else { "$" };
+            format!(
+                "<span class=\"math-error\" title=\"{}\"><code>{}{}{}</code> ⚠ {}</span>",
+                markdown::escape_html(&e.to_string()),
+                delimiter,
+                markdown::escape_html(&span.latex),
+                delimiter,
+                markdown::escape_html(&e.to_string())
+            )
+        }
+    }
+}
+
+/// Swap the placeholders in rendered HTML for the typeset formulas
+pub fn replace_placeholders(html: &str, spans: &[MathSpan]) -> String {
+    let mut html = html.to_string();
+    for (index, span) in spans.iter().enumerate() {
+        let placeholder = placeholder(index);
+        let rendered = render(span);
+        if span.display {
+            // Display math on its own line becomes its own paragraph
+            html = html.replace(&format!("<p>{}</p>", placeholder), &rendered);
+        }
+        html = html.replace(&placeholder, &rendered);
+    }
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inline(latex: &str) -> MathSpan {
+        MathSpan {
+            latex: latex.to_string(),
+            display: false,
+        }
+    }
+
+    fn display(latex: &str) -> MathSpan {
+        MathSpan {
+            latex: latex.to_string(),
+            display: true,
+        }
+    }
+
+    #[test]
+    fn extracts_inline_and_display_math() {
+        let extracted = extract("Area $\\pi r^2$ and\n\n$$\n\\sum x\n$$\n");
+        assert_eq!(extracted.text, "Area MDWRMATH0X and\n\nMDWRMATH1X\n");
+        assert_eq!(
+            extracted.spans,
+            vec![inline("\\pi r^2"), display("\\sum x")]
+        );
+    }
+
+    #[test]
+    fn leaves_prices_alone() {
+        let extracted = extract("$5 and $10, or $ x $");
+        assert_eq!(extracted.text, "$5 and $10, or $ x $");
+        assert!(extracted.spans.is_empty());
+    }
+
+    #[test]
+    fn leaves_escaped_dollars_alone() {
+        let extracted = extract("\\$a\\$ and $b$");
+        assert_eq!(extracted.text, "\\$a\\$ and MDWRMATH0X");
+        assert_eq!(extracted.spans, vec![inline("b")]);
+    }
+
+    #[test]
+    fn leaves_code_alone() {
+        let text = "`$a$` and ``$b$``\n```\n$c$\n```\n~~~\n$$d$$\n~~~\n$e$";
+        let extracted = extract(text);
+        assert_eq!(
+            extracted.text,
+            "`$a$` and ``$b$``\n```\n$c$\n```\n~~~\n$$d$$\n~~~\nMDWRMATH0X"
+        );
+        assert_eq!(extracted.spans, vec![inline("e")]);
+    }
+
+    #[test]
+    fn inline_math_stays_in_its_paragraph() {
+        let extracted = extract("$a\n\nb$");
+        assert_eq!(extracted.text, "$a\n\nb$");
+        assert!(extracted.spans.is_empty());
+
+        let extracted = extract("$a\nb$");
+        assert_eq!(extracted.spans, vec![inline("a\nb")]);
+    }
+
+    #[test]
+    fn unclosed_delimiters_stay_text() {
+        let extracted = extract("$$ open and $x");
+        assert_eq!(extracted.text, "$$ open and $x");
+        assert!(extracted.spans.is_empty());
+    }
+
+    #[test]
+    fn placeholders_are_distinct() {
+        assert_ne!(placeholder(1), placeholder(10));
+        assert!(!placeholder(10).contains(&placeholder(1)));
+        assert!(placeholder(3).chars().all(|c| c.is_ascii_alphanumeric()));
+    }
+}
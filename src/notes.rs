@@ -0,0 +1,488 @@
+use chrono::Local;
+use gtk::glib;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::frontmatter::FrontMatter;
+use crate::markdown;
+
+// URI scheme for wiki-links in the rendered preview
+pub const NOTE_SCHEME: &str = "note:";
+
+const NOTE_EXTENSIONS: &[&str] = &["md", "markdown"];
+
+// Longest context shown for a backlink, in characters
+const SNIPPET_LENGTH: usize = 120;
+
+/// The notes folder: the configured path, or `Notes` in the user's documents
+pub fn notes_dir(configured: &str) -> PathBuf {
+    if !configured.trim().is_empty() {
+        return PathBuf::from(configured.trim());
+    }
+    glib::user_special_dir(glib::UserDirectory::Documents)
+        .unwrap_or_else(glib::home_dir)
+        .join("Notes")
+}
+
+/// A `[[Target#Heading|alias]]` link in a note
+#[derive(Debug, Clone, PartialEq)]
+pub struct WikiLink {
+    /// Note name or path; empty for links to a heading in the same note
+    pub target: String,
+    pub heading: Option<String>,
+    pub alias: Option<String>,
+    /// Byte range of the whole link, brackets included
+    pub range: Range<usize>,
+}
+
+impl WikiLink {
+    /// Text shown for the link
+    pub fn label(&self) -> String {
+        if let Some(ref alias) = self.alias {
+            return alias.clone();
+        }
+        match self.heading {
+            Some(ref heading) if self.target.is_empty() => heading.clone(),
+            Some(ref heading) => format!("{} › {}", self.target, heading),
+            None => self.target.clone(),
+        }
+    }
+}
+
+/// A note linking to the current one
+#[derive(Debug, Clone, PartialEq)]
+pub struct Backlink {
+    pub path: PathBuf,
+    pub title: String,
+    /// Zero-based line of the link in the linking note
+    pub line: usize,
+    pub snippet: String,
+}
+
+/// Find the wiki-links in a note, skipping fenced code blocks and code spans
+pub fn wiki_links(text: &str) -> Vec<WikiLink> {
+    let mut links = Vec::new();
+    for (offset, line) in markdown::prose_lines(text) {
+        find_links_in_line(line, offset, &mut links);
+    }
+    links
+}
+
+fn find_links_in_line(line: &str, offset: usize, links: &mut Vec<WikiLink>) {
+    let bytes = line.as_bytes();
+    let mut in_code = false;
+    let mut index = 0;
+
+    while index < bytes.len() {
+        if bytes[index] == b'`' {
+            in_code = !in_code;
+        } else if !in_code && bytes[index..].starts_with(b"[[") {
+            let inner_start = index + 2;
+            if let Some(close) = line[inner_start..].find("]]") {
+                let end = inner_start + close + 2;
+                let inner = &line[inner_start..inner_start + close];
+                if let Some(link) = parse_link(inner, offset + index..offset + end) {
+                    links.push(link);
+                    index = end;
+                    continue;
+                }
+            }
+        }
+        index += 1;
+    }
+}
+
+fn parse_link(inner: &str, range: Range<usize>) -> Option<WikiLink> {
+    if inner.trim().is_empty() || inner.contains(['[', ']', '\n']) {
+        return None;
+    }
+    let (reference, alias) = match inner.split_once('|') {
+        Some((reference, alias)) => (reference, non_empty(alias)),
+        None => (inner, None),
+    };
+    let (target, heading) = match reference.split_once('#') {
+        Some((target, heading)) => (target, non_empty(heading)),
+        None => (reference, None),
+    };
+    Some(WikiLink {
+        target: target.trim().to_string(),
+        heading,
+        alias,
+        range,
+    })
+}
+
+fn non_empty(value: &str) -> Option<String> {
+    let value = value.trim();
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+/// Rewrite wiki-links as Markdown links to `note:` URIs so the preview can
+/// render them and report clicks
+pub fn wiki_links_to_markdown(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut last = 0;
+    for link in wiki_links(text) {
+        out.push_str(&text[last..link.range.start]);
+        let label = link
+            .label()
+            .replace('\\', "\\\\")
+            .replace('[', "\\[")
+            .replace(']', "\\]");
+        out.push_str(&format!("[{}]({})", label, note_uri(&link)));
+        last = link.range.end;
+    }
+    out.push_str(&text[last..]);
+    out
+}
+
+fn note_uri(link: &WikiLink) -> String {
+    let mut uri = format!(
+        "{}{}",
+        NOTE_SCHEME,
+        glib::Uri::escape_string(&link.target, None, true)
+    );
+    if let Some(ref heading) = link.heading {
+        uri.push('#');
+        uri.push_str(&glib::Uri::escape_string(heading, None, true));
+    }
+    uri
+}
+
+/// The target and heading of a `note:` URI from the preview
+pub fn parse_note_uri(uri: &str) -> Option<(String, Option<String>)> {
+    let reference = uri.strip_prefix(NOTE_SCHEME)?;
+    let (target, heading) = match reference.split_once('#') {
+        Some((target, heading)) => (target, Some(heading)),
+        None => (reference, None),
+    };
+    let unescape = |value: &str| {
+        glib::Uri::unescape_string(value, None::<&str>)
+            .map(|value| value.to_string())
+            .unwrap_or_else(|| value.to_string())
+    };
+    Some((unescape(target), heading.map(unescape)))
+}
+
+/// Markdown files in the notes folder and its subfolders, skipping hidden ones
+pub fn list_notes(dir: &Path) -> Vec<PathBuf> {
+    let mut notes = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let path = entry.path();
+            let hidden = entry.file_name().to_string_lossy().starts_with('.');
+            if hidden {
+                continue;
+            }
+            if path.is_dir() {
+                pending.push(path);
+            } else if is_note(&path) {
+                notes.push(path);
+            }
+        }
+    }
+    notes.sort();
+    notes
+}
+
+/// Whether a path has a Markdown file extension
+pub fn is_note(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| NOTE_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+/// A note's title: the front matter title, or else its file name
+pub fn note_title(path: &Path, text: &str) -> String {
+    FrontMatter::parse(text)
+        .and_then(|front_matter| front_matter.title)
+        .unwrap_or_else(|| {
+            path.file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        })
+}
+
+/// Names a link can use for a note: its file name, its path inside the notes
+/// folder and its title, normalized for comparison
+fn note_names(dir: &Path, path: &Path, title: Option<&str>) -> Vec<String> {
+    let mut names = Vec::new();
+    if let Some(stem) = path.file_stem() {
+        names.push(normalize(&stem.to_string_lossy()));
+    }
+    if let Ok(relative) = path.strip_prefix(dir) {
+        names.push(normalize(&relative.with_extension("").to_string_lossy()));
+    }
+    if let Some(title) = title {
+        names.push(normalize(title));
+    }
+    names
+}
+
+fn normalize(name: &str) -> String {
+    let name = name.trim().replace('\\', "/");
+    let name = NOTE_EXTENSIONS
+        .iter()
+        .find_map(|ext| name.strip_suffix(&format!(".{}", ext)))
+        .unwrap_or(&name);
+    name.to_lowercase()
+}
+
+/// Find the note a link target refers to
+pub fn resolve(dir: &Path, target: &str) -> Option<PathBuf> {
+    let wanted = normalize(target);
+    let notes = list_notes(dir);
+
+    // File names are cheap to compare, so only read notes for titles after
+    notes
+        .iter()
+        .find(|path| note_names(dir, path, None).contains(&wanted))
+        .cloned()
+        .or_else(|| {
+            notes.into_iter().find(|path| {
+                fs::read_to_string(path)
+                    .is_ok_and(|text| normalize(&note_title(path, &text)) == wanted)
+            })
+        })
+}
+
+/// Create a note for a link target that doesn't exist yet, titled after it.
+/// Targets with `/` create the note in a subfolder.
+pub fn create_note(dir: &Path, target: &str) -> std::io::Result<PathBuf> {
+    let mut path = dir.to_path_buf();
+    for component in target.split('/') {
+        let component = file_name(component);
+        if !component.is_empty() {
+            path.push(component);
+        }
+    }
+    if path == dir {
+        path.push("Untitled");
+    }
+    let path = path.with_extension("md");
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if !path.exists() {
+        let title = target.rsplit('/').next().unwrap_or(target).trim();
+        let front_matter = FrontMatter {
+            title: Some(title.to_string()),
+            date: Some(Local::now().format("%Y-%m-%d").to_string()),
+            ..Default::default()
+        };
+        fs::write(&path, format!("{}\n", front_matter.to_yaml()))?;
+    }
+    Ok(path)
+}
+
+/// A name turned into something safe to use as a file name, without
+/// characters that other systems reject
+pub fn file_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '-',
+            c => c,
+        })
+        .collect();
+    name.trim().trim_start_matches('.').to_string()
+}
+
+/// A path for a new note in the folder that doesn't overwrite an existing
+/// one, numbering the name if it is taken
+pub fn new_note_path(dir: &Path, name: &str) -> PathBuf {
+    let mut path = dir.join(format!("{}.md", name));
+    let mut number = 2;
+    while path.exists() {
+        path = dir.join(format!("{} {}.md", name, number));
+        number += 1;
+    }
+    path
+}
+
+/// Every link to a note from the other notes in the folder
+pub fn backlinks(dir: &Path, note: &Path, title: &str) -> Vec<Backlink> {
+    let names = note_names(dir, note, Some(title));
+    let mut backlinks = Vec::new();
+
+    for path in list_notes(dir) {
+        if path == note {
+            continue;
+        }
+        let Ok(text) = fs::read_to_string(&path) else {
+            continue;
+        };
+        for link in wiki_links(&text) {
+            if link.target.is_empty() || !names.contains(&normalize(&link.target)) {
+                continue;
+            }
+            backlinks.push(Backlink {
+                title: note_title(&path, &text),
+                path: path.clone(),
+                line: text[..link.range.start].matches('\n').count(),
+                snippet: snippet(&text, &link.range),
+            });
+        }
+    }
+    backlinks
+}
+
+/// The line around a link, shortened to fit a sidebar row
+fn snippet(text: &str, range: &Range<usize>) -> String {
+    let line_start = text[..range.start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = text[range.end..]
+        .find('\n')
+        .map_or(text.len(), |i| range.end + i);
+    let line = text[line_start..line_end].trim();
+
+    if line.chars().count() <= SNIPPET_LENGTH {
+        return line.to_string();
+    }
+
+    // Keep the link in view by starting a little before it
+    let link_column = text[line_start..range.start].trim_start().chars().count();
+    let skip = link_column.saturating_sub(SNIPPET_LENGTH / 3);
+    let shortened: String = line.chars().skip(skip).take(SNIPPET_LENGTH).collect();
+    format!(
+        "{}{}{}",
+        if skip > 0 { "…" } else { "" },
+        shortened.trim(),
+        if skip + SNIPPET_LENGTH < line.chars().count() {
+            "…"
+        } else {
+            ""
+        }
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notes_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("md-wr-notes-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn parses_targets_headings_and_aliases() {
+        let text = "See [[Ideas]], [[Project Plan#Goals|the goals]] and [[#Intro]].";
+        let links = wiki_links(text);
+        assert_eq!(links.len(), 3);
+
+        assert_eq!(links[0].target, "Ideas");
+        assert_eq!(&text[links[0].range.clone()], "[[Ideas]]");
+        assert_eq!(links[0].label(), "Ideas");
+
+        assert_eq!(links[1].target, "Project Plan");
+        assert_eq!(links[1].heading.as_deref(), Some("Goals"));
+        assert_eq!(links[1].alias.as_deref(), Some("the goals"));
+        assert_eq!(links[1].label(), "the goals");
+
+        assert_eq!(links[2].target, "");
+        assert_eq!(links[2].label(), "Intro");
+    }
+
+    #[test]
+    fn skips_links_in_code_and_empty_links() {
+        let text = "`[[inline]]` [[ ]] [[a]b]]\n```\n[[fenced]]\n```\n[[Real]]\n";
+        let targets: Vec<String> = wiki_links(text)
+            .into_iter()
+            .map(|link| link.target)
+            .collect();
+        assert_eq!(targets, ["Real"]);
+    }
+
+    #[test]
+    fn labels_links_to_other_notes_headings() {
+        let link = &wiki_links("[[Plan#Goals]]")[0];
+        assert_eq!(link.label(), "Plan › Goals");
+    }
+
+    #[test]
+    fn round_trips_note_uris() {
+        let markdown = wiki_links_to_markdown("Go to [[My Note#Next Steps]] now");
+        assert_eq!(
+            markdown,
+            "Go to [My Note › Next Steps](note:My%20Note#Next%20Steps) now"
+        );
+        assert_eq!(
+            parse_note_uri("note:My%20Note#Next%20Steps"),
+            Some(("My Note".to_string(), Some("Next Steps".to_string())))
+        );
+        assert_eq!(parse_note_uri("https://example.com"), None);
+    }
+
+    #[test]
+    fn titles_from_front_matter_or_file_name() {
+        let path = Path::new("/notes/meeting-notes.md");
+        assert_eq!(
+            note_title(path, "---\ntitle: Weekly Sync\n---\nBody"),
+            "Weekly Sync"
+        );
+        assert_eq!(note_title(path, "No front matter"), "meeting-notes");
+    }
+
+    #[test]
+    fn resolves_by_file_name_path_and_title() {
+        let dir = notes_dir("resolve");
+        fs::create_dir_all(dir.join("projects")).unwrap();
+        fs::create_dir_all(dir.join(".trash")).unwrap();
+        fs::write(dir.join("Ideas.md"), "ideas").unwrap();
+        fs::write(
+            dir.join("projects/plan.markdown"),
+            "---\ntitle: Big Plan\n---\n",
+        )
+        .unwrap();
+        fs::write(dir.join(".trash/old.md"), "old").unwrap();
+
+        assert_eq!(resolve(&dir, "ideas"), Some(dir.join("Ideas.md")));
+        assert_eq!(resolve(&dir, "Ideas.md"), Some(dir.join("Ideas.md")));
+        let plan = Some(dir.join("projects/plan.markdown"));
+        assert_eq!(resolve(&dir, "projects/plan"), plan);
+        assert_eq!(resolve(&dir, "Big Plan"), plan);
+        assert_eq!(resolve(&dir, "old"), None);
+    }
+
+    #[test]
+    fn creates_missing_notes_with_a_title() {
+        let dir = notes_dir("create");
+        let path = create_note(&dir, "drafts/What? Now").unwrap();
+        assert_eq!(path, dir.join("drafts/What- Now.md"));
+        let text = fs::read_to_string(&path).unwrap();
+        assert_eq!(note_title(&path, &text), "What? Now");
+    }
+
+    #[test]
+    fn finds_backlinks_with_their_line() {
+        let dir = notes_dir("backlinks");
+        let note = dir.join("Target.md");
+        fs::write(
+            &note,
+            "---\ntitle: The Target\n---\n[[Target]] links to itself",
+        )
+        .unwrap();
+        fs::write(dir.join("a.md"), "first line\nsee [[target]] here\n").unwrap();
+        fs::write(
+            dir.join("b.md"),
+            "via title: [[The Target#Part]]\n[[Other]]\n",
+        )
+        .unwrap();
+
+        let backlinks = backlinks(&dir, &note, "The Target");
+        assert_eq!(backlinks.len(), 2);
+        assert_eq!(backlinks[0].path, dir.join("a.md"));
+        assert_eq!(backlinks[0].line, 1);
+        assert_eq!(backlinks[0].snippet, "see [[target]] here");
+        assert_eq!(backlinks[1].path, dir.join("b.md"));
+        assert_eq!(backlinks[1].line, 0);
+    }
+}
@@ -0,0 +1,140 @@
+use adw::prelude::*;
+use gio::Settings;
+use gtk::glib;
+use std::fs;
+use std::path::Path;
+
+use crate::snippets;
+
+/// Show the preferences dialog, with each row bound directly to GSettings
+pub fn show(parent: &impl IsA<gtk::Widget>, settings: &Settings, config_dir: &Path) {
+    let dialog = adw::PreferencesDialog::new();
+    dialog.set_title("Preferences");
+
+    let page = adw::PreferencesPage::new();
+    page.set_title("Editor");
+    page.set_icon_name(Some("accessories-text-editor-symbolic"));
+
+    let editing_group = adw::PreferencesGroup::new();
+    editing_group.set_title("Editing");
+
+    let auto_pair_row = adw::SwitchRow::new();
+    auto_pair_row.set_title("Auto-Close Brackets and Quotes");
+    auto_pair_row.set_subtitle("Also wraps selected text and pairs emphasis markers");
+    settings.bind("auto-pair", &auto_pair_row, "active").build();
+    editing_group.add(&auto_pair_row);
+
+    page.add(&editing_group);
+
+    let notes_group = adw::PreferencesGroup::new();
+    notes_group.set_title("Notes");
+    notes_group.set_description(Some(
+        "Wiki-links are resolved against the notes folder. Leave it empty to use Documents/Notes.",
+    ));
+
+    let notes_folder_row = adw::EntryRow::new();
+    notes_folder_row.set_title("Notes Folder");
+    settings
+        .bind("notes-folder", &notes_folder_row, "text")
+        .build();
+    notes_group.add(&notes_folder_row);
+
+    page.add(&notes_group);
+
+    let snippets_group = adw::PreferencesGroup::new();
+    snippets_group.set_title("Snippets");
+    snippets_group.set_description(Some(
+        "Type a snippet's trigger and press Tab to insert it, or press Ctrl+Shift+J to pick one.",
+    ));
+
+    let edit_snippets_row = adw::ActionRow::new();
+    edit_snippets_row.set_title("Edit Snippets");
+    edit_snippets_row.set_subtitle(&snippets::snippets_path(config_dir).to_string_lossy());
+    edit_snippets_row.add_suffix(&gtk::Image::from_icon_name("go-next-symbolic"));
+    edit_snippets_row.set_activatable(true);
+    let config_dir = config_dir.to_path_buf();
+    edit_snippets_row.connect_activated(glib::clone!(
+        #[weak]
+        dialog,
+        move |_| {
+            show_snippets_editor(&dialog, &config_dir);
+        }
+    ));
+    snippets_group.add(&edit_snippets_row);
+
+    page.add(&snippets_group);
+
+    let session_group = adw::PreferencesGroup::new();
+    session_group.set_title("Startup");
+
+    let restore_session_row = adw::SwitchRow::new();
+    restore_session_row.set_title("Restore Previous Session");
+    restore_session_row
+        .set_subtitle("Reopen the last document with its cursor, selection and window size");
+    settings
+        .bind("restore-session", &restore_session_row, "active")
+        .build();
+    session_group.add(&restore_session_row);
+
+    page.add(&session_group);
+    dialog.add(&page);
+
+    dialog.connect_closed(|_| {
+        gio::Settings::sync();
+    });
+    dialog.present(Some(parent));
+}
+
+/// Edit the snippets file as plain text
+fn show_snippets_editor(parent: &impl IsA<gtk::Widget>, config_dir: &Path) {
+    // Loading writes the default snippets when there is no file yet
+    snippets::load(config_dir);
+    let path = snippets::snippets_path(config_dir);
+    let text = fs::read_to_string(&path).unwrap_or_default();
+
+    let view = gtk::TextView::new();
+    view.set_monospace(true);
+    view.set_left_margin(8);
+    view.set_right_margin(8);
+    view.set_top_margin(8);
+    view.set_bottom_margin(8);
+    view.buffer().set_text(&text);
+
+    let scrolled = gtk::ScrolledWindow::new();
+    scrolled.set_child(Some(&view));
+    scrolled.set_vexpand(true);
+
+    let save_button = gtk::Button::with_label("Save");
+    save_button.add_css_class("suggested-action");
+    let header_bar = adw::HeaderBar::new();
+    header_bar.pack_end(&save_button);
+
+    let toolbar_view = adw::ToolbarView::new();
+    toolbar_view.add_top_bar(&header_bar);
+    toolbar_view.set_content(Some(&scrolled));
+
+    let dialog = adw::Dialog::new();
+    dialog.set_title("Snippets");
+    dialog.set_content_width(640);
+    dialog.set_content_height(560);
+    dialog.set_child(Some(&toolbar_view));
+
+    save_button.connect_clicked(glib::clone!(
+        #[weak]
+        dialog,
+        #[weak]
+        view,
+        move |_| {
+            let buffer = view.buffer();
+            let text = buffer.text(&buffer.start_iter(), &buffer.end_iter(), false);
+            match fs::write(&path, text.as_str()) {
+                Ok(()) => {
+                    println!("Saved snippets to {:?}", path);
+                    dialog.close();
+                }
+                Err(e) => eprintln!("Failed to save snippets to {:?}: {}", path, e),
+            }
+        }
+    ));
+    dialog.present(Some(parent));
+}
@@ -0,0 +1,223 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::frontmatter::{self, FrontMatter};
+use crate::{diagrams, highlight, markdown, math, notes, toc};
+
+pub const DEFAULT_THEME: &str = "github";
+
+// Prefix for themes loaded from the user's themes directory
+const USER_THEME_PREFIX: &str = "user:";
+
+// Styles shared by every theme for content md-wr generates itself
+const BASE_CSS: &str = "
+.math-display { margin: 1em 0; overflow-x: auto; text-align: center; }
+.math-error { color: #c01c28; }
+html.dark .math-error { color: #f66151; }
+.diagram { margin: 1em 0; text-align: center; overflow-x: auto; }
+.diagram svg { max-width: 100%; height: auto; }
+.diagram.mermaid { white-space: pre; }
+.diagram-error { margin: 1em 0; padding: 8px 12px; color: #c01c28; border: 1px solid currentColor; border-radius: 6px; }
+.diagram-error pre { margin: 4px 0 0; white-space: pre-wrap; background: transparent; }
+html.dark .diagram-error { color: #f66151; }
+a[href^='note:'] { text-decoration-style: dashed; }
+";
+
+/// Built-in preview stylesheets: (id, display name, css)
+const BUILTIN_THEMES: &[(&str, &str, &str)] = &[
+    ("github", "GitHub", include_str!("data/preview/github.css")),
+    (
+        "academic",
+        "Academic",
+        include_str!("data/preview/academic.css"),
+    ),
+    (
+        "minimal",
+        "Minimal",
+        include_str!("data/preview/minimal.css"),
+    ),
+];
+
+/// A preview stylesheet the user can pick
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreviewTheme {
+    pub id: String,
+    pub name: String,
+}
+
+/// Directory holding user-supplied `.css` preview themes
+pub fn themes_dir(config_dir: &Path) -> PathBuf {
+    config_dir.join("themes")
+}
+
+/// List built-in themes followed by user themes sorted by name
+pub fn available_themes(config_dir: &Path) -> Vec<PreviewTheme> {
+    let mut themes: Vec<PreviewTheme> = BUILTIN_THEMES
+        .iter()
+        .map(|(id, name, _)| PreviewTheme {
+            id: id.to_string(),
+            name: name.to_string(),
+        })
+        .collect();
+
+    let mut user_themes: Vec<PreviewTheme> = fs::read_dir(themes_dir(config_dir))
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "css"))
+        .filter_map(|path| {
+            let stem = path.file_stem()?.to_str()?.to_string();
+            Some(PreviewTheme {
+                id: format!("{}{}", USER_THEME_PREFIX, stem),
+                name: stem,
+            })
+        })
+        .collect();
+    user_themes.sort_by(|a, b| a.name.cmp(&b.name));
+
+    themes.extend(user_themes);
+    themes
+}
+
+/// Whether a theme id refers to a stylesheet in the user's themes directory
+pub fn is_user_theme(id: &str) -> bool {
+    id.starts_with(USER_THEME_PREFIX)
+}
+
+/// Load the CSS for a theme, falling back to the default theme
+pub fn theme_css(config_dir: &Path, id: &str) -> String {
+    if let Some(name) = id.strip_prefix(USER_THEME_PREFIX) {
+        let path = themes_dir(config_dir).join(format!("{}.css", name));
+        match fs::read_to_string(&path) {
+            Ok(css) => return css,
+            Err(e) => eprintln!("Failed to load preview theme {:?}: {}", path, e),
+        }
+    }
+
+    BUILTIN_THEMES
+        .iter()
+        .find(|(builtin_id, _, _)| *builtin_id == id)
+        .or_else(|| BUILTIN_THEMES.first())
+        .map(|(_, _, css)| css.to_string())
+        .unwrap_or_default()
+}
+
+/// Full stylesheet for a page: the preview theme plus matching code highlighting
+pub fn page_css(config_dir: &Path, theme_id: &str, dark: bool) -> String {
+    format!(
+        "{}\n{}",
+        theme_css(config_dir, theme_id),
+        highlight::theme_css(theme_id, dark)
+    )
+}
+
+/// Render Markdown source to an HTML fragment, with math typeset as MathML,
+/// diagram code blocks replaced by diagrams and other code highlighted.
+/// Front matter is metadata, so it is not rendered; wiki-links become links
+/// to `note:` URIs. Headings get GitHub-style ids for in-page links.
+pub fn render_body(text: &str) -> String {
+    let text = notes::wiki_links_to_markdown(frontmatter::strip(text));
+    let extracted = math::extract(&text);
+    let html = match markdown::parse(&extracted.text) {
+        Some(mut document) => {
+            diagrams::render_diagrams(&mut document);
+            highlight::highlight_code_blocks(&mut document);
+            markdown::to_html(&document)
+        }
+        None => format!("<pre>{}</pre>", markdown::escape_html(&extracted.text)),
+    };
+    toc::add_heading_ids(&math::replace_placeholders(&html, &extracted.spans))
+}
+
+/// Render Markdown source to a complete HTML page styled with the given CSS,
+/// titled from the front matter when it has a title
+pub fn render_page(text: &str, css: &str, dark: bool) -> String {
+    let body = render_body(text);
+    let title = FrontMatter::parse(text)
+        .and_then(|front_matter| front_matter.title)
+        .map(|title| format!("<title>{}</title>\n", markdown::escape_html(&title)))
+        .unwrap_or_default();
+
+    // Only pull in the Mermaid library when the page has a diagram
+    let scripts = if body.contains(diagrams::MERMAID_CLASS) {
+        diagrams::mermaid_script(dark)
+    } else {
+        String::new()
+    };
+
+    format!(
+        "<!DOCTYPE html>\n<html class=\"{}\">\n<head>\n<meta charset=\"utf-8\">\n{}<style>\n{}\n{}\n</style>\n</head>\n<body>\n{}\n{}\n</body>\n</html>\n",
+        if dark { "dark" } else { "light" },
+        title,
+        BASE_CSS,
+        css,
+        body,
+        scripts
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("md-wr-preview-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn lists_builtin_then_user_themes_by_name() {
+        let dir = config_dir("list");
+        let themes = themes_dir(&dir);
+        fs::create_dir_all(&themes).unwrap();
+        fs::write(themes.join("solar.css"), "body {}").unwrap();
+        fs::write(themes.join("Dusk.css"), "body {}").unwrap();
+        fs::write(themes.join("notes.txt"), "not a theme").unwrap();
+
+        let ids: Vec<String> = available_themes(&dir)
+            .into_iter()
+            .map(|theme| theme.id)
+            .collect();
+        assert_eq!(
+            ids,
+            ["github", "academic", "minimal", "user:Dusk", "user:solar"]
+        );
+    }
+
+    #[test]
+    fn lists_builtin_themes_without_a_themes_directory() {
+        let themes = available_themes(&config_dir("builtin"));
+        assert_eq!(themes.len(), BUILTIN_THEMES.len());
+        assert_eq!(themes[0].id, DEFAULT_THEME);
+        assert_eq!(themes[0].name, "GitHub");
+    }
+
+    #[test]
+    fn user_themes_are_prefixed() {
+        assert!(is_user_theme("user:solar"));
+        assert!(!is_user_theme("github"));
+        assert!(!is_user_theme("solar"));
+    }
+
+    #[test]
+    fn loads_user_theme_css() {
+        let dir = config_dir("css");
+        fs::create_dir_all(themes_dir(&dir)).unwrap();
+        fs::write(themes_dir(&dir).join("solar.css"), "body { color: gold; }").unwrap();
+
+        assert_eq!(theme_css(&dir, "user:solar"), "body { color: gold; }");
+    }
+
+    #[test]
+    fn unknown_themes_fall_back_to_the_default() {
+        let dir = config_dir("fallback");
+        let default_css = theme_css(&dir, DEFAULT_THEME);
+        assert_eq!(theme_css(&dir, "user:missing"), default_css);
+        assert_eq!(theme_css(&dir, "no-such-theme"), default_css);
+        assert_eq!(theme_css(&dir, "academic"), BUILTIN_THEMES[1].2);
+    }
+}
@@ -0,0 +1,66 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::markdown;
+
+// Monospace source with a right-aligned line number column that wraps long
+// lines instead of cutting them off at the page edge
+const SOURCE_CSS: &str = "
+body { margin: 0; font-family: monospace; font-size: 10pt; color: #000; background: #fff; }
+h1 { font-family: sans-serif; font-size: 12pt; margin: 0 0 1em; }
+table { border-collapse: collapse; width: 100%; }
+tr { break-inside: avoid; }
+td { padding: 0; vertical-align: top; }
+td.line-number { width: 1%; padding-right: 1em; text-align: right; color: #77767b; white-space: nowrap; user-select: none; }
+td.line pre { margin: 0; font: inherit; white-space: pre-wrap; overflow-wrap: anywhere; }
+";
+
+fn settings_path(config_dir: &Path) -> PathBuf {
+    config_dir.join("print-settings.ini")
+}
+
+fn page_setup_path(config_dir: &Path) -> PathBuf {
+    config_dir.join("page-setup.ini")
+}
+
+/// Print settings from the last print, or the defaults
+pub fn load_settings(config_dir: &Path) -> gtk::PrintSettings {
+    gtk::PrintSettings::from_file(settings_path(config_dir)).unwrap_or_default()
+}
+
+/// Page size, orientation and margins from the last page setup, or the defaults
+pub fn load_page_setup(config_dir: &Path) -> gtk::PageSetup {
+    gtk::PageSetup::from_file(page_setup_path(config_dir)).unwrap_or_default()
+}
+
+/// Remember print settings and page setup for the next print
+pub fn save(config_dir: &Path, settings: &gtk::PrintSettings, page_setup: &gtk::PageSetup) {
+    if let Err(e) = fs::create_dir_all(config_dir) {
+        eprintln!("Failed to create config directory: {}", e);
+        return;
+    }
+    if let Err(e) = settings.to_file(settings_path(config_dir)) {
+        eprintln!("Failed to save print settings: {}", e);
+    }
+    if let Err(e) = page_setup.to_file(page_setup_path(config_dir)) {
+        eprintln!("Failed to save page setup: {}", e);
+    }
+}
+
+/// Markdown source as a page to print, headed by `title` and with every line
+/// numbered
+pub fn source_page(text: &str, title: &str) -> String {
+    let mut rows = String::new();
+    for (index, line) in text.split('\n').enumerate() {
+        rows.push_str(&format!(
+            "<tr><td class=\"line-number\">{}</td><td class=\"line\"><pre>{}</pre></td></tr>\n",
+            index + 1,
+            markdown::escape_html(line)
+        ));
+    }
+    let title = markdown::escape_html(title);
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n{}\n</style>\n</head>\n<body>\n<h1>{}</h1>\n<table>\n{}</table>\n</body>\n</html>\n",
+        title, SOURCE_CSS, title, rows
+    )
+}
@@ -0,0 +1,175 @@
+use chrono::{Local, TimeZone};
+use gio::Settings;
+use gio::prelude::*;
+use std::path::{Path, PathBuf};
+
+// GSettings key holding (path, unix time opened, pinned) entries
+const RECENT_FILES_KEY: &str = "recent-files";
+
+// Unpinned entries kept in the list; pinned favourites are never dropped
+const MAX_RECENT: usize = 20;
+
+/// A recently opened file or note
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecentFile {
+    pub path: PathBuf,
+    /// When the file was last opened, in seconds since the Unix epoch
+    pub opened: i64,
+    pub pinned: bool,
+}
+
+impl RecentFile {
+    /// When the file was last opened, relative to today
+    pub fn opened_label(&self) -> String {
+        let Some(opened) = Local.timestamp_opt(self.opened, 0).single() else {
+            return String::new();
+        };
+        let days = (Local::now().date_naive() - opened.date_naive()).num_days();
+        match days {
+            0 => format!("Today, {}", opened.format("%H:%M")),
+            1 => format!("Yesterday, {}", opened.format("%H:%M")),
+            _ => opened.format("%b %-d, %Y").to_string(),
+        }
+    }
+}
+
+/// Recent files, most recently opened first
+pub fn load(settings: &Settings) -> Vec<RecentFile> {
+    let entries: Vec<(String, i64, bool)> = settings.get(RECENT_FILES_KEY);
+    let mut files: Vec<RecentFile> = entries
+        .into_iter()
+        .map(|(path, opened, pinned)| RecentFile {
+            path: PathBuf::from(path),
+            opened,
+            pinned,
+        })
+        .collect();
+    files.sort_by_key(|file| std::cmp::Reverse(file.opened));
+    files
+}
+
+fn store(settings: &Settings, files: &[RecentFile]) {
+    let entries: Vec<(String, i64, bool)> = files
+        .iter()
+        .map(|file| {
+            (
+                file.path.to_string_lossy().into_owned(),
+                file.opened,
+                file.pinned,
+            )
+        })
+        .collect();
+    if let Err(e) = settings.set(RECENT_FILES_KEY, entries) {
+        eprintln!("Failed to save recent files: {}", e);
+    }
+}
+
+/// Move a file to the top of the list, adding it if needed
+pub fn record_opened(settings: &Settings, path: &Path) {
+    let mut files = load(settings);
+    add_opened(&mut files, path, Local::now().timestamp());
+    store(settings, &files);
+}
+
+/// Put a file first, keeping whether it was pinned, and drop the oldest
+/// unpinned files beyond `MAX_RECENT`
+fn add_opened(files: &mut Vec<RecentFile>, path: &Path, opened: i64) {
+    let pinned = files.iter().any(|file| file.path == path && file.pinned);
+    files.retain(|file| file.path != path);
+    files.insert(
+        0,
+        RecentFile {
+            path: path.to_path_buf(),
+            opened,
+            pinned,
+        },
+    );
+
+    let mut unpinned = 0;
+    files.retain(|file| {
+        if !file.pinned {
+            unpinned += 1;
+        }
+        file.pinned || unpinned <= MAX_RECENT
+    });
+}
+
+pub fn set_pinned(settings: &Settings, path: &Path, pinned: bool) {
+    let mut files = load(settings);
+    for file in files.iter_mut().filter(|file| file.path == path) {
+        file.pinned = pinned;
+    }
+    store(settings, &files);
+}
+
+pub fn remove(settings: &Settings, path: &Path) {
+    let mut files = load(settings);
+    files.retain(|file| file.path != path);
+    store(settings, &files);
+}
+
+/// Drop entries whose files no longer exist and return the rest
+pub fn prune_missing(settings: &Settings) -> Vec<RecentFile> {
+    let mut files = load(settings);
+    let count = files.len();
+    files.retain(|file| file.path.is_file());
+    if files.len() != count {
+        println!(
+            "Removed {} missing files from recent files",
+            count - files.len()
+        );
+        store(settings, &files);
+    }
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str, opened: i64, pinned: bool) -> RecentFile {
+        RecentFile {
+            path: PathBuf::from(path),
+            opened,
+            pinned,
+        }
+    }
+
+    #[test]
+    fn moves_a_reopened_file_to_the_top() {
+        let mut files = vec![file("/a.md", 30, false), file("/b.md", 20, true)];
+        add_opened(&mut files, Path::new("/b.md"), 40);
+        assert_eq!(files, [file("/b.md", 40, true), file("/a.md", 30, false)]);
+
+        add_opened(&mut files, Path::new("/c.md"), 50);
+        assert_eq!(files[0], file("/c.md", 50, false));
+        assert_eq!(files.len(), 3);
+    }
+
+    #[test]
+    fn keeps_only_the_newest_unpinned_files() {
+        let mut files: Vec<RecentFile> = (0..MAX_RECENT as i64)
+            .map(|i| file(&format!("/{}.md", i), 100 - i, false))
+            .collect();
+        files.push(file("/pinned.md", 1, true));
+
+        add_opened(&mut files, Path::new("/new.md"), 200);
+        assert_eq!(files.len(), MAX_RECENT + 1);
+        assert_eq!(files[0].path, Path::new("/new.md"));
+        // The oldest unpinned file is dropped, the older pinned one stays
+        let last_unpinned = format!("/{}.md", MAX_RECENT - 1);
+        assert!(
+            !files
+                .iter()
+                .any(|file| file.path == Path::new(&last_unpinned))
+        );
+        assert!(files.iter().any(|file| file.pinned));
+    }
+
+    #[test]
+    fn labels_files_opened_today() {
+        let now = file("/a.md", Local::now().timestamp(), false);
+        assert!(now.opened_label().starts_with("Today, "));
+        assert_eq!(file("/a.md", i64::MAX, false).opened_label(), "");
+    }
+}
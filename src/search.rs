@@ -0,0 +1,602 @@
+use gtk::glib;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{frontmatter, markdown, notes, tags};
+
+const INDEX_FILE: &str = "search-index.tsv";
+const INDEX_HEADER: &str = "md-wr search index 1";
+
+// BM25 ranking parameters
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+
+// Extra weight for query terms found in a note's title or headings
+const TITLE_BOOST: f64 = 2.0;
+const HEADING_BOOST: f64 = 1.0;
+
+const MAX_RESULTS: usize = 50;
+const SNIPPET_LENGTH: usize = 160;
+
+/// One part of a search query
+#[derive(Debug, Clone, PartialEq)]
+pub enum Clause {
+    Term(String),
+    /// `word*`
+    Prefix(String),
+    /// `"several words"`
+    Phrase(Vec<String>),
+    /// `filename:text` or `file:text`
+    Filename(String),
+    /// `heading:text`
+    Heading(String),
+    /// `tag:name`
+    Tag(String),
+}
+
+/// Parse a query. Every clause must match for a note to be found.
+pub fn parse_query(query: &str) -> Vec<Clause> {
+    let mut clauses = Vec::new();
+    let mut rest = query.trim_start();
+
+    while !rest.is_empty() {
+        let (word, quoted, remaining) = next_word(rest);
+        rest = remaining.trim_start();
+
+        if quoted {
+            clauses.extend(text_clause(&word));
+            continue;
+        }
+
+        let filter = word.split_once(':').and_then(|(name, value)| {
+            let value = value.to_string();
+            match name.to_lowercase().as_str() {
+                "filename" | "file" => Some(Clause::Filename(value.to_lowercase())),
+                "heading" => Some(Clause::Heading(value.to_lowercase())),
+                "tag" => Some(Clause::Tag(value.trim_start_matches('#').to_lowercase())),
+                _ => None,
+            }
+        });
+        match filter {
+            Some(clause) => clauses.push(clause),
+            None => match word.strip_suffix('*') {
+                Some(stem) => clauses.extend(tokenize(stem).pop().map(Clause::Prefix)),
+                None => clauses.extend(text_clause(&word)),
+            },
+        }
+    }
+    clauses
+}
+
+/// The next word of a query, or the contents of a quoted phrase. Returns
+/// the word, whether it was quoted and the rest of the query.
+fn next_word(text: &str) -> (String, bool, &str) {
+    if let Some(quoted) = text.strip_prefix('"') {
+        return match quoted.find('"') {
+            Some(end) => (quoted[..end].to_string(), true, &quoted[end + 1..]),
+            None => (quoted.to_string(), true, ""),
+        };
+    }
+
+    // A filter value may be quoted, as in heading:"two words"
+    if let Some(colon) = text.find(":\"")
+        && !text[..colon].contains(char::is_whitespace)
+    {
+        let value = &text[colon + 2..];
+        let end = value.find('"').unwrap_or(value.len());
+        let rest = value.get(end + 1..).unwrap_or("");
+        return (format!("{}:{}", &text[..colon], &value[..end]), false, rest);
+    }
+    let end = text.find(char::is_whitespace).unwrap_or(text.len());
+    (text[..end].to_string(), false, &text[end..])
+}
+
+/// A single word becomes a term; words that split into several, such as
+/// quoted text or `e-mail`, have to appear together as a phrase
+fn text_clause(text: &str) -> Option<Clause> {
+    let mut tokens = tokenize(text);
+    match tokens.len() {
+        0 => None,
+        1 => tokens.pop().map(Clause::Term),
+        _ => Some(Clause::Phrase(tokens)),
+    }
+}
+
+/// Lowercased words, split with Unicode word segmentation
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.unicode_words()
+        .map(|word| word.to_lowercase())
+        .collect()
+}
+
+/// A note found by a search
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchResult {
+    pub path: PathBuf,
+    pub title: String,
+    /// Zero-based line of the best matching line
+    pub line: usize,
+    /// That line as Pango markup, with matched words in bold
+    pub snippet: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct IndexedNote {
+    modified: u64,
+    title: String,
+    tags: Vec<String>,
+    headings: Vec<String>,
+    length: u32,
+    terms: HashMap<String, u32>,
+}
+
+impl IndexedNote {
+    fn from_text(path: &Path, text: &str, modified: u64) -> Self {
+        let body = frontmatter::strip(text);
+        let headings = markdown::prose_lines(body)
+            .into_iter()
+            .map(|(_, line)| line.trim())
+            .filter(|line| line.starts_with('#'))
+            .map(|line| line.trim_start_matches('#').trim().to_lowercase())
+            .filter(|heading| !heading.is_empty())
+            .collect();
+
+        let title = notes::note_title(path, text);
+        let tokens = tokenize(&format!("{}\n{}", title, body));
+        let mut terms = HashMap::new();
+        for token in &tokens {
+            *terms.entry(token.clone()).or_insert(0) += 1;
+        }
+
+        Self {
+            modified,
+            title,
+            tags: tags::note_tags(text)
+                .iter()
+                .map(|tag| tag.to_lowercase())
+                .collect(),
+            headings,
+            length: tokens.len() as u32,
+            terms,
+        }
+    }
+
+    fn to_line(&self, path: &Path) -> String {
+        let clean = |text: &str| text.replace(['\t', '\n', '\x1f'], " ");
+        let terms: Vec<String> = self
+            .terms
+            .iter()
+            .map(|(term, count)| format!("{}:{}", term, count))
+            .collect();
+        format!(
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            clean(&path.to_string_lossy()),
+            self.modified,
+            self.length,
+            clean(&self.title),
+            self.tags.join(","),
+            self.headings
+                .iter()
+                .map(|heading| clean(heading))
+                .collect::<Vec<_>>()
+                .join("\x1f"),
+            terms.join(" ")
+        )
+    }
+
+    fn from_line(line: &str) -> Option<(PathBuf, Self)> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let [path, modified, length, title, tags, headings, terms] = fields[..] else {
+            return None;
+        };
+        let split = |value: &str, separator: char| -> Vec<String> {
+            value
+                .split(separator)
+                .filter(|item| !item.is_empty())
+                .map(|item| item.to_string())
+                .collect()
+        };
+        Some((
+            PathBuf::from(path),
+            Self {
+                modified: modified.parse().ok()?,
+                length: length.parse().ok()?,
+                title: title.to_string(),
+                tags: split(tags, ','),
+                headings: split(headings, '\x1f'),
+                terms: terms
+                    .split(' ')
+                    .filter_map(|entry| {
+                        let (term, count) = entry.rsplit_once(':')?;
+                        Some((term.to_string(), count.parse().ok()?))
+                    })
+                    .collect(),
+            },
+        ))
+    }
+}
+
+/// Full-text index of the notes folder, kept in the config directory and
+/// brought up to date by comparing file modification times
+#[derive(Debug, Default)]
+pub struct SearchIndex {
+    file: PathBuf,
+    notes: HashMap<PathBuf, IndexedNote>,
+    /// Number of notes containing each term, counted when the notes change
+    document_frequency: HashMap<String, u32>,
+}
+
+impl SearchIndex {
+    pub fn load(config_dir: &Path) -> Self {
+        let file = config_dir.join(INDEX_FILE);
+        let mut notes = HashMap::new();
+        if let Ok(content) = fs::read_to_string(&file) {
+            let mut lines = content.lines();
+            // An index written by another version is rebuilt from scratch
+            if lines.next() == Some(INDEX_HEADER) {
+                notes.extend(lines.filter_map(IndexedNote::from_line));
+            }
+        }
+        let mut index = Self {
+            file,
+            notes,
+            document_frequency: HashMap::new(),
+        };
+        index.count_documents();
+        index
+    }
+
+    fn count_documents(&mut self) {
+        self.document_frequency.clear();
+        for note in self.notes.values() {
+            for term in note.terms.keys() {
+                *self.document_frequency.entry(term.clone()).or_insert(0) += 1;
+            }
+        }
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let mut content = String::from(INDEX_HEADER);
+        content.push('\n');
+        for (path, note) in &self.notes {
+            content.push_str(&note.to_line(path));
+            content.push('\n');
+        }
+        fs::write(&self.file, content)
+    }
+
+    /// Index new and changed notes and forget deleted ones, saving the index
+    /// if anything changed. Returns the number of notes re-indexed.
+    pub fn refresh(&mut self, dir: &Path) -> usize {
+        let paths: HashSet<PathBuf> = notes::list_notes(dir).into_iter().collect();
+        let before = self.notes.len();
+        self.notes.retain(|path, _| paths.contains(path));
+        let removed = before - self.notes.len();
+
+        let mut updated = 0;
+        for path in paths {
+            let modified = modified_time(&path);
+            let current = self
+                .notes
+                .get(&path)
+                .is_some_and(|note| note.modified == modified);
+            if !current && self.update_file(&path, modified) {
+                updated += 1;
+            }
+        }
+
+        if updated > 0 || removed > 0 {
+            self.count_documents();
+            println!(
+                "Search index: {} notes updated, {} removed",
+                updated, removed
+            );
+            if let Err(e) = self.save() {
+                eprintln!("Failed to save search index: {}", e);
+            }
+        }
+        updated
+    }
+
+    fn update_file(&mut self, path: &Path, modified: u64) -> bool {
+        match fs::read_to_string(path) {
+            Ok(text) => {
+                self.notes.insert(
+                    path.to_path_buf(),
+                    IndexedNote::from_text(path, &text, modified),
+                );
+                true
+            }
+            Err(e) => {
+                eprintln!("Failed to index {:?}: {}", path, e);
+                false
+            }
+        }
+    }
+
+    /// Run a query, returning the best matches first
+    pub fn search(&self, query: &str) -> Vec<SearchResult> {
+        let clauses = parse_query(query);
+        if clauses.is_empty() {
+            return Vec::new();
+        }
+
+        let average_length = (self
+            .notes
+            .values()
+            .map(|note| note.length as f64)
+            .sum::<f64>()
+            / self.notes.len().max(1) as f64)
+            .max(1.0);
+
+        let mut results = Vec::new();
+        for (path, note) in &self.notes {
+            // Phrases need the note's text to check word order
+            let mut text: Option<String> = None;
+            let mut matched_terms = Vec::new();
+            let mut matches = true;
+
+            for clause in &clauses {
+                let clause_matches = match clause {
+                    Clause::Term(term) => {
+                        matched_terms.push(term.clone());
+                        note.terms.contains_key(term)
+                    }
+                    Clause::Prefix(prefix) => {
+                        let expanded: Vec<String> = note
+                            .terms
+                            .keys()
+                            .filter(|term| term.starts_with(prefix.as_str()))
+                            .cloned()
+                            .collect();
+                        let found = !expanded.is_empty();
+                        matched_terms.extend(expanded);
+                        found
+                    }
+                    Clause::Phrase(words) => {
+                        matched_terms.extend(words.iter().cloned());
+                        words.iter().all(|word| note.terms.contains_key(word)) && {
+                            let text = text.get_or_insert_with(|| {
+                                fs::read_to_string(path).unwrap_or_default()
+                            });
+                            contains_phrase(&tokenize(frontmatter::strip(text)), words)
+                        }
+                    }
+                    Clause::Filename(name) => path
+                        .file_name()
+                        .is_some_and(|file| file.to_string_lossy().to_lowercase().contains(name)),
+                    Clause::Heading(heading) => note
+                        .headings
+                        .iter()
+                        .any(|candidate| candidate.contains(heading.as_str())),
+                    Clause::Tag(tag) => note.tags.iter().any(|candidate| {
+                        candidate == tag || candidate.starts_with(&format!("{}/", tag))
+                    }),
+                };
+                if !clause_matches {
+                    matches = false;
+                    break;
+                }
+            }
+            if !matches {
+                continue;
+            }
+
+            let score = self.score(note, &matched_terms, average_length);
+            results.push((path, note, score, matched_terms));
+        }
+
+        results.sort_by(|a, b| {
+            b.2.total_cmp(&a.2)
+                .then_with(|| a.1.title.to_lowercase().cmp(&b.1.title.to_lowercase()))
+        });
+        results.truncate(MAX_RESULTS);
+
+        results
+            .into_iter()
+            .map(|(path, note, _, terms)| {
+                let text = fs::read_to_string(path).unwrap_or_default();
+                let (line, snippet) = snippet(&text, &terms);
+                SearchResult {
+                    path: path.clone(),
+                    title: note.title.clone(),
+                    line,
+                    snippet,
+                }
+            })
+            .collect()
+    }
+
+    /// BM25 over the note's words, boosted for terms in the title or headings
+    fn score(&self, note: &IndexedNote, terms: &[String], average_length: f64) -> f64 {
+        let total = self.notes.len() as f64;
+        let title = tokenize(&note.title);
+
+        let mut seen = HashSet::new();
+        terms
+            .iter()
+            .filter(|term| seen.insert(term.as_str()))
+            .map(|term| {
+                let frequency = *note.terms.get(term).unwrap_or(&0) as f64;
+                let containing = *self.document_frequency.get(term).unwrap_or(&0) as f64;
+                let idf = (1.0 + (total - containing + 0.5) / (containing + 0.5)).ln();
+                let length = note.length as f64 / average_length;
+
+                let mut score =
+                    idf * frequency * (K1 + 1.0) / (frequency + K1 * (1.0 - B + B * length));
+                if title.contains(term) {
+                    score += TITLE_BOOST * idf;
+                }
+                if note
+                    .headings
+                    .iter()
+                    .any(|heading| tokenize(heading).contains(term))
+                {
+                    score += HEADING_BOOST * idf;
+                }
+                score
+            })
+            .sum()
+    }
+}
+
+fn modified_time(path: &Path) -> u64 {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |duration| duration.as_millis() as u64)
+}
+
+fn contains_phrase(tokens: &[String], phrase: &[String]) -> bool {
+    tokens.windows(phrase.len()).any(|window| window == phrase)
+}
+
+/// The line matching the most query terms, as Pango markup with the
+/// matched words in bold
+fn snippet(text: &str, terms: &[String]) -> (usize, String) {
+    let body_start = text[..frontmatter::block_len(text)].matches('\n').count();
+    let best = text
+        .lines()
+        .enumerate()
+        .skip(body_start)
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            let words: HashSet<String> = tokenize(line).into_iter().collect();
+            let found = terms.iter().filter(|term| words.contains(*term)).count();
+            (index, line, found)
+        })
+        .fold(
+            None,
+            |best: Option<(usize, &str, usize)>, candidate| match best {
+                Some(best) if best.2 >= candidate.2 => Some(best),
+                _ => Some(candidate),
+            },
+        );
+    let Some((index, line, _)) = best else {
+        return (0, String::new());
+    };
+
+    let line = line.trim();
+    let mut markup = String::new();
+    let mut length = 0;
+    for (_, word) in line.split_word_bound_indices() {
+        if length >= SNIPPET_LENGTH {
+            markup.push('…');
+            break;
+        }
+        length += word.chars().count();
+        let escaped = glib::markup_escape_text(word);
+        if terms.contains(&word.to_lowercase()) {
+            markup.push_str(&format!("<b>{}</b>", escaped));
+        } else {
+            markup.push_str(&escaped);
+        }
+    }
+    (index, markup)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index(notes: &[(&str, &str)]) -> SearchIndex {
+        let mut index = SearchIndex::default();
+        for (name, text) in notes {
+            let path = PathBuf::from(name);
+            let note = IndexedNote::from_text(&path, text, 0);
+            index.notes.insert(path, note);
+        }
+        index.count_documents();
+        index
+    }
+
+    fn terms(words: &[&str]) -> Vec<String> {
+        words.iter().map(|word| word.to_string()).collect()
+    }
+
+    #[test]
+    fn tokenizes_words() {
+        assert_eq!(
+            tokenize("Hello, World! It's e-mail 42."),
+            terms(&["hello", "world", "it's", "e", "mail", "42"])
+        );
+        assert!(tokenize("  -- ").is_empty());
+    }
+
+    #[test]
+    fn parses_clauses() {
+        assert_eq!(
+            parse_query("Rust \"two words\" pro* tag:#Work heading:\"An Intro\" file:Notes"),
+            vec![
+                Clause::Term("rust".to_string()),
+                Clause::Phrase(terms(&["two", "words"])),
+                Clause::Prefix("pro".to_string()),
+                Clause::Tag("work".to_string()),
+                Clause::Heading("an intro".to_string()),
+                Clause::Filename("notes".to_string()),
+            ]
+        );
+        assert_eq!(
+            parse_query("e-mail"),
+            vec![Clause::Phrase(terms(&["e", "mail"]))]
+        );
+        assert!(parse_query("   ").is_empty());
+    }
+
+    #[test]
+    fn counts_documents_per_term() {
+        let index = index(&[("a.md", "apple banana apple"), ("b.md", "apple")]);
+        assert_eq!(index.document_frequency.get("apple"), Some(&2));
+        assert_eq!(index.document_frequency.get("banana"), Some(&1));
+        assert_eq!(index.document_frequency.get("cherry"), None);
+    }
+
+    #[test]
+    fn rare_and_frequent_terms_score_higher() {
+        let index = index(&[
+            ("a.md", "apple apple apple banana"),
+            ("b.md", "apple banana cherry date"),
+            ("c.md", "banana cherry date elder"),
+        ]);
+        let note = |name: &str| &index.notes[&PathBuf::from(name)];
+        let average = 4.0;
+
+        let apple = terms(&["apple"]);
+        assert!(
+            index.score(note("a.md"), &apple, average) > index.score(note("b.md"), &apple, average)
+        );
+        assert_eq!(index.score(note("c.md"), &apple, average), 0.0);
+
+        // banana is in every note, apple only in two
+        let banana = terms(&["banana"]);
+        assert!(
+            index.score(note("b.md"), &apple, average)
+                > index.score(note("b.md"), &banana, average)
+        );
+    }
+
+    #[test]
+    fn titles_and_headings_boost_the_score() {
+        let index = index(&[
+            ("a.md", "# Garden\n\nPlants"),
+            ("b.md", "Intro\n\n## Garden\n\nPlants"),
+            ("c.md", "Intro\n\nThe garden plants"),
+        ]);
+        let note = |name: &str| &index.notes[&PathBuf::from(name)];
+        let garden = terms(&["garden"]);
+        let score = |name: &str| index.score(note(name), &garden, 3.0);
+        assert!(score("a.md") > score("c.md"));
+        assert!(score("b.md") > score("c.md"));
+    }
+
+    #[test]
+    fn finds_phrases() {
+        let tokens = tokenize("the quick brown fox");
+        assert!(contains_phrase(&tokens, &terms(&["quick", "brown"])));
+        assert!(!contains_phrase(&tokens, &terms(&["brown", "quick"])));
+    }
+}
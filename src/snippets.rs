@@ -0,0 +1,435 @@
+use chrono::Local;
+use std::collections::HashMap;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+const DEFAULT_SNIPPETS: &str = include_str!("data/snippets.txt");
+
+/// A snippet expanded by typing its trigger and pressing Tab
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snippet {
+    pub trigger: String,
+    pub description: String,
+    pub body: String,
+}
+
+/// Values for the built-in variables
+#[derive(Debug, Clone, Default)]
+pub struct Variables {
+    pub selection: String,
+    pub clipboard: String,
+}
+
+/// A numbered tab stop. The first range is the one visited, the rest mirror it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TabStop {
+    pub number: u32,
+    pub ranges: Vec<Range<usize>>,
+}
+
+/// An expanded snippet, with tab stops as character ranges of `text` in the
+/// order they are visited: 1, 2, ... and then 0
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expansion {
+    pub text: String,
+    pub stops: Vec<TabStop>,
+}
+
+enum Token {
+    Text(String),
+    Stop(u32, Option<String>),
+}
+
+impl Snippet {
+    pub fn uses_clipboard(&self) -> bool {
+        self.body.contains("$CLIPBOARD") || self.body.contains("${CLIPBOARD}")
+    }
+
+    /// Fill in the variables and lay out the tab stops. Lines after the first
+    /// are indented with `indent`. Without a `$0` the snippet ends after its text.
+    pub fn expand(&self, variables: &Variables, indent: &str) -> Expansion {
+        let body: Vec<String> = self
+            .body
+            .split('\n')
+            .enumerate()
+            .map(|(i, line)| match i {
+                0 => line.to_string(),
+                _ if line.is_empty() => String::new(),
+                _ => format!("{}{}", indent, line),
+            })
+            .collect();
+        let body = body.join("\n");
+        let tokens = tokenize(&body, variables);
+
+        // Mirrors without a default show the default given elsewhere
+        let mut defaults: HashMap<u32, String> = HashMap::new();
+        for token in &tokens {
+            if let Token::Stop(number, Some(default)) = token {
+                defaults.entry(*number).or_insert_with(|| default.clone());
+            }
+        }
+
+        let mut text = String::new();
+        let mut length = 0;
+        let mut stops: Vec<TabStop> = Vec::new();
+        for token in tokens {
+            let (number, value) = match token {
+                Token::Text(value) => {
+                    length += value.chars().count();
+                    text.push_str(&value);
+                    continue;
+                }
+                Token::Stop(number, default) => {
+                    let value = default
+                        .or_else(|| defaults.get(&number).cloned())
+                        .unwrap_or_default();
+                    (number, value)
+                }
+            };
+            let start = length;
+            length += value.chars().count();
+            text.push_str(&value);
+            let range = start..length;
+            match stops.iter_mut().find(|stop| stop.number == number) {
+                Some(stop) => stop.ranges.push(range),
+                None => stops.push(TabStop {
+                    number,
+                    ranges: vec![range],
+                }),
+            }
+        }
+
+        if !stops.iter().any(|stop| stop.number == 0) {
+            let end = length..length;
+            stops.push(TabStop {
+                number: 0,
+                ranges: vec![end],
+            });
+        }
+        stops.sort_by_key(|stop| {
+            if stop.number == 0 {
+                u32::MAX
+            } else {
+                stop.number
+            }
+        });
+        Expansion { text, stops }
+    }
+}
+
+/// The user's snippets file in the config directory
+pub fn snippets_path(config_dir: &Path) -> PathBuf {
+    config_dir.join("snippets.txt")
+}
+
+/// Read the snippets file, creating it with the default snippets first if
+/// there is none yet
+pub fn load(config_dir: &Path) -> Vec<Snippet> {
+    let path = snippets_path(config_dir);
+    match fs::read_to_string(&path) {
+        Ok(text) => parse(&text),
+        Err(_) => {
+            let written =
+                fs::create_dir_all(config_dir).and_then(|_| fs::write(&path, DEFAULT_SNIPPETS));
+            if let Err(e) = written {
+                eprintln!("Failed to write default snippets to {:?}: {}", path, e);
+            }
+            parse(DEFAULT_SNIPPETS)
+        }
+    }
+}
+
+/// Parse snipMate-style definitions: a `snippet TRIGGER description` line
+/// followed by body lines indented with a tab. Lines starting with `#`
+/// outside a body are comments.
+pub fn parse(text: &str) -> Vec<Snippet> {
+    let mut snippets = Vec::new();
+    let mut current: Option<(String, String, Vec<&str>)> = None;
+
+    let mut finish = |current: Option<(String, String, Vec<&str>)>| {
+        if let Some((trigger, description, mut lines)) = current {
+            while lines.last().is_some_and(|line| line.is_empty()) {
+                lines.pop();
+            }
+            snippets.push(Snippet {
+                trigger,
+                description,
+                body: lines.join("\n"),
+            });
+        }
+    };
+
+    for line in text.lines() {
+        if let Some(header) = line.strip_prefix("snippet ") {
+            finish(current.take());
+            let header = header.trim();
+            let (trigger, description) = header
+                .split_once(char::is_whitespace)
+                .unwrap_or((header, ""));
+            current = Some((
+                trigger.to_string(),
+                description.trim().to_string(),
+                Vec::new(),
+            ));
+        } else if let Some(body) = line
+            .strip_prefix('\t')
+            .or_else(|| line.strip_prefix("    "))
+        {
+            if let Some((_, _, lines)) = current.as_mut() {
+                lines.push(body);
+            }
+        } else if line.trim().is_empty() {
+            if let Some((_, _, lines)) = current.as_mut() {
+                lines.push("");
+            }
+        } else if !line.starts_with('#') {
+            eprintln!("Ignoring line in snippets file: {}", line);
+        }
+    }
+    finish(current);
+    snippets
+}
+
+/// Split a body into text and tab stops, filling in the variables.
+/// `\$` and `\}` stand for literal characters.
+fn tokenize(body: &str, variables: &Variables) -> Vec<Token> {
+    let chars: Vec<char> = body.chars().collect();
+    let mut tokens = Vec::new();
+    let mut text = String::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        match chars[i] {
+            '\\' if matches!(chars.get(i + 1), Some('$' | '}' | '\\')) => {
+                text.push(chars[i + 1]);
+                i += 2;
+            }
+            '$' => {
+                let (token, next) = match parse_dollar(&chars, i + 1, variables) {
+                    Some(parsed) => parsed,
+                    None => {
+                        text.push('$');
+                        i += 1;
+                        continue;
+                    }
+                };
+                match token {
+                    Token::Text(value) => text.push_str(&value),
+                    stop => {
+                        if !text.is_empty() {
+                            tokens.push(Token::Text(std::mem::take(&mut text)));
+                        }
+                        tokens.push(stop);
+                    }
+                }
+                i = next;
+            }
+            c => {
+                text.push(c);
+                i += 1;
+            }
+        }
+    }
+    if !text.is_empty() {
+        tokens.push(Token::Text(text));
+    }
+    tokens
+}
+
+/// Parse what follows a `$` at `start`: `N`, `{N}`, `{N:default}`, `NAME` or
+/// `{NAME}`. Returns the token and the index after it.
+fn parse_dollar(chars: &[char], start: usize, variables: &Variables) -> Option<(Token, usize)> {
+    let braced = chars.get(start) == Some(&'{');
+    let name_start = if braced { start + 1 } else { start };
+    let mut i = name_start;
+
+    if chars.get(i).is_some_and(|c| c.is_ascii_digit()) {
+        while chars.get(i).is_some_and(|c| c.is_ascii_digit()) {
+            i += 1;
+        }
+        let number: u32 = chars[name_start..i]
+            .iter()
+            .collect::<String>()
+            .parse()
+            .ok()?;
+        if !braced {
+            return Some((Token::Stop(number, None), i));
+        }
+        match chars.get(i) {
+            Some('}') => return Some((Token::Stop(number, None), i + 1)),
+            Some(':') => {}
+            _ => return None,
+        }
+
+        // Defaults may contain variables but not other tab stops
+        let mut default = String::new();
+        i += 1;
+        loop {
+            match chars.get(i)? {
+                '}' => break,
+                '\\' if matches!(chars.get(i + 1), Some('$' | '}' | '\\')) => {
+                    default.push(chars[i + 1]);
+                    i += 2;
+                }
+                '$' => match parse_dollar(chars, i + 1, variables) {
+                    Some((Token::Text(value), next)) => {
+                        default.push_str(&value);
+                        i = next;
+                    }
+                    _ => {
+                        default.push('$');
+                        i += 1;
+                    }
+                },
+                c => {
+                    default.push(*c);
+                    i += 1;
+                }
+            }
+        }
+        return Some((Token::Stop(number, Some(default)), i + 1));
+    }
+
+    while chars
+        .get(i)
+        .is_some_and(|c| c.is_ascii_uppercase() || *c == '_')
+    {
+        i += 1;
+    }
+    let name: String = chars[name_start..i].iter().collect();
+    if braced {
+        if chars.get(i) != Some(&'}') {
+            return None;
+        }
+        i += 1;
+    }
+    let now = Local::now();
+    let value = match name.as_str() {
+        "DATE" => now.format("%Y-%m-%d").to_string(),
+        "TIME" => now.format("%H:%M").to_string(),
+        "SELECTION" => variables.selection.clone(),
+        "CLIPBOARD" => variables.clipboard.clone(),
+        _ => return None,
+    };
+    Some((Token::Text(value), i))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snippet(body: &str) -> Snippet {
+        Snippet {
+            trigger: "t".to_string(),
+            description: String::new(),
+            body: body.to_string(),
+        }
+    }
+
+    fn expand(body: &str) -> Expansion {
+        snippet(body).expand(&Variables::default(), "")
+    }
+
+    /// A tab stop without mirrors
+    fn stop(number: u32, range: Range<usize>) -> TabStop {
+        TabStop {
+            number,
+            ranges: vec![range],
+        }
+    }
+
+    #[test]
+    fn parses_snippets_file() {
+        let text = "# comment\nsnippet link Markdown link\n\t[$1]($2)\n\nsnippet code\n    ```\n    $0\n    ```\n\n";
+        assert_eq!(
+            parse(text),
+            vec![
+                Snippet {
+                    trigger: "link".to_string(),
+                    description: "Markdown link".to_string(),
+                    body: "[$1]($2)".to_string(),
+                },
+                Snippet {
+                    trigger: "code".to_string(),
+                    description: String::new(),
+                    body: "```\n$0\n```".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn default_snippets_parse() {
+        let snippets = parse(DEFAULT_SNIPPETS);
+        assert!(!snippets.is_empty());
+        assert!(snippets.iter().all(|snippet| !snippet.trigger.is_empty()));
+    }
+
+    #[test]
+    fn orders_tab_stops_with_zero_last() {
+        let expansion = expand("${2:b} $0 ${1:a}");
+        assert_eq!(expansion.text, "b  a");
+        assert_eq!(
+            expansion.stops,
+            vec![stop(1, 3..4), stop(2, 0..1), stop(0, 2..2)]
+        );
+    }
+
+    #[test]
+    fn ends_after_the_text_without_a_final_stop() {
+        let expansion = expand("**$1**");
+        assert_eq!(expansion.text, "****");
+        assert_eq!(expansion.stops, vec![stop(1, 2..2), stop(0, 4..4)]);
+    }
+
+    #[test]
+    fn mirrors_show_the_default() {
+        let expansion = expand("$1 = ${1:name};");
+        assert_eq!(expansion.text, "name = name;");
+        assert_eq!(
+            expansion.stops,
+            vec![
+                TabStop {
+                    number: 1,
+                    ranges: vec![0..4, 7..11],
+                },
+                stop(0, 12..12)
+            ]
+        );
+    }
+
+    #[test]
+    fn ranges_count_characters() {
+        let expansion = expand("é ${1:ü}");
+        assert_eq!(expansion.stops, vec![stop(1, 2..3), stop(0, 3..3)]);
+    }
+
+    #[test]
+    fn escapes_and_unknown_variables_stay_literal() {
+        assert_eq!(expand("\\$1 \\} \\\\ $FOO $").text, "$1 } \\ $FOO $");
+        assert_eq!(expand("${1:a\\}b}").text, "a}b");
+        assert_eq!(expand("${1:unclosed").text, "${1:unclosed");
+    }
+
+    #[test]
+    fn fills_in_variables() {
+        let variables = Variables {
+            selection: "word".to_string(),
+            clipboard: "https://example.com".to_string(),
+        };
+        let snippet = snippet("[${1:$SELECTION}](${CLIPBOARD})");
+        assert!(snippet.uses_clipboard());
+        let expansion = snippet.expand(&variables, "");
+        assert_eq!(expansion.text, "[word](https://example.com)");
+        assert_eq!(expansion.stops[0], stop(1, 1..5));
+        assert!(!self::snippet("$SELECTION").uses_clipboard());
+    }
+
+    #[test]
+    fn indents_lines_after_the_first() {
+        let expansion = snippet("- $1\n\n- $0").expand(&Variables::default(), "  ");
+        assert_eq!(expansion.text, "- \n\n  - ");
+        assert_eq!(expansion.stops, vec![stop(1, 2..2), stop(0, 8..8)]);
+    }
+}
@@ -0,0 +1,223 @@
+use markdown_ppp::ast::{Block, Document, Inline};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{frontmatter, markdown};
+
+// Average adult rates used for the time estimates
+const READING_WORDS_PER_MINUTE: f64 = 238.0;
+const SPEAKING_WORDS_PER_MINUTE: f64 = 150.0;
+
+/// Statistics for a Markdown document, computed on its rendered text
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DocumentStats {
+    pub characters: usize,
+    pub characters_no_spaces: usize,
+    pub words: usize,
+    pub sentences: usize,
+    pub paragraphs: usize,
+    pub headings: usize,
+    pub links: usize,
+    pub images: usize,
+    pub code_blocks: usize,
+    pub syllables: usize,
+}
+
+impl DocumentStats {
+    /// Compute statistics for Markdown source, leaving out any front matter
+    pub fn from_markdown(text: &str, include_code: bool) -> Self {
+        let text = frontmatter::strip(text);
+        let Some(document) = markdown::parse(text) else {
+            return Self::from_plain_text(text);
+        };
+
+        let plain = markdown::plain_text(&document, include_code);
+        let mut stats = Self::from_plain_text(&plain);
+        stats.paragraphs = 0;
+        stats.count_structure(&document);
+        stats
+    }
+
+    /// Compute statistics for text that is already free of Markdown syntax
+    pub fn from_plain_text(text: &str) -> Self {
+        let words: Vec<&str> = text.unicode_words().collect();
+
+        Self {
+            characters: text.chars().filter(|c| *c != '\n').count(),
+            characters_no_spaces: text.chars().filter(|c| !c.is_whitespace()).count(),
+            words: words.len(),
+            sentences: count_sentences(text),
+            paragraphs: text.split("\n\n").filter(|p| !p.trim().is_empty()).count(),
+            syllables: words.iter().map(|w| count_syllables(w)).sum(),
+            ..Default::default()
+        }
+    }
+
+    fn count_structure(&mut self, document: &Document) {
+        for block in &document.blocks {
+            self.visit_block(block);
+        }
+    }
+
+    fn visit_block(&mut self, block: &Block) {
+        match block {
+            Block::Paragraph(inlines) => {
+                self.paragraphs += 1;
+                self.visit_inlines(inlines);
+            }
+            Block::Heading(heading) => {
+                self.headings += 1;
+                self.visit_inlines(&heading.content);
+            }
+            Block::BlockQuote(blocks) => {
+                for block in blocks {
+                    self.visit_block(block);
+                }
+            }
+            Block::List(list) => {
+                for item in &list.items {
+                    for block in &item.blocks {
+                        self.visit_block(block);
+                    }
+                }
+            }
+            Block::CodeBlock(_) => self.code_blocks += 1,
+            Block::Table(table) => {
+                for row in &table.rows {
+                    for cell in row {
+                        self.visit_inlines(cell);
+                    }
+                }
+            }
+            Block::FootnoteDefinition(definition) => {
+                for block in &definition.blocks {
+                    self.visit_block(block);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn visit_inlines(&mut self, inlines: &[Inline]) {
+        for inline in inlines {
+            match inline {
+                Inline::Link(link) => {
+                    self.links += 1;
+                    self.visit_inlines(&link.children);
+                }
+                Inline::Autolink(_) => self.links += 1,
+                Inline::Image(_) => self.images += 1,
+                Inline::Emphasis(children)
+                | Inline::Strong(children)
+                | Inline::Strikethrough(children) => self.visit_inlines(children),
+                _ => {}
+            }
+        }
+    }
+
+    /// Estimated silent reading time in minutes
+    pub fn reading_minutes(&self) -> f64 {
+        self.words as f64 / READING_WORDS_PER_MINUTE
+    }
+
+    /// Estimated time to read the text aloud in minutes
+    pub fn speaking_minutes(&self) -> f64 {
+        self.words as f64 / SPEAKING_WORDS_PER_MINUTE
+    }
+
+    /// Flesch reading ease score (higher is easier, roughly 0-100)
+    pub fn flesch_reading_ease(&self) -> Option<f64> {
+        let (words_per_sentence, syllables_per_word) = self.ratios()?;
+        Some(206.835 - 1.015 * words_per_sentence - 84.6 * syllables_per_word)
+    }
+
+    /// Flesch-Kincaid grade level (approximate US school grade)
+    pub fn flesch_kincaid_grade(&self) -> Option<f64> {
+        let (words_per_sentence, syllables_per_word) = self.ratios()?;
+        Some(0.39 * words_per_sentence + 11.8 * syllables_per_word - 15.59)
+    }
+
+    fn ratios(&self) -> Option<(f64, f64)> {
+        if self.words == 0 || self.sentences == 0 {
+            return None;
+        }
+        let words = self.words as f64;
+        Some((words / self.sentences as f64, self.syllables as f64 / words))
+    }
+}
+
+/// Count words using Unicode word segmentation, so CJK text is counted
+/// per character or word instead of per whitespace-separated run
+pub fn count_words(text: &str) -> usize {
+    text.unicode_words().count()
+}
+
+/// Count the words in the rendered text of Markdown source
+pub fn markdown_word_count(text: &str, include_code: bool) -> usize {
+    let text = frontmatter::strip(text);
+    match markdown::parse(text) {
+        Some(document) => count_words(&markdown::plain_text(&document, include_code)),
+        None => count_words(text),
+    }
+}
+
+/// Format a duration in minutes as a short human readable string
+pub fn format_minutes(minutes: f64) -> String {
+    let seconds = (minutes * 60.0).round() as u64;
+    if seconds < 60 {
+        format!("{} sec", seconds)
+    } else if seconds < 3600 {
+        format!("{} min", (seconds + 30) / 60)
+    } else {
+        format!("{} h {} min", seconds / 3600, (seconds % 3600) / 60)
+    }
+}
+
+fn count_sentences(text: &str) -> usize {
+    let mut sentences = 0;
+    let mut in_sentence = false;
+
+    for c in text.chars() {
+        if matches!(c, '.' | '!' | '?' | '。' | '！' | '？') {
+            if in_sentence {
+                sentences += 1;
+                in_sentence = false;
+            }
+        } else if c.is_alphanumeric() {
+            in_sentence = true;
+        }
+    }
+
+    // Trailing text without terminating punctuation still counts
+    if in_sentence {
+        sentences += 1;
+    }
+    sentences
+}
+
+// Vowel-group heuristic for English; good enough for readability scores
+fn count_syllables(word: &str) -> usize {
+    let word: String = word
+        .chars()
+        .filter(|c| c.is_alphabetic())
+        .flat_map(|c| c.to_lowercase())
+        .collect();
+    if word.is_empty() {
+        return 0;
+    }
+
+    let mut syllables = 0;
+    let mut previous_vowel = false;
+    for c in word.chars() {
+        let is_vowel = matches!(c, 'a' | 'e' | 'i' | 'o' | 'u' | 'y');
+        if is_vowel && !previous_vowel {
+            syllables += 1;
+        }
+        previous_vowel = is_vowel;
+    }
+
+    // Silent trailing "e" as in "make", but not "le" as in "table"
+    if word.ends_with('e') && !word.ends_with("le") && syllables > 1 {
+        syllables -= 1;
+    }
+    syllables.max(1)
+}
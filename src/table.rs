@@ -0,0 +1,471 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Column alignment from the delimiter row of a GFM table
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Alignment {
+    #[default]
+    None,
+    Left,
+    Center,
+    Right,
+}
+
+/// A GFM pipe table. Row 0 is the header; the delimiter row is stored as
+/// `alignments` and is not counted as a row.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Table {
+    pub rows: Vec<Vec<String>>,
+    pub alignments: Vec<Alignment>,
+}
+
+impl Table {
+    /// An empty table with a header row and `body_rows` rows below it
+    pub fn new(columns: usize, body_rows: usize) -> Self {
+        let columns = columns.max(1);
+        let mut rows = vec![
+            (1..=columns)
+                .map(|i| format!("Column {}", i))
+                .collect::<Vec<_>>(),
+        ];
+        rows.extend((0..body_rows).map(|_| vec![String::new(); columns]));
+
+        Self {
+            rows,
+            alignments: vec![Alignment::None; columns],
+        }
+    }
+
+    /// Parse table lines (header, delimiter, body rows)
+    pub fn parse(lines: &[&str]) -> Option<Self> {
+        if lines.len() < 2 || !is_delimiter_row(lines[1]) {
+            return None;
+        }
+
+        let mut alignments: Vec<Alignment> = split_row(lines[1])
+            .iter()
+            .map(|cell| parse_alignment(cell))
+            .collect();
+        let mut rows: Vec<Vec<String>> = std::iter::once(lines[0])
+            .chain(lines[2..].iter().copied())
+            .map(split_row)
+            .collect();
+
+        // Cells typed past the last column add columns rather than being lost
+        let columns = rows
+            .iter()
+            .map(Vec::len)
+            .max()
+            .unwrap_or(0)
+            .max(alignments.len());
+        alignments.resize(columns, Alignment::None);
+        for row in &mut rows {
+            row.resize(columns, String::new());
+        }
+
+        Some(Self { rows, alignments })
+    }
+
+    pub fn column_count(&self) -> usize {
+        self.alignments.len()
+    }
+
+    /// Format the table with every column padded to the same width
+    pub fn format(&self) -> String {
+        let widths: Vec<usize> = (0..self.column_count())
+            .map(|column| {
+                self.rows
+                    .iter()
+                    .map(|row| display_width(&row[column]))
+                    .max()
+                    .unwrap_or(0)
+                    .max(3)
+            })
+            .collect();
+
+        let mut lines = Vec::with_capacity(self.rows.len() + 1);
+        for (index, row) in self.rows.iter().enumerate() {
+            let cells: Vec<String> = row
+                .iter()
+                .zip(&widths)
+                .zip(&self.alignments)
+                .map(|((cell, width), alignment)| pad_cell(cell, *width, *alignment))
+                .collect();
+            lines.push(format!("| {} |", cells.join(" | ")));
+
+            if index == 0 {
+                let delimiters: Vec<String> = widths
+                    .iter()
+                    .zip(&self.alignments)
+                    .map(|(width, alignment)| delimiter_cell(*width, *alignment))
+                    .collect();
+                lines.push(format!("| {} |", delimiters.join(" | ")));
+            }
+        }
+        lines.join("\n")
+    }
+
+    /// Insert an empty body row at `index` (at least 1, below the header)
+    pub fn insert_row(&mut self, index: usize) {
+        let index = index.clamp(1, self.rows.len());
+        self.rows
+            .insert(index, vec![String::new(); self.column_count()]);
+    }
+
+    /// Delete a body row; the header row can't be deleted
+    pub fn delete_row(&mut self, index: usize) {
+        if index > 0 && index < self.rows.len() {
+            self.rows.remove(index);
+        }
+    }
+
+    /// Move a body row up or down by one, returning its new index
+    pub fn move_row(&mut self, index: usize, down: bool) -> usize {
+        let target = if down {
+            index + 1
+        } else {
+            index.wrapping_sub(1)
+        };
+        if index == 0 || target == 0 || target >= self.rows.len() {
+            return index;
+        }
+        self.rows.swap(index, target);
+        target
+    }
+
+    pub fn insert_column(&mut self, index: usize) {
+        let index = index.min(self.column_count());
+        for row in &mut self.rows {
+            row.insert(index, String::new());
+        }
+        self.alignments.insert(index, Alignment::None);
+    }
+
+    /// Delete a column, keeping at least one
+    pub fn delete_column(&mut self, index: usize) {
+        if self.column_count() <= 1 || index >= self.column_count() {
+            return;
+        }
+        for row in &mut self.rows {
+            row.remove(index);
+        }
+        self.alignments.remove(index);
+    }
+
+    /// Move a column left or right by one, returning its new index
+    pub fn move_column(&mut self, index: usize, right: bool) -> usize {
+        let target = if right {
+            index + 1
+        } else {
+            index.wrapping_sub(1)
+        };
+        if index >= self.column_count() || target >= self.column_count() {
+            return index;
+        }
+        for row in &mut self.rows {
+            row.swap(index, target);
+        }
+        self.alignments.swap(index, target);
+        target
+    }
+
+    pub fn set_alignment(&mut self, column: usize, alignment: Alignment) {
+        if let Some(current) = self.alignments.get_mut(column) {
+            *current = alignment;
+        }
+    }
+}
+
+/// Whether a line could be part of a pipe table: it starts or ends with a
+/// pipe, or is a delimiter row. Prose that only contains a pipe is not.
+pub fn is_table_line(line: &str) -> bool {
+    let trimmed = line.trim();
+    has_leading_pipe(trimmed)
+        || (trimmed.ends_with('|') && !trimmed.ends_with("\\|"))
+        || (trimmed.contains('|') && is_delimiter_row(trimmed))
+}
+
+fn has_leading_pipe(line: &str) -> bool {
+    line.trim_start().starts_with('|')
+}
+
+/// Whether a line is a delimiter row such as `| --- | :-: |`
+pub fn is_delimiter_row(line: &str) -> bool {
+    let cells = split_row(line);
+    !cells.is_empty()
+        && cells.iter().all(|cell| {
+            let inner = cell.trim_start_matches(':').trim_end_matches(':');
+            !inner.is_empty() && inner.chars().all(|c| c == '-')
+        })
+}
+
+/// Map a line of the table to a row index; the delimiter line has no row
+pub fn row_for_line(line_index: usize) -> Option<usize> {
+    match line_index {
+        0 => Some(0),
+        1 => None,
+        n => Some(n - 1),
+    }
+}
+
+/// Map a row index back to its line within the formatted table
+pub fn line_for_row(row: usize) -> usize {
+    if row == 0 { 0 } else { row + 1 }
+}
+
+/// The column containing a character offset in a table line
+pub fn column_at(line: &str, char_offset: usize) -> usize {
+    let mut pipes: usize = 0;
+    let mut escaped = false;
+    for (index, c) in line.chars().enumerate() {
+        if index >= char_offset {
+            break;
+        }
+        if c == '|' && !escaped {
+            pipes += 1;
+        }
+        escaped = c == '\\' && !escaped;
+    }
+    // The leading pipe opens the first column
+    if has_leading_pipe(line) {
+        pipes.saturating_sub(1)
+    } else {
+        pipes
+    }
+}
+
+/// Character range of a cell's trimmed content in a table line
+pub fn cell_range(line: &str, column: usize) -> Option<(usize, usize)> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut pipes = Vec::new();
+    let mut escaped = false;
+    for (index, c) in chars.iter().enumerate() {
+        if *c == '|' && !escaped {
+            pipes.push(index);
+        }
+        escaped = *c == '\\' && !escaped;
+    }
+
+    let (start, end) = if has_leading_pipe(line) {
+        (
+            *pipes.get(column)? + 1,
+            pipes.get(column + 1).copied().unwrap_or(chars.len()),
+        )
+    } else if column == 0 {
+        (0, pipes.first().copied().unwrap_or(chars.len()))
+    } else {
+        (
+            *pipes.get(column - 1)? + 1,
+            pipes.get(column).copied().unwrap_or(chars.len()),
+        )
+    };
+    if start > end {
+        return None;
+    }
+
+    // Trim surrounding spaces, leaving the cursor after one space in empty cells
+    let mut content_start = start;
+    while content_start < end && chars[content_start] == ' ' {
+        content_start += 1;
+    }
+    let mut content_end = end;
+    while content_end > content_start && chars[content_end - 1] == ' ' {
+        content_end -= 1;
+    }
+    if content_start == end {
+        let cursor = (start + 1).min(end);
+        return Some((cursor, cursor));
+    }
+    Some((content_start, content_end))
+}
+
+fn split_row(line: &str) -> Vec<String> {
+    let trimmed = line.trim();
+    let trimmed = trimmed.strip_prefix('|').unwrap_or(trimmed);
+    let trimmed = if trimmed.ends_with('|') && !trimmed.ends_with("\\|") {
+        &trimmed[..trimmed.len() - 1]
+    } else {
+        trimmed
+    };
+
+    let mut cells = Vec::new();
+    let mut current = String::new();
+    let mut escaped = false;
+    for c in trimmed.chars() {
+        if c == '|' && !escaped {
+            cells.push(current.trim().to_string());
+            current.clear();
+        } else {
+            current.push(c);
+        }
+        escaped = c == '\\' && !escaped;
+    }
+    cells.push(current.trim().to_string());
+    cells
+}
+
+fn parse_alignment(cell: &str) -> Alignment {
+    match (cell.starts_with(':'), cell.ends_with(':')) {
+        (true, true) => Alignment::Center,
+        (true, false) => Alignment::Left,
+        (false, true) => Alignment::Right,
+        (false, false) => Alignment::None,
+    }
+}
+
+fn display_width(text: &str) -> usize {
+    text.graphemes(true).count()
+}
+
+fn pad_cell(cell: &str, width: usize, alignment: Alignment) -> String {
+    let padding = width.saturating_sub(display_width(cell));
+    match alignment {
+        Alignment::Right => format!("{}{}", " ".repeat(padding), cell),
+        Alignment::Center => format!(
+            "{}{}{}",
+            " ".repeat(padding / 2),
+            cell,
+            " ".repeat(padding - padding / 2)
+        ),
+        Alignment::None | Alignment::Left => format!("{}{}", cell, " ".repeat(padding)),
+    }
+}
+
+fn delimiter_cell(width: usize, alignment: Alignment) -> String {
+    match alignment {
+        Alignment::None => "-".repeat(width),
+        Alignment::Left => format!(":{}", "-".repeat(width - 1)),
+        Alignment::Center => format!(":{}:", "-".repeat(width - 2)),
+        Alignment::Right => format!("{}:", "-".repeat(width - 1)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Option<Table> {
+        let lines: Vec<&str> = text.lines().collect();
+        Table::parse(&lines)
+    }
+
+    #[test]
+    fn parses_header_alignments_and_rows() {
+        let table = parse("| a | b | c |\n|:--|:-:|--:|\n| 1 | 2 | 3 |").unwrap();
+        assert_eq!(
+            table.alignments,
+            vec![Alignment::Left, Alignment::Center, Alignment::Right]
+        );
+        assert_eq!(table.rows, vec![vec!["a", "b", "c"], vec!["1", "2", "3"]]);
+    }
+
+    #[test]
+    fn needs_a_delimiter_row() {
+        assert_eq!(parse("| a | b |\n| 1 | 2 |"), None);
+        assert_eq!(parse("| a | b |"), None);
+    }
+
+    #[test]
+    fn extra_cells_add_columns() {
+        let table = parse("| a | b |\n| --- | --- |\n| 1 | 2 | 3 |").unwrap();
+        assert_eq!(table.column_count(), 3);
+        assert_eq!(table.alignments[2], Alignment::None);
+        assert_eq!(table.rows[0], vec!["a", "b", ""]);
+        assert_eq!(table.rows[1], vec!["1", "2", "3"]);
+    }
+
+    #[test]
+    fn missing_cells_are_empty() {
+        let table = parse("| a | b |\n| --- | --- |\n| 1 |").unwrap();
+        assert_eq!(table.rows[1], vec!["1", ""]);
+    }
+
+    #[test]
+    fn escaped_pipes_stay_in_their_cell() {
+        let table = parse("| a \\| b | c |\n| --- | --- |").unwrap();
+        assert_eq!(table.rows[0], vec!["a \\| b", "c"]);
+    }
+
+    #[test]
+    fn formats_padded_columns() {
+        let table = parse("|a|long header|\n|:-:|--:|\n|wide cell|1|").unwrap();
+        assert_eq!(
+            table.format(),
+            "|     a     | long header |\n\
+             | :-------: | ----------: |\n\
+             | wide cell |           1 |"
+        );
+    }
+
+    #[test]
+    fn formatting_is_stable() {
+        let formatted = Table::new(3, 2).format();
+        assert_eq!(parse(&formatted).unwrap().format(), formatted);
+    }
+
+    #[test]
+    fn formats_to_at_least_three_dashes() {
+        let table = parse("|a|\n|-|").unwrap();
+        assert_eq!(table.format(), "| a   |\n| --- |");
+    }
+
+    #[test]
+    fn table_lines_need_a_table_shape() {
+        assert!(is_table_line("| a | b |"));
+        assert!(is_table_line("a | b |"));
+        assert!(is_table_line("| a | b"));
+        assert!(is_table_line("--- | :-:"));
+        assert!(!is_table_line("either this | or that"));
+        assert!(!is_table_line("---"));
+        assert!(!is_table_line("ends with an escaped pipe \\|"));
+    }
+
+    #[test]
+    fn delimiter_rows() {
+        assert!(is_delimiter_row("| --- | :-: | --: |"));
+        assert!(is_delimiter_row("---|---"));
+        assert!(!is_delimiter_row("| --- | text |"));
+        assert!(!is_delimiter_row("| : |"));
+    }
+
+    #[test]
+    fn rows_and_lines_skip_the_delimiter() {
+        assert_eq!(row_for_line(0), Some(0));
+        assert_eq!(row_for_line(1), None);
+        assert_eq!(row_for_line(3), Some(2));
+        assert_eq!(line_for_row(0), 0);
+        assert_eq!(line_for_row(2), 3);
+    }
+
+    #[test]
+    fn finds_columns_and_cells() {
+        let line = "| ab | cd |";
+        assert_eq!(column_at(line, 2), 0);
+        assert_eq!(column_at(line, 7), 1);
+        assert_eq!(cell_range(line, 0), Some((2, 4)));
+        assert_eq!(cell_range(line, 1), Some((7, 9)));
+        assert_eq!(cell_range("|    | x |", 0), Some((2, 2)));
+        assert_eq!(cell_range(line, 5), None);
+    }
+
+    #[test]
+    fn edits_rows_and_columns() {
+        let mut table = parse("| a | b |\n| --- | :-: |\n| 1 | 2 |\n| 3 | 4 |").unwrap();
+        assert_eq!(table.move_row(1, true), 2);
+        assert_eq!(table.rows[1], vec!["3", "4"]);
+        assert_eq!(table.move_row(1, false), 1);
+        table.insert_row(0);
+        assert_eq!(table.rows[1], vec!["", ""]);
+        table.delete_row(0);
+        assert_eq!(table.rows.len(), 4);
+
+        assert_eq!(table.move_column(0, true), 1);
+        assert_eq!(table.rows[0], vec!["b", "a"]);
+        assert_eq!(table.alignments, vec![Alignment::Center, Alignment::None]);
+        table.insert_column(1);
+        assert_eq!(table.column_count(), 3);
+        table.delete_column(1);
+        table.delete_column(0);
+        table.delete_column(0);
+        assert_eq!(table.column_count(), 1);
+    }
+}
@@ -0,0 +1,347 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::frontmatter::{self, FrontMatter};
+use crate::{markdown, notes};
+
+/// An inline `#tag` in the body of a note
+#[derive(Debug, Clone, PartialEq)]
+pub struct InlineTag {
+    pub name: String,
+    /// Byte range of the name, without the `#`
+    pub range: Range<usize>,
+}
+
+/// A note in the tag index
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexedNote {
+    pub path: PathBuf,
+    pub title: String,
+    /// Lowercased tags of the note
+    pub tags: Vec<String>,
+}
+
+/// Tags from every note in the notes folder. Tags are matched ignoring case
+/// and shown as first spelled.
+#[derive(Debug, Clone, Default)]
+pub struct TagIndex {
+    names: BTreeMap<String, String>,
+    pub notes: Vec<IndexedNote>,
+}
+
+impl TagIndex {
+    pub fn build(dir: &Path) -> Self {
+        let mut index = Self::default();
+        for path in notes::list_notes(dir) {
+            let text = match fs::read_to_string(&path) {
+                Ok(text) => text,
+                Err(e) => {
+                    eprintln!("Failed to index tags in {:?}: {}", path, e);
+                    continue;
+                }
+            };
+            let tags = note_tags(&text);
+            for tag in &tags {
+                index
+                    .names
+                    .entry(tag.to_lowercase())
+                    .or_insert_with(|| tag.clone());
+            }
+            index.notes.push(IndexedNote {
+                title: notes::note_title(&path, &text),
+                tags: tags.iter().map(|tag| tag.to_lowercase()).collect(),
+                path,
+            });
+        }
+        index
+    }
+
+    /// Tags sorted by name, as (key, display name, number of notes)
+    pub fn tags(&self) -> Vec<(String, String, usize)> {
+        self.names
+            .iter()
+            .map(|(key, name)| {
+                let count = self
+                    .notes
+                    .iter()
+                    .filter(|note| note.tags.contains(key))
+                    .count();
+                (key.clone(), name.clone(), count)
+            })
+            .collect()
+    }
+
+    pub fn contains(&self, tag: &str) -> bool {
+        self.names.contains_key(&tag.to_lowercase())
+    }
+
+    /// Notes with every one of the tag keys, or all notes when none are given
+    pub fn notes_with_tags(&self, keys: &[String]) -> Vec<&IndexedNote> {
+        let mut notes: Vec<&IndexedNote> = self
+            .notes
+            .iter()
+            .filter(|note| keys.iter().all(|key| note.tags.contains(key)))
+            .collect();
+        notes.sort_by_key(|note| note.title.to_lowercase());
+        notes
+    }
+}
+
+/// Whether a name can be used as an inline tag
+pub fn is_valid_tag(name: &str) -> bool {
+    name.chars().all(is_tag_char) && name.chars().any(|c| c.is_alphabetic() || c == '_')
+}
+
+fn is_tag_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '-' | '/')
+}
+
+/// Find inline `#tags` in the body of a note, skipping front matter, code
+/// and headings
+pub fn inline_tags(text: &str) -> Vec<InlineTag> {
+    let body_start = frontmatter::block_len(text);
+    let mut tags = Vec::new();
+    for (offset, line) in markdown::prose_lines(&text[body_start..]) {
+        find_tags_in_line(line, body_start + offset, &mut tags);
+    }
+    tags
+}
+
+fn find_tags_in_line(line: &str, offset: usize, tags: &mut Vec<InlineTag>) {
+    let mut in_code = false;
+    let mut previous: Option<char> = None;
+    for (index, c) in line.char_indices() {
+        if c == '`' {
+            in_code = !in_code;
+        } else if c == '#' && !in_code && previous.is_none_or(char::is_whitespace) {
+            // A tag only starts after whitespace, so anchors like (#id) and
+            // [[Note#Heading]] are left alone
+            let start = index + 1;
+            let name: String = line[start..]
+                .chars()
+                .take_while(|c| is_tag_char(*c))
+                .collect();
+            let name = name.trim_end_matches(['/', '-']);
+            if is_valid_tag(name) {
+                tags.push(InlineTag {
+                    name: name.to_string(),
+                    range: offset + start..offset + start + name.len(),
+                });
+            }
+        }
+        previous = Some(c);
+    }
+}
+
+/// Front matter tags followed by inline tags, without duplicates
+pub fn note_tags(text: &str) -> Vec<String> {
+    let mut tags = FrontMatter::parse(text)
+        .map(|front_matter| front_matter.tags)
+        .unwrap_or_default();
+    tags.extend(inline_tags(text).into_iter().map(|tag| tag.name));
+
+    let mut seen = HashSet::new();
+    tags.retain(|tag| seen.insert(tag.to_lowercase()));
+    tags
+}
+
+/// The new name of `tag` when `old` is renamed to `new`, including nested
+/// tags like `old/child`
+fn renamed_tag(tag: &str, old: &str, new: &str) -> Option<String> {
+    let key = tag.to_lowercase();
+    let old_key = old.to_lowercase();
+    if key != old_key && !key.starts_with(&format!("{}/", old_key)) {
+        return None;
+    }
+    let child: String = tag.chars().skip(old.chars().count()).collect();
+    Some(format!("{}{}", new, child))
+}
+
+/// Rename a tag in one note, including nested tags like `#old/child`. If the
+/// note already has the new tag the two are merged. Returns the new text, or
+/// None if the note doesn't use the tag.
+pub fn rename_in_text(text: &str, old: &str, new: &str) -> Option<String> {
+    let body_start = frontmatter::block_len(text);
+    let mut changed = false;
+
+    let mut front = text[..body_start].to_string();
+    if let Some(front_matter) = FrontMatter::parse(text)
+        && front_matter
+            .tags
+            .iter()
+            .any(|tag| renamed_tag(tag, old, new).is_some())
+    {
+        let mut tags: Vec<String> = Vec::new();
+        for tag in front_matter.tags {
+            let tag = renamed_tag(&tag, old, new).unwrap_or(tag);
+            if !tags
+                .iter()
+                .any(|existing| existing.to_lowercase() == tag.to_lowercase())
+            {
+                tags.push(tag);
+            }
+        }
+        // Only the tags entry is rewritten so the rest of the block is kept
+        let renamed =
+            frontmatter::set_entry(text, "tags", Some(&frontmatter::tags_entry(text, &tags)));
+        front = renamed[..frontmatter::block_len(&renamed)].to_string();
+        changed = true;
+    }
+
+    let mut body = String::with_capacity(text.len() - body_start);
+    let mut last = body_start;
+    for tag in inline_tags(text) {
+        let Some(renamed) = renamed_tag(&tag.name, old, new) else {
+            continue;
+        };
+        body.push_str(&text[last..tag.range.start]);
+        body.push_str(&renamed);
+        last = tag.range.end;
+        changed = true;
+    }
+    body.push_str(&text[last..]);
+
+    changed.then(|| format!("{}{}", front, body))
+}
+
+/// Rename or merge a tag in every note of the folder, returning the changed
+/// files. Every note is read before any is written, so a note that can't be
+/// read leaves the folder untouched.
+pub fn rename_tag(dir: &Path, old: &str, new: &str) -> std::io::Result<Vec<PathBuf>> {
+    let mut renamed = Vec::new();
+    for path in notes::list_notes(dir) {
+        let text = fs::read_to_string(&path)?;
+        if let Some(new_text) = rename_in_text(&text, old, new) {
+            renamed.push((path, new_text));
+        }
+    }
+
+    let mut changed = Vec::new();
+    for (path, new_text) in renamed {
+        fs::write(&path, new_text)?;
+        changed.push(path);
+    }
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(text: &str) -> Vec<String> {
+        inline_tags(text).into_iter().map(|tag| tag.name).collect()
+    }
+
+    fn front_matter_tags(text: &str) -> Vec<String> {
+        FrontMatter::parse(text).unwrap().tags
+    }
+
+    fn notes_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("md-wr-tags-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn finds_inline_tags() {
+        let text = "#start of line, mid #idea and #project/alpha-2.\n";
+        assert_eq!(names(text), ["start", "idea", "project/alpha-2"]);
+        let tag = &inline_tags(text)[1];
+        assert_eq!(&text[tag.range.clone()], "idea");
+    }
+
+    #[test]
+    fn skips_headings_code_anchors_and_numbers() {
+        let text = "# Heading\n## Sub\n`#code` [link](#anchor) [[Note#Part]] #123 #2024-01 a#b\n```\n#fenced\n```\n#real";
+        assert_eq!(names(text), ["real"]);
+    }
+
+    #[test]
+    fn skips_front_matter() {
+        let text = "---\ntitle: '#not'\ntags: [one]\n---\n#two\n";
+        assert_eq!(names(text), ["two"]);
+        assert_eq!(note_tags(text), ["one", "two"]);
+    }
+
+    #[test]
+    fn merges_tags_ignoring_case() {
+        let text = "---\ntags: [Rust]\n---\n#rust #GTK #gtk\n";
+        assert_eq!(note_tags(text), ["Rust", "GTK"]);
+    }
+
+    #[test]
+    fn validates_tag_names() {
+        assert!(is_valid_tag("idea"));
+        assert!(is_valid_tag("year/2024"));
+        assert!(is_valid_tag("_draft"));
+        assert!(!is_valid_tag("2024"));
+        assert!(!is_valid_tag("two words"));
+        assert!(!is_valid_tag(""));
+    }
+
+    #[test]
+    fn renames_inline_and_nested_tags() {
+        let text = "#Old and #old/child but not #older or `#old`\n";
+        assert_eq!(
+            rename_in_text(text, "old", "new").as_deref(),
+            Some("#new and #new/child but not #older or `#old`\n")
+        );
+        assert_eq!(rename_in_text(text, "missing", "new"), None);
+    }
+
+    #[test]
+    fn renames_and_merges_front_matter_tags() {
+        let text = "---\ntitle: Note\ntags: [draft, todo]\n---\nBody #draft\n";
+        let renamed = rename_in_text(text, "draft", "todo").unwrap();
+        assert_eq!(front_matter_tags(&renamed), ["todo"]);
+        assert!(renamed.ends_with("---\nBody #todo\n"));
+        assert_eq!(
+            FrontMatter::parse(&renamed).unwrap().title.as_deref(),
+            Some("Note")
+        );
+    }
+
+    #[test]
+    fn indexes_and_renames_across_notes() {
+        let dir = notes_dir("index");
+        fs::write(dir.join("a.md"), "---\ntitle: Zeta\n---\n#Work #idea\n").unwrap();
+        fs::write(dir.join("b.md"), "#work\n").unwrap();
+        fs::write(dir.join("c.md"), "no tags\n").unwrap();
+
+        let index = TagIndex::build(&dir);
+        assert_eq!(
+            index.tags(),
+            [
+                ("idea".to_string(), "idea".to_string(), 1),
+                ("work".to_string(), "Work".to_string(), 2),
+            ]
+        );
+        assert!(index.contains("WORK"));
+        let titles = |keys: &[String]| -> Vec<String> {
+            index
+                .notes_with_tags(keys)
+                .into_iter()
+                .map(|note| note.title.clone())
+                .collect()
+        };
+        assert_eq!(titles(&["work".to_string()]), ["b", "Zeta"]);
+        assert_eq!(titles(&["work".to_string(), "idea".to_string()]), ["Zeta"]);
+        assert_eq!(titles(&[]), ["b", "c", "Zeta"]);
+
+        let changed = rename_tag(&dir, "work", "job").unwrap();
+        assert_eq!(changed, [dir.join("a.md"), dir.join("b.md")]);
+        assert_eq!(fs::read_to_string(dir.join("b.md")).unwrap(), "#job\n");
+    }
+
+    #[test]
+    fn renames_nested_front_matter_tags_keeping_other_keys() {
+        let text = "---\ntitle: Note\n# a comment\ntags:\n  - old/child\n  - keep\n---\nBody\n";
+        let renamed = rename_in_text(text, "old", "new").unwrap();
+        assert_eq!(front_matter_tags(&renamed), ["new/child", "keep"]);
+        assert!(renamed.starts_with("---\ntitle: Note\n# a comment\n"));
+        assert!(renamed.ends_with("---\nBody\n"));
+    }
+}
@@ -0,0 +1,181 @@
+use chrono::Local;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+// Placeholders filled in automatically instead of being asked for
+const BUILTIN_VARIABLES: &[&str] = &["date", "time", "uuid", "title"];
+
+/// Built-in document templates: (name, body)
+const BUILTIN_TEMPLATES: &[(&str, &str)] = &[
+    (
+        "Meeting Notes",
+        include_str!("data/templates/meeting-notes.md"),
+    ),
+    (
+        "Architecture Decision Record",
+        include_str!("data/templates/adr.md"),
+    ),
+    ("Changelog", include_str!("data/templates/changelog.md")),
+    ("README", include_str!("data/templates/readme.md")),
+];
+
+/// A template for new documents, with `{{name}}` placeholders
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    pub name: String,
+    pub body: String,
+}
+
+impl Template {
+    /// Placeholders other than the built-in ones, in order of first use
+    pub fn variables(&self) -> Vec<String> {
+        let mut variables: Vec<String> = Vec::new();
+        for (name, _) in placeholders(&self.body) {
+            if !BUILTIN_VARIABLES.contains(&name) && !variables.iter().any(|v| v == name) {
+                variables.push(name.to_string());
+            }
+        }
+        variables
+    }
+
+    /// Fill in the placeholders. Built-in ones come from the title and the
+    /// current time; unknown ones are left as they are.
+    pub fn expand(&self, title: &str, values: &HashMap<String, String>) -> String {
+        let now = Local::now();
+        let mut out = String::with_capacity(self.body.len());
+        let mut last = 0;
+        for (name, range) in placeholders(&self.body) {
+            let value = match name {
+                "date" => now.format("%Y-%m-%d").to_string(),
+                "time" => now.format("%H:%M").to_string(),
+                "uuid" => Uuid::new_v4().to_string(),
+                "title" => title.to_string(),
+                _ => match values.get(name) {
+                    Some(value) => value.clone(),
+                    None => continue,
+                },
+            };
+            out.push_str(&self.body[last..range.start]);
+            out.push_str(&value);
+            last = range.end;
+        }
+        out.push_str(&self.body[last..]);
+        out
+    }
+}
+
+/// Directory holding the user's `.md` templates
+pub fn templates_dir(config_dir: &Path) -> PathBuf {
+    config_dir.join("templates")
+}
+
+/// Built-in templates followed by the user's templates sorted by name
+pub fn available_templates(config_dir: &Path) -> Vec<Template> {
+    let mut templates: Vec<Template> = BUILTIN_TEMPLATES
+        .iter()
+        .map(|(name, body)| Template {
+            name: name.to_string(),
+            body: body.to_string(),
+        })
+        .collect();
+
+    let mut user_templates: Vec<Template> = fs::read_dir(templates_dir(config_dir))
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "md"))
+        .filter_map(|path| match fs::read_to_string(&path) {
+            Ok(body) => Some(Template {
+                name: path.file_stem()?.to_string_lossy().into_owned(),
+                body,
+            }),
+            Err(e) => {
+                eprintln!("Failed to load template {:?}: {}", path, e);
+                None
+            }
+        })
+        .collect();
+    user_templates.sort_by(|a, b| a.name.cmp(&b.name));
+
+    templates.extend(user_templates);
+    templates
+}
+
+/// `{{name}}` placeholders with their byte ranges, braces included. Spaces
+/// inside the braces are allowed.
+fn placeholders(text: &str) -> Vec<(&str, std::ops::Range<usize>)> {
+    let mut found = Vec::new();
+    let mut offset = 0;
+    while let Some(start) = text[offset..].find("{{").map(|i| offset + i) {
+        let Some(close) = text[start + 2..].find("}}") else {
+            break;
+        };
+        let end = start + 2 + close + 2;
+        let name = text[start + 2..end - 2].trim();
+        let valid = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '_' || c == '-');
+        if valid {
+            found.push((name, start..end));
+            offset = end;
+        } else {
+            offset = start + 2;
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template(body: &str) -> Template {
+        Template {
+            name: "Test".to_string(),
+            body: body.to_string(),
+        }
+    }
+
+    #[test]
+    fn finds_placeholders() {
+        let text = "{{a}} {{ b-c }} {{}} {{two words}} {{d}";
+        let found: Vec<(&str, std::ops::Range<usize>)> = placeholders(text);
+        assert_eq!(found, vec![("a", 0..5), ("b-c", 6..15)]);
+    }
+
+    #[test]
+    fn lists_variables_once_without_builtins() {
+        let template = template("# {{title}}\n{{owner}} on {{date}}\n{{status}} {{ owner }}");
+        assert_eq!(template.variables(), vec!["owner", "status"]);
+        assert!(self::template("No placeholders").variables().is_empty());
+    }
+
+    #[test]
+    fn expands_title_and_values() {
+        let template = template("# {{title}}\nBy {{owner}}, {{unknown}}");
+        let values = HashMap::from([("owner".to_string(), "Sam".to_string())]);
+        assert_eq!(
+            template.expand("Plan", &values),
+            "# Plan\nBy Sam, {{unknown}}"
+        );
+    }
+
+    #[test]
+    fn expands_builtins() {
+        let expanded = template("{{date}} {{time}} {{uuid}}").expand("", &HashMap::new());
+        assert!(!expanded.contains("{{"));
+        assert_eq!(expanded.split(' ').count(), 3);
+    }
+
+    #[test]
+    fn builtin_templates_have_names_and_bodies() {
+        for (name, body) in BUILTIN_TEMPLATES {
+            assert!(!name.is_empty());
+            assert!(!body.trim().is_empty());
+        }
+    }
+}
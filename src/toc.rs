@@ -0,0 +1,290 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::frontmatter;
+use crate::markdown;
+
+/// Comments around a generated table of contents, so it can be found and
+/// replaced when the headings change
+pub const START_MARKER: &str = "<!-- toc -->";
+pub const END_MARKER: &str = "<!-- tocstop -->";
+
+/// An ATX heading with its GitHub-style anchor
+#[derive(Debug, Clone, PartialEq)]
+pub struct Heading {
+    pub level: usize,
+    pub text: String,
+    pub anchor: String,
+}
+
+/// Headings outside front matter and code blocks, with anchors made unique
+/// the way GitHub does: `name`, `name-1`, `name-2`...
+pub fn headings(text: &str) -> Vec<Heading> {
+    let body_start = frontmatter::block_len(text);
+    let mut slugs = Slugs::default();
+    markdown::prose_lines(text)
+        .into_iter()
+        .filter(|(offset, _)| *offset >= body_start)
+        .filter_map(|(_, line)| parse_heading(line))
+        .map(|(level, text)| Heading {
+            level,
+            anchor: slugs.unique(&slug(&text)),
+            text,
+        })
+        .collect()
+}
+
+/// The table of contents for `text` as a nested list of links, between the markers
+pub fn generate(text: &str) -> String {
+    let headings = headings(text);
+    let top = headings.iter().map(|h| h.level).min().unwrap_or(1);
+
+    let mut out = format!("{}\n\n", START_MARKER);
+    // Skipped levels are nested one step at a time so the list stays valid
+    let mut depth = 0;
+    for (index, heading) in headings.iter().enumerate() {
+        let wanted = heading.level - top;
+        depth = if index == 0 { 0 } else { wanted.min(depth + 1) };
+        out.push_str(&format!(
+            "{}- [{}](#{})\n",
+            "  ".repeat(depth),
+            heading.text.replace('[', "\\[").replace(']', "\\]"),
+            heading.anchor
+        ));
+    }
+    if !headings.is_empty() {
+        out.push('\n');
+    }
+    out.push_str(END_MARKER);
+    out
+}
+
+/// Byte range of the table of contents in `text`, markers included
+pub fn find(text: &str) -> Option<Range<usize>> {
+    let start = markdown::prose_lines(text)
+        .into_iter()
+        .find(|(_, line)| line.trim() == START_MARKER)
+        .map(|(offset, _)| offset)?;
+    let end_line = text[start..].find(END_MARKER)? + start;
+    Some(start..end_line + END_MARKER.len())
+}
+
+/// The range of an outdated table of contents and its replacement, or None
+/// when there is none or it is up to date
+pub fn update(text: &str) -> Option<(Range<usize>, String)> {
+    let range = find(text)?;
+    let toc = generate(text);
+    (text[range.clone()] != toc).then_some((range, toc))
+}
+
+/// Add GitHub-style `id` attributes to the headings of rendered HTML that
+/// don't have one, so table of contents links work in the preview
+pub fn add_heading_ids(html: &str) -> String {
+    let mut slugs = Slugs::default();
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find("<h") {
+        let level = rest[start + 2..]
+            .chars()
+            .next()
+            .filter(|c| ('1'..='6').contains(c));
+        let close = level.map(|level| format!("</h{}>", level));
+        let open_end = start + 4;
+        let inner_end = match close {
+            Some(ref close) if rest[start + 3..].starts_with('>') => {
+                rest[open_end..].find(close.as_str()).map(|i| open_end + i)
+            }
+            _ => None,
+        };
+        let Some(inner_end) = inner_end else {
+            out.push_str(&rest[..start + 2]);
+            rest = &rest[start + 2..];
+            continue;
+        };
+
+        let text = unescape_html(&strip_tags(&rest[open_end..inner_end]));
+        out.push_str(&rest[..start + 3]);
+        out.push_str(&format!(" id=\"{}\"", slugs.unique(&slug(&text))));
+        rest = &rest[start + 3..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// GitHub's anchor for a heading: lowercased, punctuation dropped and spaces
+/// turned into hyphens
+pub fn slug(text: &str) -> String {
+    text.trim()
+        .to_lowercase()
+        .chars()
+        .filter(|c| c.is_alphanumeric() || *c == ' ' || *c == '-' || *c == '_')
+        .map(|c| if c == ' ' { '-' } else { c })
+        .collect()
+}
+
+#[derive(Default)]
+struct Slugs {
+    seen: HashMap<String, usize>,
+}
+
+impl Slugs {
+    fn unique(&mut self, slug: &str) -> String {
+        let count = self.seen.entry(slug.to_string()).or_insert(0);
+        let unique = match *count {
+            0 => slug.to_string(),
+            n => format!("{}-{}", slug, n),
+        };
+        *count += 1;
+        unique
+    }
+}
+
+/// Level and plain text of an ATX heading line
+fn parse_heading(line: &str) -> Option<(usize, String)> {
+    let line = line.trim_end();
+    let trimmed = line.trim_start_matches(' ');
+    if line.len() - trimmed.len() > 3 {
+        return None;
+    }
+    let level = trimmed.chars().take_while(|&c| c == '#').count();
+    let rest = &trimmed[level..];
+    if !(1..=6).contains(&level) || !(rest.is_empty() || rest.starts_with([' ', '\t'])) {
+        return None;
+    }
+
+    // A closing run of #s is not part of the heading
+    let content = rest.trim();
+    let content = match content.trim_end_matches('#') {
+        stripped if stripped.is_empty() || stripped.ends_with([' ', '\t']) => stripped.trim_end(),
+        _ => content,
+    };
+    let text = plain_inline_text(content);
+    (!text.is_empty()).then_some((level, text))
+}
+
+/// Heading text as it reads once rendered: links and images become their
+/// text and emphasis and code markers are dropped
+fn plain_inline_text(text: &str) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(open) = rest.find('[') {
+        let link = rest[open + 1..].find("](").and_then(|close| {
+            let target = open + 1 + close + 2;
+            rest[target..]
+                .find(')')
+                .map(|end| (open + 1 + close, target + end))
+        });
+        let Some((label_end, link_end)) = link else {
+            out.push_str(&rest[..=open]);
+            rest = &rest[open + 1..];
+            continue;
+        };
+        let prefix = rest[..open].strip_suffix('!').unwrap_or(&rest[..open]);
+        out.push_str(prefix);
+        out.push_str(&rest[open + 1..label_end]);
+        rest = &rest[link_end + 1..];
+    }
+    out.push_str(rest);
+    out.replace(['*', '`'], "")
+        .replace("~~", "")
+        .trim()
+        .to_string()
+}
+
+fn strip_tags(html: &str) -> String {
+    let mut out = String::new();
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            c if !in_tag => out.push(c),
+            _ => {}
+        }
+    }
+    out
+}
+
+fn unescape_html(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slugs_like_github() {
+        assert_eq!(slug("Hello, World!"), "hello-world");
+        assert_eq!(slug("  snake_case and-dash "), "snake_case-and-dash");
+        assert_eq!(slug("Café 2.0"), "café-20");
+    }
+
+    #[test]
+    fn makes_anchors_unique() {
+        let anchors: Vec<String> = headings("# Intro\n## Intro\n## Intro\n")
+            .into_iter()
+            .map(|heading| heading.anchor)
+            .collect();
+        assert_eq!(anchors, vec!["intro", "intro-1", "intro-2"]);
+    }
+
+    #[test]
+    fn parses_atx_headings_only() {
+        let text = "---\ntitle: x\n---\n# One #\n#NoSpace\n    # Indented code\n```\n# In code\n```\n### [Link](url) and `code` ##\n####### Seven\n";
+        let headings = headings(text);
+        let found: Vec<(usize, &str)> = headings
+            .iter()
+            .map(|heading| (heading.level, heading.text.as_str()))
+            .collect();
+        assert_eq!(found, vec![(1, "One"), (3, "Link and code")]);
+    }
+
+    #[test]
+    fn generates_nested_list() {
+        let text = "## A\n#### Deep\n## B [x]\n";
+        assert_eq!(
+            generate(text),
+            "<!-- toc -->\n\n- [A](#a)\n  - [Deep](#deep)\n- [B \\[x\\]](#b-x)\n\n<!-- tocstop -->"
+        );
+        assert_eq!(generate("No headings"), "<!-- toc -->\n\n<!-- tocstop -->");
+    }
+
+    #[test]
+    fn updates_only_when_outdated() {
+        let stale = "# Title\n\n<!-- toc -->\n\n- [Old](#old)\n\n<!-- tocstop -->\n\n## New\n";
+        let (range, toc) = update(stale).unwrap();
+        assert_eq!(
+            &stale[range.clone()],
+            "<!-- toc -->\n\n- [Old](#old)\n\n<!-- tocstop -->"
+        );
+        assert_eq!(
+            toc,
+            "<!-- toc -->\n\n- [Title](#title)\n  - [New](#new)\n\n<!-- tocstop -->"
+        );
+
+        let mut current = stale.to_string();
+        current.replace_range(range, &toc);
+        assert_eq!(update(&current), None);
+        assert_eq!(update("# No toc\n"), None);
+    }
+
+    #[test]
+    fn ignores_markers_in_code() {
+        assert_eq!(find("```\n<!-- toc -->\n<!-- tocstop -->\n```\n"), None);
+        assert_eq!(find("<!-- toc -->\nunclosed\n"), None);
+    }
+
+    #[test]
+    fn adds_ids_to_rendered_headings() {
+        let html = "<h1>Intro</h1><h2 id=\"kept\">Kept</h2><h2><em>Intro</em> &amp; more</h2><hr>";
+        assert_eq!(
+            add_heading_ids(html),
+            "<h1 id=\"intro\">Intro</h1><h2 id=\"kept\">Kept</h2><h2 id=\"intro--more\"><em>Intro</em> &amp; more</h2><hr>"
+        );
+    }
+}